@@ -1,6 +1,12 @@
 use std::io::{self, BufRead, Write};
 
-use lang::{lexer, parser::Parser};
+use lang::{
+    SyntaxError,
+    interpreter::Interpreter,
+    lexer,
+    parser::Parser,
+    resolver::{self, Warning},
+};
 
 fn main() {
     println!("Lang REPL - Enter expressions (Ctrl+D to exit)");
@@ -8,6 +14,7 @@ fn main() {
 
     let stdin = io::stdin();
     let mut stdout = io::stdout();
+    let mut interpreter = Interpreter::new(io::stdout());
 
     loop {
         print!("> ");
@@ -36,15 +43,67 @@ fn main() {
             break;
         }
 
-        match lexer::scan(line) {
-            Ok(tokens) => {
-                let mut parser = Parser::new(tokens);
-                match parser.parse() {
-                    Ok(expr) => println!("{:#?}", expr),
-                    Err(e) => eprintln!("Parse error: {}", e),
+        let tokens = match lexer::scan(line) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                eprintln!("Lexer error: {}", e);
+                continue;
+            }
+        };
+
+        let mut parser = Parser::new(tokens);
+
+        // Lines ending in ';' or '}' are run as statements, anything else is
+        // evaluated as a single expression and its value echoed back.
+        if line.ends_with(';') || line.ends_with('}') {
+            let program = match parser.parse_program() {
+                Ok(program) => program,
+                Err(e) => {
+                    eprintln!("Parse error: {}", e);
+                    continue;
                 }
+            };
+
+            if !report_resolution(resolver::resolve(&program)) {
+                continue;
+            }
+
+            if let Err(e) = interpreter.run(&program) {
+                eprintln!("Runtime error: {}", e);
             }
-            Err(e) => eprintln!("Lexer error: {}", e),
+        } else {
+            let expr = match parser.parse() {
+                Ok(expr) => expr,
+                Err(e) => {
+                    eprintln!("Parse error: {}", e);
+                    continue;
+                }
+            };
+
+            if !report_resolution(resolver::resolve_expr(&expr)) {
+                continue;
+            }
+
+            match interpreter.evaluate(&expr) {
+                Ok(value) => println!("{}", value),
+                Err(e) => eprintln!("Runtime error: {}", e),
+            }
+        }
+    }
+}
+
+/// Prints resolver diagnostics, returning whether the input can be run.
+fn report_resolution(result: Result<Vec<Warning>, SyntaxError>) -> bool {
+    match result {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("Resolve error: {}", e);
+            false
         }
     }
 }
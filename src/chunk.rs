@@ -0,0 +1,221 @@
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Operand: u16 constant index.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+
+    /// Operand: u8 stack slot relative to the frame.
+    GetLocal,
+    SetLocal,
+    /// Operand: u16 constant index of the variable name.
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    /// Operand: u8 index into the closure's upvalues.
+    GetUpvalue,
+    SetUpvalue,
+
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    /// Operand: u8, 0 for `and` and 1 for `or`. Fails unless the top of the
+    /// stack is a bool.
+    CheckBool,
+    /// Operand: u8 value count. Replaces that many values with a string
+    /// joining them as `print` would show them.
+    Interpolate,
+
+    Print,
+    /// Operand: u16 forward offset.
+    Jump,
+    /// Operand: u16 forward offset. Leaves the condition on the stack.
+    JumpIfFalse,
+    /// Operand: u16 backward offset.
+    Loop,
+    /// Operand: u8 argument count.
+    Call,
+    /// Operand: u16 constant index of a function, followed by an
+    /// `(is_local, index)` byte pair per upvalue.
+    Closure,
+    CloseUpvalue,
+    Return,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 34] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::CheckBool,
+        OpCode::Interpolate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OpCode::ALL.get(byte as usize).copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(i64),
+    Float(f64),
+    String(Rc<str>),
+    Function(Rc<FunctionProto>),
+}
+
+/// A compiled function body. The top-level script is a nameless function
+/// taking no arguments.
+#[derive(Debug, PartialEq)]
+pub struct FunctionProto {
+    pub name: Option<String>,
+    pub arity: u8,
+    pub upvalue_count: u8,
+    pub chunk: Chunk,
+}
+
+/// Source position of every instruction from `offset` up to the next entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineStart {
+    pub offset: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    pub lines: Vec<LineStart>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Chunk::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: u32, column: u32) {
+        let offset = self.code.len();
+        self.code.push(byte);
+
+        match self.lines.last() {
+            Some(last) if last.line == line && last.column == column => {}
+            _ => self.lines.push(LineStart {
+                offset,
+                line,
+                column,
+            }),
+        }
+    }
+
+    /// Returns the index of `constant`, reusing an existing entry for equal
+    /// numbers and strings.
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        if !matches!(constant, Constant::Function(_))
+            && let Some(index) = self.constants.iter().position(|c| *c == constant)
+        {
+            return index;
+        }
+
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    /// Source line and column of the instruction at `offset`.
+    pub fn position(&self, offset: usize) -> (u32, u32) {
+        let index = self
+            .lines
+            .partition_point(|start| start.offset <= offset)
+            .saturating_sub(1);
+
+        match self.lines.get(index) {
+            Some(start) => (start.line, start.column),
+            None => (0, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for (byte, op) in OpCode::ALL.iter().enumerate() {
+            assert_eq!(*op as u8, byte as u8);
+            assert_eq!(OpCode::from_byte(byte as u8), Some(*op));
+        }
+        assert_eq!(OpCode::from_byte(OpCode::ALL.len() as u8), None);
+    }
+
+    #[test]
+    fn test_line_table_is_run_length_encoded() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::Nil as u8, 1, 1);
+        chunk.write(OpCode::Pop as u8, 1, 1);
+        chunk.write(OpCode::True as u8, 2, 5);
+        chunk.write(OpCode::Pop as u8, 2, 5);
+
+        assert_eq!(chunk.lines.len(), 2);
+        assert_eq!(chunk.position(0), (1, 1));
+        assert_eq!(chunk.position(1), (1, 1));
+        assert_eq!(chunk.position(2), (2, 5));
+        assert_eq!(chunk.position(3), (2, 5));
+    }
+
+    #[test]
+    fn test_constants_are_deduplicated() {
+        let mut chunk = Chunk::new();
+        let a = chunk.add_constant(Constant::String("x".into()));
+        let b = chunk.add_constant(Constant::Number(1));
+        let c = chunk.add_constant(Constant::String("x".into()));
+        assert_eq!((a, b, c), (0, 1, 0));
+    }
+}
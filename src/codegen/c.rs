@@ -0,0 +1,549 @@
+//! Translates a resolved program into a C file that includes
+//! [`RUNTIME_HEADER`] (saved as [`RUNTIME_HEADER_NAME`]) and builds with any
+//! C11 compiler.
+//!
+//! Values stay dynamically typed as tagged unions, so every program the
+//! interpreter runs compiles, with the same output and runtime errors. Each
+//! subexpression is evaluated into its own temporary to keep the
+//! interpreter's left-to-right evaluation order, which C doesn't guarantee
+//! for function arguments. Locals live in heap cells that closures capture
+//! by reference, and a `for` loop's `continue` jumps to a label in front of
+//! the increment.
+
+use std::{collections::HashMap, fmt::Write};
+
+use super::CodegenError;
+use crate::{BinaryOp, Expr, Function, InterpolationPart, Program, Stmt, Token, UnaryOp};
+
+pub const RUNTIME_HEADER_NAME: &str = "lang_runtime.h";
+pub const RUNTIME_HEADER: &str = include_str!("c_runtime.h");
+
+/// The C function currently being written.
+struct FunctionState {
+    body: String,
+    indent: usize,
+    // Each maps a variable name to the C variable holding its cell.
+    scopes: Vec<HashMap<String, String>>,
+    // Cells captured from enclosing functions, as expressions evaluated
+    // where the closure is created.
+    captures: Vec<String>,
+    // Label in front of the increment for `for` loops, and whether any
+    // `continue` jumps to it; `while` loops use a plain `continue`.
+    loops: Vec<Option<(String, bool)>>,
+}
+
+impl FunctionState {
+    fn new() -> Self {
+        FunctionState {
+            body: String::new(),
+            indent: 1,
+            scopes: Vec::new(),
+            captures: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+}
+
+struct Generator {
+    // The function being written is last; `main` is first.
+    functions: Vec<FunctionState>,
+    definitions: Vec<String>,
+    globals: Vec<String>,
+    names: usize,
+}
+
+/// Emits the C file for `program`.
+pub fn emit(program: &Program) -> Result<String, CodegenError> {
+    let mut generator = Generator {
+        functions: vec![FunctionState::new()],
+        definitions: Vec::new(),
+        globals: Vec::new(),
+        names: 0,
+    };
+
+    for statement in &program.statements {
+        generator.statement(statement)?;
+    }
+    generator.line("return 0;".to_string());
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* Generated by lang */\n#include \"{RUNTIME_HEADER_NAME}\"\n"
+    )
+    .unwrap();
+    if !generator.globals.is_empty() {
+        out.push_str("static LangGlobal globals[] = {\n");
+        for name in &generator.globals {
+            writeln!(out, "    {{\"{name}\"}},").unwrap();
+        }
+        out.push_str("};\n\n");
+    }
+    for definition in &generator.definitions {
+        out.push_str(definition);
+        out.push('\n');
+    }
+    write!(
+        out,
+        "int main(void) {{\n{}}}\n",
+        generator.functions[0].body
+    )
+    .unwrap();
+    Ok(out)
+}
+
+impl Generator {
+    fn state(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn line(&mut self, line: String) {
+        let state = self.state();
+        writeln!(state.body, "{:1$}{line}", "", state.indent * 4).unwrap();
+    }
+
+    fn open(&mut self, line: String) {
+        self.line(line);
+        self.state().indent += 1;
+    }
+
+    fn close(&mut self, line: &str) {
+        self.state().indent -= 1;
+        self.line(line.to_string());
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{prefix}{}", self.names)
+    }
+
+    /// Evaluates `value` into a new temporary and returns its name.
+    fn temp(&mut self, value: String) -> String {
+        let temp = self.fresh("t");
+        self.line(format!("LangValue {temp} = {value};"));
+        temp
+    }
+
+    fn global(&mut self, name: &str) -> String {
+        let index = match self.globals.iter().position(|global| global == name) {
+            Some(index) => index,
+            None => {
+                self.globals.push(name.to_string());
+                self.globals.len() - 1
+            }
+        };
+        format!("&globals[{index}]")
+    }
+
+    fn is_global_scope(&self) -> bool {
+        self.functions.len() == 1 && self.functions[0].scopes.is_empty()
+    }
+
+    /// Declares a local `name` holding `value` in the innermost scope.
+    fn declare_local(&mut self, name: &Token, value: &str) -> String {
+        let cell = self.fresh(&format!("v_{}_", name.lexeme));
+        self.line(format!("LangValue *{cell} = lang_cell({value});"));
+        self.state()
+            .scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.lexeme.clone(), cell.clone());
+        cell
+    }
+
+    /// Returns an expression for the cell of the local `name` as seen from
+    /// `self.functions[level]`, capturing it from enclosing functions as
+    /// needed.
+    fn cell(&mut self, name: &str, level: usize) -> String {
+        let state = &self.functions[level];
+        if let Some(cell) = state.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return cell.clone();
+        }
+
+        assert!(level > 0, "the resolver found no local '{name}'");
+        let outer = self.cell(name, level - 1);
+        let captures = &mut self.functions[level].captures;
+        let index = match captures.iter().position(|capture| *capture == outer) {
+            Some(index) => index,
+            None => {
+                captures.push(outer);
+                captures.len() - 1
+            }
+        };
+        format!("self->cells[{index}]")
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), CodegenError> {
+        match statement {
+            Stmt::Expression(expr) => {
+                let value = self.expression(expr)?;
+                self.line(format!("(void){value};"));
+            }
+            Stmt::Print { value, .. } => {
+                let value = self.expression(value)?;
+                self.line(format!("lang_print({value});"));
+            }
+            Stmt::Let {
+                name, initializer, ..
+            } => {
+                let value = self.expression(initializer)?;
+                if self.is_global_scope() {
+                    let global = self.global(&name.lexeme);
+                    self.line(format!("lang_define_global({global}, {value});"));
+                } else {
+                    self.declare_local(name, &value);
+                }
+            }
+            Stmt::Block(statements) => {
+                self.open("{".to_string());
+                self.state().scopes.push(HashMap::new());
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.state().scopes.pop();
+                self.close("}");
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.expression(condition)?;
+                self.open(format!("if (lang_truthy({condition})) {{"));
+                self.statement(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.close("} else {");
+                    self.state().indent += 1;
+                    self.statement(else_branch)?;
+                }
+                self.close("}");
+            }
+            Stmt::While { condition, body } => {
+                self.open("for (;;) {".to_string());
+                let condition = self.expression(condition)?;
+                self.line(format!("if (!lang_truthy({condition})) break;"));
+                self.state().loops.push(None);
+                self.statement(body)?;
+                self.state().loops.pop();
+                self.close("}");
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.open("{".to_string());
+                self.state().scopes.push(HashMap::new());
+                if let Some(initializer) = initializer {
+                    self.statement(initializer)?;
+                }
+
+                self.open("for (;;) {".to_string());
+                if let Some(condition) = condition {
+                    let condition = self.expression(condition)?;
+                    self.line(format!("if (!lang_truthy({condition})) break;"));
+                }
+                let label = self.fresh("continue_");
+                self.state().loops.push(Some((label.clone(), false)));
+                self.statement(body)?;
+                if let Some(Some((_, true))) = self.state().loops.pop() {
+                    self.line(format!("{label}:;"));
+                }
+                if let Some(increment) = increment {
+                    let value = self.expression(increment)?;
+                    self.line(format!("(void){value};"));
+                }
+                self.close("}");
+
+                self.state().scopes.pop();
+                self.close("}");
+            }
+            Stmt::Break { .. } => self.line("break;".to_string()),
+            Stmt::Continue { .. } => match self.state().loops.last_mut().unwrap() {
+                Some((label, used)) => {
+                    *used = true;
+                    let label = label.clone();
+                    self.line(format!("goto {label};"));
+                }
+                None => self.line("continue;".to_string()),
+            },
+            Stmt::Function(function) => {
+                let name = function.name.as_ref().unwrap();
+                if self.is_global_scope() {
+                    let closure = self.function(function)?;
+                    let global = self.global(&name.lexeme);
+                    self.line(format!("lang_define_global({global}, {closure});"));
+                } else {
+                    // Declared first so the function can call itself.
+                    let cell = self.declare_local(name, "lang_nil()");
+                    let closure = self.function(function)?;
+                    self.line(format!("*{cell} = {closure};"));
+                }
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.expression(value)?,
+                    None => "lang_nil()".to_string(),
+                };
+                self.line(format!("return {value};"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes out `function` as a C function and returns an expression that
+    /// creates a closure over it.
+    fn function(&mut self, function: &Function) -> Result<String, CodegenError> {
+        let symbol = self.fresh("fn_");
+        self.functions.push(FunctionState::new());
+        self.state().scopes.push(HashMap::new());
+        for (index, param) in function.params.iter().enumerate() {
+            self.declare_local(param, &format!("args[{index}]"));
+        }
+        for statement in &function.body {
+            self.statement(statement)?;
+        }
+        self.line("return lang_nil();".to_string());
+
+        let state = self.functions.pop().unwrap();
+        self.definitions.push(format!(
+            "static LangValue {symbol}(LangClosure *self, LangValue *args) {{\n{}}}\n",
+            state.body
+        ));
+
+        let name = match &function.name {
+            Some(name) => format!("\"{}\"", name.lexeme),
+            None => "NULL".to_string(),
+        };
+        let cells = match state.captures.len() {
+            0 => "NULL".to_string(),
+            _ => format!("(LangValue *[]){{{}}}", state.captures.join(", ")),
+        };
+        Ok(format!(
+            "lang_closure({name}, {}, {symbol}, {}, {cells})",
+            function.params.len(),
+            state.captures.len()
+        ))
+    }
+
+    /// Emits the code for `expr` and returns the temporary holding its value.
+    fn expression(&mut self, expr: &Expr) -> Result<String, CodegenError> {
+        let value = match expr {
+            Expr::Number(n) => format!("lang_number({n})"),
+            // `{:?}` keeps every digit needed to read the same double back.
+            Expr::Float(n) => format!("lang_float({n:?})"),
+            Expr::String(s) => format!("lang_string({})", string_literal(s)),
+            Expr::Bool(b) => format!("lang_bool({b})"),
+            Expr::Grouping(inner) => return self.expression(inner),
+            Expr::Unary {
+                operator,
+                operand,
+                line,
+                column,
+            } => {
+                let operand = self.expression(operand)?;
+                let function = match operator {
+                    UnaryOp::Negate => "lang_negate",
+                    UnaryOp::Not => "lang_not",
+                };
+                format!("{function}({operand}, {line}, {column})")
+            }
+            Expr::Binary {
+                left,
+                operator: operator @ (BinaryOp::And | BinaryOp::Or),
+                right,
+                line,
+                column,
+            } => {
+                let symbol = operator.symbol();
+                let left = self.expression(left)?;
+                let result = self.temp(format!(
+                    "lang_bool(lang_expect_bool({left}, \"{symbol}\", {line}, {column}))"
+                ));
+
+                // Short-circuit: the right operand is only evaluated when it
+                // can change the result.
+                let negate = if matches!(operator, BinaryOp::And) {
+                    ""
+                } else {
+                    "!"
+                };
+                self.open(format!("if ({negate}{result}.as.boolean) {{"));
+                let right = self.expression(right)?;
+                self.line(format!(
+                    "{result} = lang_bool(lang_expect_bool({right}, \"{symbol}\", {line}, \
+                     {column}));"
+                ));
+                self.close("}");
+                return Ok(result);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                line,
+                column,
+            } => {
+                let left = self.expression(left)?;
+                let right = self.expression(right)?;
+                match operator {
+                    BinaryOp::Equal => format!("lang_bool(lang_equal({left}, {right}))"),
+                    BinaryOp::NotEqual => format!("lang_bool(!lang_equal({left}, {right}))"),
+                    _ => {
+                        let function = match operator {
+                            BinaryOp::Add => "lang_add",
+                            BinaryOp::Sub => "lang_subtract",
+                            BinaryOp::Mul => "lang_multiply",
+                            BinaryOp::Div => "lang_divide",
+                            BinaryOp::Less => "lang_less",
+                            BinaryOp::LessEqual => "lang_less_equal",
+                            BinaryOp::Greater => "lang_greater",
+                            BinaryOp::GreaterEqual => "lang_greater_equal",
+                            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::And | BinaryOp::Or => {
+                                unreachable!()
+                            }
+                        };
+                        format!("{function}({left}, {right}, {line}, {column})")
+                    }
+                }
+            }
+            Expr::Variable { name, depth } => match depth.get() {
+                Some(_) => {
+                    let cell = self.cell(&name.lexeme, self.functions.len() - 1);
+                    format!("*{cell}")
+                }
+                None => {
+                    let global = self.global(&name.lexeme);
+                    format!("lang_get_global({global}, {}, {})", name.line, name.column)
+                }
+            },
+            Expr::Assign { name, value, depth } => {
+                let value = self.expression(value)?;
+                match depth.get() {
+                    Some(_) => {
+                        let cell = self.cell(&name.lexeme, self.functions.len() - 1);
+                        self.line(format!("*{cell} = {value};"));
+                    }
+                    None => {
+                        let global = self.global(&name.lexeme);
+                        self.line(format!(
+                            "lang_set_global({global}, {value}, {}, {});",
+                            name.line, name.column
+                        ));
+                    }
+                }
+                return Ok(value);
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                line,
+                column,
+            } => {
+                let callee = self.expression(callee)?;
+                let mut values = Vec::new();
+                for argument in arguments {
+                    values.push(self.expression(argument)?);
+                }
+                let args = match values.len() {
+                    0 => "NULL".to_string(),
+                    _ => format!("(LangValue[]){{{}}}", values.join(", ")),
+                };
+                format!(
+                    "lang_call({callee}, {args}, {}, {line}, {column})",
+                    values.len()
+                )
+            }
+            Expr::Function(function) => self.function(function)?,
+            Expr::Interpolation(parts) => {
+                let mut values = Vec::new();
+                for part in parts {
+                    values.push(match part {
+                        InterpolationPart::Literal(text) => {
+                            format!("lang_string({})", string_literal(text))
+                        }
+                        InterpolationPart::Expr(expr) => self.expression(expr)?,
+                    });
+                }
+                format!(
+                    "lang_interpolate((LangValue[]){{{}}}, {})",
+                    values.join(", "),
+                    values.len()
+                )
+            }
+        };
+        Ok(self.temp(value))
+    }
+}
+
+/// Quotes `s` as a C string literal, using octal escapes so a following
+/// digit can't extend them.
+fn string_literal(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' => write!(literal, "\\{}", byte as char).unwrap(),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => write!(literal, "\\{byte:03o}").unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser::Parser, resolver};
+
+    fn parse(input: &str) -> Program {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        resolver::resolve(&program).unwrap();
+        program
+    }
+
+    #[test]
+    fn test_emitted_code() {
+        assert_eq!(
+            emit(&parse("let a = 1;\nprint a + 2;")).unwrap(),
+            "\
+/* Generated by lang */
+#include \"lang_runtime.h\"
+
+static LangGlobal globals[] = {
+    {\"a\"},
+};
+
+int main(void) {
+    LangValue t1 = lang_number(1);
+    lang_define_global(&globals[0], t1);
+    LangValue t2 = lang_get_global(&globals[0], 2, 7);
+    LangValue t3 = lang_number(2);
+    LangValue t4 = lang_add(t2, t3, 2, 9);
+    lang_print(t4);
+    return 0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_closures_capture_cells() {
+        let code = emit(&parse(
+            "fn outer() { let x = 1; fn inner() { return x; } return inner; }",
+        ))
+        .unwrap();
+        assert!(code.contains("LangValue *v_x_3 = lang_cell(t2);"));
+        assert!(code.contains("LangValue t6 = *self->cells[0];"));
+        assert!(code.contains(
+            "*v_inner_4 = lang_closure(\"inner\", 0, fn_5, 1, (LangValue *[]){v_x_3});"
+        ));
+    }
+
+    #[test]
+    fn test_string_literals() {
+        assert_eq!(string_literal("a\"b\\c\nd1"), "\"a\\\"b\\\\c\\012d1\"");
+    }
+}
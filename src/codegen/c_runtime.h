@@ -0,0 +1,494 @@
+/*
+ * Runtime for C code generated by lang's C backend (`--emit c`). Values are
+ * tagged unions; strings and closures are allocated with malloc and never
+ * freed, which is fine for the short-lived scripts this targets.
+ *
+ * Every operation mirrors the tree-walking interpreter, including its error
+ * messages and the way it prints floats. Runtime errors print
+ * `line:column: message` to stderr and exit with status 1.
+ *
+ * Requires C11.
+ */
+#ifndef LANG_RUNTIME_H
+#define LANG_RUNTIME_H
+
+#include <inttypes.h>
+#include <math.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Deepest call nesting allowed before reporting a stack overflow. */
+#define LANG_MAX_CALL_DEPTH 200
+
+typedef enum {
+    LANG_NIL,
+    LANG_BOOL,
+    LANG_NUMBER,
+    LANG_FLOAT,
+    LANG_STRING,
+    LANG_FUNCTION,
+} LangTag;
+
+typedef struct LangClosure LangClosure;
+
+typedef struct {
+    LangTag tag;
+    union {
+        bool boolean;
+        int64_t number;
+        double floating;
+        const char *string;
+        LangClosure *function;
+    } as;
+} LangValue;
+
+typedef LangValue (*LangCode)(LangClosure *self, LangValue *args);
+
+struct LangClosure {
+    /* NULL for anonymous functions. */
+    const char *name;
+    int arity;
+    LangCode code;
+    /* Heap cells of the variables the function captures. */
+    LangValue **cells;
+};
+
+typedef struct {
+    const char *name;
+    bool defined;
+    LangValue value;
+} LangGlobal;
+
+static int lang_call_depth = 0;
+
+_Noreturn static inline void lang_error(uint32_t line, uint32_t column, const char *format, ...) {
+    va_list args;
+    fflush(stdout);
+    fprintf(stderr, "%" PRIu32 ":%" PRIu32 ": ", line, column);
+    va_start(args, format);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    fputc('\n', stderr);
+    exit(1);
+}
+
+static inline void *lang_alloc(size_t size) {
+    void *memory = malloc(size);
+    if (memory == NULL) {
+        fputs("Out of memory\n", stderr);
+        exit(1);
+    }
+    return memory;
+}
+
+static inline LangValue lang_nil(void) {
+    LangValue value = {.tag = LANG_NIL};
+    return value;
+}
+
+static inline LangValue lang_bool(bool boolean) {
+    LangValue value = {.tag = LANG_BOOL, .as.boolean = boolean};
+    return value;
+}
+
+static inline LangValue lang_number(int64_t number) {
+    LangValue value = {.tag = LANG_NUMBER, .as.number = number};
+    return value;
+}
+
+static inline LangValue lang_float(double floating) {
+    LangValue value = {.tag = LANG_FLOAT, .as.floating = floating};
+    return value;
+}
+
+static inline LangValue lang_string(const char *string) {
+    LangValue value = {.tag = LANG_STRING, .as.string = string};
+    return value;
+}
+
+static inline LangValue lang_closure(const char *name, int arity, LangCode code, int cell_count,
+                                     LangValue **cells) {
+    LangClosure *closure = lang_alloc(sizeof(LangClosure));
+    closure->name = name;
+    closure->arity = arity;
+    closure->code = code;
+    closure->cells = NULL;
+    if (cell_count > 0) {
+        closure->cells = lang_alloc(sizeof(LangValue *) * (size_t)cell_count);
+        memcpy(closure->cells, cells, sizeof(LangValue *) * (size_t)cell_count);
+    }
+
+    LangValue value = {.tag = LANG_FUNCTION, .as.function = closure};
+    return value;
+}
+
+/* Boxes a local variable so closures can share it. */
+static inline LangValue *lang_cell(LangValue value) {
+    LangValue *cell = lang_alloc(sizeof(LangValue));
+    *cell = value;
+    return cell;
+}
+
+static inline const char *lang_type_name(LangValue value) {
+    switch (value.tag) {
+    case LANG_NIL:
+        return "nil";
+    case LANG_BOOL:
+        return "bool";
+    case LANG_NUMBER:
+        return "number";
+    case LANG_FLOAT:
+        return "float";
+    case LANG_STRING:
+        return "string";
+    case LANG_FUNCTION:
+        return "function";
+    }
+    return "unknown";
+}
+
+static inline bool lang_truthy(LangValue value) {
+    switch (value.tag) {
+    case LANG_NIL:
+        return false;
+    case LANG_BOOL:
+        return value.as.boolean;
+    default:
+        return true;
+    }
+}
+
+/* Large enough for any number `lang_format` writes. */
+#define LANG_FORMAT_SIZE 48
+
+/*
+ * Writes the shortest decimal that reads back as `x`, the way Rust's `{:?}`
+ * does: in plain notation with at least one decimal between 1e-4 and 1e16,
+ * and in exponent notation outside that range.
+ */
+static inline void lang_format_float(double x, char buffer[LANG_FORMAT_SIZE]) {
+    if (isnan(x)) {
+        strcpy(buffer, "NaN");
+        return;
+    }
+    if (isinf(x)) {
+        strcpy(buffer, x < 0 ? "-inf" : "inf");
+        return;
+    }
+
+    char digits[32];
+    int precision = 0;
+    for (;;) {
+        snprintf(digits, sizeof digits, "%.*e", precision, x);
+        if (strtod(digits, NULL) == x) {
+            break;
+        }
+        precision++;
+    }
+    char *e = strchr(digits, 'e');
+    int exponent = atoi(e + 1);
+
+    double magnitude = x < 0 ? -x : x;
+    if (magnitude == 0 || (magnitude >= 1e-4 && magnitude < 1e16)) {
+        int decimals = precision - exponent;
+        snprintf(buffer, LANG_FORMAT_SIZE, "%.*f", decimals > 1 ? decimals : 1, x);
+    } else {
+        *e = '\0';
+        snprintf(buffer, LANG_FORMAT_SIZE, "%se%d", digits, exponent);
+    }
+}
+
+/* The text `print` shows for `value`. Numbers are written to `buffer`. */
+static inline const char *lang_format(LangValue value, char buffer[LANG_FORMAT_SIZE]) {
+    switch (value.tag) {
+    case LANG_NIL:
+        return "nil";
+    case LANG_BOOL:
+        return value.as.boolean ? "true" : "false";
+    case LANG_NUMBER:
+        snprintf(buffer, LANG_FORMAT_SIZE, "%" PRId64, value.as.number);
+        return buffer;
+    case LANG_FLOAT:
+        lang_format_float(value.as.floating, buffer);
+        return buffer;
+    case LANG_STRING:
+        return value.as.string;
+    case LANG_FUNCTION:
+        if (value.as.function->name != NULL) {
+            const char *name = value.as.function->name;
+            char *text = lang_alloc(strlen(name) + sizeof "<fn >");
+            sprintf(text, "<fn %s>", name);
+            return text;
+        }
+        return "<fn>";
+    }
+    return "";
+}
+
+static inline void lang_print(LangValue value) {
+    char buffer[LANG_FORMAT_SIZE];
+    puts(lang_format(value, buffer));
+}
+
+/* Joins `count` values into a string, each shown as `print` would show it. */
+static inline LangValue lang_interpolate(LangValue *values, int count) {
+    char buffer[LANG_FORMAT_SIZE];
+    size_t length = 0;
+    for (int i = 0; i < count; i++) {
+        length += strlen(lang_format(values[i], buffer));
+    }
+    char *result = lang_alloc(length + 1);
+    char *end = result;
+    for (int i = 0; i < count; i++) {
+        const char *text = lang_format(values[i], buffer);
+        size_t text_length = strlen(text);
+        memcpy(end, text, text_length);
+        end += text_length;
+    }
+    *end = '\0';
+    return lang_string(result);
+}
+
+static inline bool lang_is_number(LangValue value) {
+    return value.tag == LANG_NUMBER || value.tag == LANG_FLOAT;
+}
+
+/* Whether an operation on `a` and `b` works on floats: both are numbers, and
+ * at least one of them is a float. */
+static inline bool lang_floats(LangValue a, LangValue b) {
+    return lang_is_number(a) && lang_is_number(b) &&
+           (a.tag == LANG_FLOAT || b.tag == LANG_FLOAT);
+}
+
+static inline double lang_as_float(LangValue value) {
+    return value.tag == LANG_FLOAT ? value.as.floating : (double)value.as.number;
+}
+
+static inline bool lang_equal(LangValue a, LangValue b) {
+    if (lang_floats(a, b)) {
+        return lang_as_float(a) == lang_as_float(b);
+    }
+    if (a.tag != b.tag) {
+        return false;
+    }
+    switch (a.tag) {
+    case LANG_NIL:
+        return true;
+    case LANG_BOOL:
+        return a.as.boolean == b.as.boolean;
+    case LANG_NUMBER:
+        return a.as.number == b.as.number;
+    case LANG_FLOAT:
+        return a.as.floating == b.as.floating;
+    case LANG_STRING:
+        return strcmp(a.as.string, b.as.string) == 0;
+    case LANG_FUNCTION:
+        return a.as.function == b.as.function;
+    }
+    return false;
+}
+
+_Noreturn static inline void lang_operand_error(const char *symbol, LangValue a, LangValue b,
+                                                uint32_t line, uint32_t column) {
+    lang_error(line, column, "Invalid operand types for '%s': %s and %s", symbol,
+               lang_type_name(a), lang_type_name(b));
+}
+
+static inline bool lang_numbers(LangValue a, LangValue b) {
+    return a.tag == LANG_NUMBER && b.tag == LANG_NUMBER;
+}
+
+_Noreturn static inline void lang_overflow(uint32_t line, uint32_t column) {
+    lang_error(line, column, "Integer overflow");
+}
+
+/* Overflow is checked before each operation, since C leaves it undefined. */
+static inline LangValue lang_add(LangValue a, LangValue b, uint32_t line, uint32_t column) {
+    if (a.tag == LANG_STRING && b.tag == LANG_STRING) {
+        size_t a_len = strlen(a.as.string);
+        size_t b_len = strlen(b.as.string);
+        char *result = lang_alloc(a_len + b_len + 1);
+        memcpy(result, a.as.string, a_len);
+        memcpy(result + a_len, b.as.string, b_len + 1);
+        return lang_string(result);
+    }
+    if (lang_floats(a, b)) {
+        return lang_float(lang_as_float(a) + lang_as_float(b));
+    }
+    if (!lang_numbers(a, b)) {
+        lang_operand_error("+", a, b, line, column);
+    }
+    int64_t x = a.as.number, y = b.as.number;
+    if ((y > 0 && x > INT64_MAX - y) || (y < 0 && x < INT64_MIN - y)) {
+        lang_overflow(line, column);
+    }
+    return lang_number(x + y);
+}
+
+static inline LangValue lang_subtract(LangValue a, LangValue b, uint32_t line, uint32_t column) {
+    if (lang_floats(a, b)) {
+        return lang_float(lang_as_float(a) - lang_as_float(b));
+    }
+    if (!lang_numbers(a, b)) {
+        lang_operand_error("-", a, b, line, column);
+    }
+    int64_t x = a.as.number, y = b.as.number;
+    if ((y < 0 && x > INT64_MAX + y) || (y > 0 && x < INT64_MIN + y)) {
+        lang_overflow(line, column);
+    }
+    return lang_number(x - y);
+}
+
+static inline LangValue lang_multiply(LangValue a, LangValue b, uint32_t line, uint32_t column) {
+    if (lang_floats(a, b)) {
+        return lang_float(lang_as_float(a) * lang_as_float(b));
+    }
+    if (!lang_numbers(a, b)) {
+        lang_operand_error("*", a, b, line, column);
+    }
+    int64_t x = a.as.number, y = b.as.number;
+    bool overflows;
+    if (x > 0) {
+        overflows = y > 0 ? x > INT64_MAX / y : y < INT64_MIN / x;
+    } else {
+        overflows = y > 0 ? x < INT64_MIN / y : x != 0 && y < INT64_MAX / x;
+    }
+    if (overflows) {
+        lang_overflow(line, column);
+    }
+    return lang_number(x * y);
+}
+
+static inline LangValue lang_divide(LangValue a, LangValue b, uint32_t line, uint32_t column) {
+    if (lang_floats(a, b)) {
+        return lang_float(lang_as_float(a) / lang_as_float(b));
+    }
+    if (!lang_numbers(a, b)) {
+        lang_operand_error("/", a, b, line, column);
+    }
+    if (b.as.number == 0) {
+        lang_error(line, column, "Division by zero");
+    }
+    if (a.as.number == INT64_MIN && b.as.number == -1) {
+        lang_overflow(line, column);
+    }
+    return lang_number(a.as.number / b.as.number);
+}
+
+static inline LangValue lang_less(LangValue a, LangValue b, uint32_t line, uint32_t column) {
+    if (lang_floats(a, b)) {
+        return lang_bool(lang_as_float(a) < lang_as_float(b));
+    }
+    if (!lang_numbers(a, b)) {
+        lang_operand_error("<", a, b, line, column);
+    }
+    return lang_bool(a.as.number < b.as.number);
+}
+
+static inline LangValue lang_less_equal(LangValue a, LangValue b, uint32_t line,
+                                        uint32_t column) {
+    if (lang_floats(a, b)) {
+        return lang_bool(lang_as_float(a) <= lang_as_float(b));
+    }
+    if (!lang_numbers(a, b)) {
+        lang_operand_error("<=", a, b, line, column);
+    }
+    return lang_bool(a.as.number <= b.as.number);
+}
+
+static inline LangValue lang_greater(LangValue a, LangValue b, uint32_t line, uint32_t column) {
+    if (lang_floats(a, b)) {
+        return lang_bool(lang_as_float(a) > lang_as_float(b));
+    }
+    if (!lang_numbers(a, b)) {
+        lang_operand_error(">", a, b, line, column);
+    }
+    return lang_bool(a.as.number > b.as.number);
+}
+
+static inline LangValue lang_greater_equal(LangValue a, LangValue b, uint32_t line,
+                                           uint32_t column) {
+    if (lang_floats(a, b)) {
+        return lang_bool(lang_as_float(a) >= lang_as_float(b));
+    }
+    if (!lang_numbers(a, b)) {
+        lang_operand_error(">=", a, b, line, column);
+    }
+    return lang_bool(a.as.number >= b.as.number);
+}
+
+static inline LangValue lang_negate(LangValue value, uint32_t line, uint32_t column) {
+    if (value.tag == LANG_FLOAT) {
+        return lang_float(-value.as.floating);
+    }
+    if (value.tag != LANG_NUMBER) {
+        lang_error(line, column, "Operand of '-' must be a number, got %s",
+                   lang_type_name(value));
+    }
+    if (value.as.number == INT64_MIN) {
+        lang_overflow(line, column);
+    }
+    return lang_number(-value.as.number);
+}
+
+static inline LangValue lang_not(LangValue value, uint32_t line, uint32_t column) {
+    if (value.tag != LANG_BOOL) {
+        lang_error(line, column, "Operand of '!' must be a bool, got %s", lang_type_name(value));
+    }
+    return lang_bool(!value.as.boolean);
+}
+
+/* Operands of `and` and `or` must be bools. */
+static inline bool lang_expect_bool(LangValue value, const char *symbol, uint32_t line,
+                                    uint32_t column) {
+    if (value.tag != LANG_BOOL) {
+        lang_error(line, column, "Operands of '%s' must be bools, got %s", symbol,
+                   lang_type_name(value));
+    }
+    return value.as.boolean;
+}
+
+static inline LangValue lang_get_global(LangGlobal *global, uint32_t line, uint32_t column) {
+    if (!global->defined) {
+        lang_error(line, column, "Undefined variable '%s'", global->name);
+    }
+    return global->value;
+}
+
+static inline void lang_set_global(LangGlobal *global, LangValue value, uint32_t line,
+                                   uint32_t column) {
+    if (!global->defined) {
+        lang_error(line, column, "Cannot assign to undeclared variable '%s'", global->name);
+    }
+    global->value = value;
+}
+
+static inline void lang_define_global(LangGlobal *global, LangValue value) {
+    global->defined = true;
+    global->value = value;
+}
+
+static inline LangValue lang_call(LangValue callee, LangValue *args, int count, uint32_t line,
+                                  uint32_t column) {
+    if (callee.tag != LANG_FUNCTION) {
+        lang_error(line, column, "Can only call functions, got %s", lang_type_name(callee));
+    }
+    LangClosure *closure = callee.as.function;
+    if (closure->arity != count) {
+        lang_error(line, column, "Expected %d arguments but got %d", closure->arity, count);
+    }
+    if (lang_call_depth >= LANG_MAX_CALL_DEPTH) {
+        lang_error(line, column, "Stack overflow");
+    }
+
+    lang_call_depth++;
+    LangValue result = closure->code(closure, args);
+    lang_call_depth--;
+    return result;
+}
+
+#endif
@@ -0,0 +1,883 @@
+//! Lowers a resolved program to a textual LLVM IR module (`.ll`) that can be
+//! run with `lli` or compiled with `clang`/`llc` and linked against libc.
+//!
+//! Numbers become `i64`, bools `i1` and strings NUL-terminated `i8*`. Which
+//! programs compile is decided by the static typing in [`super::typed`].
+//! Integer overflow, division by zero and stack overflow are reported at
+//! runtime by a small runtime emitted into the module, as
+//! `line:column: message` on stderr with exit status 1.
+
+use std::{fmt::Write, mem};
+
+use super::{
+    CodegenError,
+    typed::{self, Operand, Target, Type, Variable},
+};
+use crate::{BinaryOp, Program};
+
+fn llvm_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Int => "i64",
+        Type::Bool => "i1",
+        Type::Str => "i8*",
+        Type::Nil => "void",
+    }
+}
+
+struct Branch {
+    else_label: String,
+    end_label: String,
+    in_else: bool,
+}
+
+struct Loop {
+    condition_label: String,
+    body_label: String,
+    continue_label: String,
+    break_label: String,
+}
+
+struct Logical {
+    is_and: bool,
+    left_block: String,
+    end_label: String,
+}
+
+/// The function currently being emitted. Stack slots are collected
+/// separately so they can all live in the entry block.
+struct FunctionState {
+    allocas: String,
+    body: String,
+    temps: usize,
+    labels: usize,
+    block: String,
+    terminated: bool,
+}
+
+impl FunctionState {
+    fn new() -> Self {
+        FunctionState {
+            allocas: String::new(),
+            body: String::new(),
+            temps: 0,
+            labels: 0,
+            block: "entry".to_string(),
+            terminated: false,
+        }
+    }
+}
+
+struct Generator {
+    state: FunctionState,
+    strings: Vec<String>,
+    definitions: Vec<String>,
+}
+
+/// Emits a complete module whose `main` runs `program`.
+pub fn emit(program: &Program) -> Result<String, CodegenError> {
+    let mut generator = Generator {
+        state: FunctionState::new(),
+        strings: Vec::new(),
+        definitions: Vec::new(),
+    };
+
+    let globals = typed::lower(program, &mut generator)?;
+    generator.terminate("ret i32 0".to_string());
+
+    Ok(generator.finish(globals))
+}
+
+impl Generator {
+    fn finish(mut self, global_types: Vec<(String, Type)>) -> String {
+        let mut globals = String::new();
+        for (name, ty) in global_types {
+            let initial = match ty {
+                Type::Int => "0".to_string(),
+                Type::Bool => "false".to_string(),
+                Type::Str => self.string_constant(""),
+                Type::Nil => unreachable!("nil is never stored"),
+            };
+            writeln!(
+                globals,
+                "@g.{name} = internal global {} {initial}",
+                llvm_type(ty)
+            )
+            .unwrap();
+        }
+
+        let mut out = String::new();
+        out.push_str("; Generated by lang\n\n");
+        out.push_str(RUNTIME);
+        out.push('\n');
+        for (index, s) in self.strings.iter().enumerate() {
+            writeln!(
+                out,
+                "@.str.{index} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+                s.len() + 1,
+                escape(s)
+            )
+            .unwrap();
+        }
+        if !self.strings.is_empty() {
+            out.push('\n');
+        }
+        if !globals.is_empty() {
+            out.push_str(&globals);
+            out.push('\n');
+        }
+        for definition in &self.definitions {
+            out.push_str(definition);
+            out.push('\n');
+        }
+        write!(
+            out,
+            "define i32 @main() {{\nentry:\n{}{}}}\n",
+            self.state.allocas, self.state.body
+        )
+        .unwrap();
+        out
+    }
+
+    fn emit(&mut self, instruction: String) {
+        if self.state.terminated {
+            // Code after `return`, `break` or `continue` is unreachable but
+            // still needs a block to live in.
+            let label = self.new_label("dead");
+            self.start_block(&label);
+        }
+        writeln!(self.state.body, "  {instruction}").unwrap();
+    }
+
+    fn terminate(&mut self, instruction: String) {
+        self.emit(instruction);
+        self.state.terminated = true;
+    }
+
+    fn temp(&mut self) -> String {
+        self.state.temps += 1;
+        format!("%t{}", self.state.temps)
+    }
+
+    /// Emits `instruction`, returning the temporary holding its result.
+    fn instruction(&mut self, instruction: String) -> String {
+        let temp = self.temp();
+        self.emit(format!("{temp} = {instruction}"));
+        temp
+    }
+
+    fn new_label(&mut self, prefix: &str) -> String {
+        self.state.labels += 1;
+        format!("{prefix}.{}", self.state.labels)
+    }
+
+    /// Starts `label`, falling through into it from the current block.
+    fn start_block(&mut self, label: &str) {
+        if !self.state.terminated {
+            writeln!(self.state.body, "  br label %{label}").unwrap();
+        }
+        writeln!(self.state.body, "{label}:").unwrap();
+        self.state.block = label.to_string();
+        self.state.terminated = false;
+    }
+
+    fn string_constant(&mut self, s: &str) -> String {
+        let index = match self.strings.iter().position(|existing| existing == s) {
+            Some(index) => index,
+            None => {
+                self.strings.push(s.to_string());
+                self.strings.len() - 1
+            }
+        };
+        let ty = format!("[{} x i8]", s.len() + 1);
+        format!("getelementptr inbounds ({ty}, {ty}* @.str.{index}, i64 0, i64 0)")
+    }
+
+    fn pointer(variable: &Variable) -> String {
+        match variable {
+            Variable::Local(pointer) => pointer.clone(),
+            Variable::Global(name) => format!("@g.{name}"),
+        }
+    }
+}
+
+impl Target for Generator {
+    const NAME: &'static str = "LLVM";
+
+    type Value = String;
+    type FunctionState = FunctionState;
+    type Branch = Branch;
+    type Loop = Loop;
+    type Logical = Logical;
+
+    fn number(&mut self, n: i64) -> String {
+        n.to_string()
+    }
+
+    fn string(&mut self, s: &str) -> String {
+        self.string_constant(s)
+    }
+
+    fn boolean(&mut self, b: bool) -> String {
+        b.to_string()
+    }
+
+    fn nil(&mut self) -> String {
+        String::new()
+    }
+
+    fn negate(&mut self, value: String, line: u32, column: u32) -> String {
+        self.instruction(format!(
+            "call i64 @lang_sub(i64 0, i64 {value}, i32 {line}, i32 {column})"
+        ))
+    }
+
+    fn not(&mut self, value: String) -> String {
+        self.instruction(format!("xor i1 {value}, true"))
+    }
+
+    fn arithmetic(
+        &mut self,
+        operator: &BinaryOp,
+        left: String,
+        right: String,
+        line: u32,
+        column: u32,
+    ) -> String {
+        let function = match operator {
+            BinaryOp::Add => "lang_add",
+            BinaryOp::Sub => "lang_sub",
+            BinaryOp::Mul => "lang_mul",
+            _ => "lang_div",
+        };
+        self.instruction(format!(
+            "call i64 @{function}(i64 {left}, i64 {right}, i32 {line}, i32 {column})"
+        ))
+    }
+
+    fn compare(&mut self, operator: &BinaryOp, left: String, right: String) -> String {
+        let predicate = match operator {
+            BinaryOp::Less => "slt",
+            BinaryOp::LessEqual => "sle",
+            BinaryOp::Greater => "sgt",
+            _ => "sge",
+        };
+        self.instruction(format!("icmp {predicate} i64 {left}, {right}"))
+    }
+
+    fn equality(&mut self, left: Operand<String>, right: Operand<String>, equal: bool) -> String {
+        let (a, b) = (&left.value, &right.value);
+        match left.ty {
+            _ if left.ty != right.ty => (!equal).to_string(),
+            Type::Nil => equal.to_string(),
+            Type::Str => {
+                let eq = self.instruction(format!("call i1 @lang_str_eq(i8* {a}, i8* {b})"));
+                if equal {
+                    return eq;
+                }
+                self.instruction(format!("xor i1 {eq}, true"))
+            }
+            ty => {
+                let predicate = if equal { "eq" } else { "ne" };
+                self.instruction(format!("icmp {predicate} {} {a}, {b}", llvm_type(ty)))
+            }
+        }
+    }
+
+    fn concat(&mut self, left: String, right: String) -> String {
+        self.instruction(format!("call i8* @lang_concat(i8* {left}, i8* {right})"))
+    }
+
+    fn stringify(&mut self, value: Operand<String>) -> String {
+        match value.ty {
+            Type::Int => {
+                self.instruction(format!("call i8* @lang_int_to_str(i64 {})", value.value))
+            }
+            Type::Bool => {
+                let true_ = self.string_constant("true");
+                let false_ = self.string_constant("false");
+                self.instruction(format!(
+                    "select i1 {}, i8* {true_}, i8* {false_}",
+                    value.value
+                ))
+            }
+            Type::Str => value.value,
+            Type::Nil => self.string_constant("nil"),
+        }
+    }
+
+    fn start_logical(&mut self, is_and: bool, left: String) -> Logical {
+        let left_block = self.state.block.clone();
+        let right_label = self.new_label("rhs");
+        let end_label = self.new_label("endlogic");
+        let (on_true, on_false) = if is_and {
+            (&right_label, &end_label)
+        } else {
+            (&end_label, &right_label)
+        };
+        self.terminate(format!("br i1 {left}, label %{on_true}, label %{on_false}"));
+        self.start_block(&right_label);
+        Logical {
+            is_and,
+            left_block,
+            end_label,
+        }
+    }
+
+    fn end_logical(&mut self, logical: Logical, right: String) -> String {
+        let right_block = self.state.block.clone();
+        self.start_block(&logical.end_label);
+        self.instruction(format!(
+            "phi i1 [ {}, %{} ], [ {right}, %{right_block} ]",
+            !logical.is_and, logical.left_block
+        ))
+    }
+
+    fn load(&mut self, variable: &Variable, ty: Type) -> String {
+        let pointer = Self::pointer(variable);
+        self.instruction(format!("load {0}, {0}* {pointer}", llvm_type(ty)))
+    }
+
+    fn store(&mut self, variable: &Variable, value: Operand<String>) -> String {
+        let pointer = Self::pointer(variable);
+        self.emit(format!(
+            "store {0} {1}, {0}* {pointer}",
+            llvm_type(value.ty),
+            value.value
+        ));
+        value.value
+    }
+
+    fn define_global(&mut self, name: &str, value: Operand<String>) {
+        self.store(&Variable::Global(name.to_string()), value);
+    }
+
+    fn define_local(&mut self, name: &str, value: Operand<String>) -> String {
+        self.state.temps += 1;
+        let pointer = format!("%{name}.{}", self.state.temps);
+        writeln!(
+            self.state.allocas,
+            "  {pointer} = alloca {}",
+            llvm_type(value.ty)
+        )
+        .unwrap();
+        let variable = Variable::Local(pointer);
+        self.store(&variable, value);
+        Self::pointer(&variable)
+    }
+
+    fn call(
+        &mut self,
+        symbol: &str,
+        arguments: Vec<Operand<String>>,
+        return_type: Type,
+        line: u32,
+        column: u32,
+    ) -> String {
+        let arguments = arguments
+            .iter()
+            .map(|argument| format!("{} {}", llvm_type(argument.ty), argument.value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let call = format!("call {} @{symbol}({arguments})", llvm_type(return_type));
+
+        self.emit(format!("call void @lang_enter(i32 {line}, i32 {column})"));
+        let result = if return_type == Type::Nil {
+            self.emit(call);
+            String::new()
+        } else {
+            self.instruction(call)
+        };
+        self.emit("call void @lang_leave()".to_string());
+        result
+    }
+
+    fn print(&mut self, value: Operand<String>) {
+        match value.ty {
+            Type::Nil => self.emit("call void @lang_print_nil()".to_string()),
+            ty => self.emit(format!(
+                "call void @lang_print_{}({} {})",
+                match ty {
+                    Type::Int => "int",
+                    Type::Bool => "bool",
+                    _ => "str",
+                },
+                llvm_type(ty),
+                value.value
+            )),
+        }
+    }
+
+    fn discard(&mut self, _value: Operand<String>) {}
+
+    fn condition(&mut self, value: Operand<String>) -> String {
+        match value.ty {
+            Type::Bool => value.value,
+            Type::Nil => "false".to_string(),
+            Type::Int | Type::Str => "true".to_string(),
+        }
+    }
+
+    fn start_if(&mut self, condition: String) -> Branch {
+        let then_label = self.new_label("then");
+        let else_label = self.new_label("else");
+        let end_label = self.new_label("endif");
+        self.terminate(format!(
+            "br i1 {condition}, label %{then_label}, label %{else_label}"
+        ));
+        self.start_block(&then_label);
+        Branch {
+            else_label,
+            end_label,
+            in_else: false,
+        }
+    }
+
+    fn start_else(&mut self, branch: &mut Branch) {
+        self.terminate(format!("br label %{}", branch.end_label));
+        self.start_block(&branch.else_label);
+        branch.in_else = true;
+    }
+
+    fn end_if(&mut self, mut branch: Branch) {
+        if !branch.in_else {
+            self.start_else(&mut branch);
+        }
+        self.start_block(&branch.end_label);
+    }
+
+    fn start_loop(&mut self, is_for: bool) -> Loop {
+        let frame = if is_for {
+            let condition_label = self.new_label("for");
+            let body_label = self.new_label("body");
+            let continue_label = self.new_label("increment");
+            let break_label = self.new_label("endfor");
+            Loop {
+                condition_label,
+                body_label,
+                continue_label,
+                break_label,
+            }
+        } else {
+            let condition_label = self.new_label("while");
+            let body_label = self.new_label("body");
+            let break_label = self.new_label("endwhile");
+            Loop {
+                continue_label: condition_label.clone(),
+                condition_label,
+                body_label,
+                break_label,
+            }
+        };
+        self.start_block(&frame.condition_label);
+        frame
+    }
+
+    fn loop_condition(&mut self, frame: &Loop, condition: Option<String>) {
+        let condition = condition.unwrap_or_else(|| "true".to_string());
+        self.terminate(format!(
+            "br i1 {condition}, label %{}, label %{}",
+            frame.body_label, frame.break_label
+        ));
+        self.start_block(&frame.body_label);
+    }
+
+    fn start_increment(&mut self, frame: &Loop) {
+        self.start_block(&frame.continue_label);
+    }
+
+    fn end_loop(&mut self, frame: Loop) {
+        self.terminate(format!("br label %{}", frame.condition_label));
+        self.start_block(&frame.break_label);
+    }
+
+    fn break_loop(&mut self, frame: &Loop) {
+        self.terminate(format!("br label %{}", frame.break_label));
+    }
+
+    fn continue_loop(&mut self, frame: &Loop) {
+        self.terminate(format!("br label %{}", frame.continue_label));
+    }
+
+    fn start_function(&mut self) -> FunctionState {
+        mem::replace(&mut self.state, FunctionState::new())
+    }
+
+    fn parameter(&mut self, index: usize, name: &str, ty: Type) -> String {
+        self.define_local(name, Operand::new(ty, format!("%arg.{index}")))
+    }
+
+    fn return_value(&mut self, value: Operand<String>) {
+        match value.ty {
+            Type::Nil => self.terminate("ret void".to_string()),
+            ty => self.terminate(format!("ret {} {}", llvm_type(ty), value.value)),
+        }
+    }
+
+    fn end_body(&mut self, return_type: Type, fall_through: Option<(String, u32, u32)>) {
+        if return_type == Type::Nil {
+            self.terminate("ret void".to_string());
+        } else if let Some((message, line, column)) = fall_through {
+            let message = self.string_constant(&message);
+            self.emit(format!(
+                "call void @lang_error(i32 {line}, i32 {column}, i8* {message})"
+            ));
+            self.terminate("unreachable".to_string());
+        } else if !self.state.terminated {
+            // Every path has returned, but a block can still be open after
+            // an `if` whose branches both return.
+            self.terminate("unreachable".to_string());
+        }
+    }
+
+    fn finish_function(
+        &mut self,
+        saved: FunctionState,
+        symbol: &str,
+        parameters: &[Type],
+        return_type: Type,
+    ) {
+        let state = mem::replace(&mut self.state, saved);
+        let params = parameters
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| format!("{} %arg.{i}", llvm_type(*ty)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.definitions.push(format!(
+            "define internal {} @{symbol}({params}) {{\nentry:\n{}{}}}\n",
+            llvm_type(return_type),
+            state.allocas,
+            state.body
+        ));
+    }
+}
+
+/// Escapes `s` for an LLVM `c"..."` string.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for byte in s.bytes() {
+        if (byte.is_ascii_graphic() || byte == b' ') && byte != b'"' && byte != b'\\' {
+            escaped.push(byte as char);
+        } else {
+            write!(escaped, "\\{byte:02X}").unwrap();
+        }
+    }
+    escaped
+}
+
+/// Printing, strings and runtime errors, built on libc.
+const RUNTIME: &str = r#"declare i32 @printf(i8*, ...)
+declare i32 @dprintf(i32, i8*, ...)
+declare i32 @snprintf(i8*, i64, i8*, ...)
+declare i8* @malloc(i64)
+declare i64 @strlen(i8*)
+declare i8* @memcpy(i8*, i8*, i64)
+declare i32 @strcmp(i8*, i8*)
+declare void @exit(i32) noreturn
+declare { i64, i1 } @llvm.sadd.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.ssub.with.overflow.i64(i64, i64)
+declare { i64, i1 } @llvm.smul.with.overflow.i64(i64, i64)
+
+@lang.depth = internal global i32 0
+@lang.fmt.int = private unnamed_addr constant [6 x i8] c"%lld\0A\00"
+@lang.fmt.digits = private unnamed_addr constant [5 x i8] c"%lld\00"
+@lang.fmt.str = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+@lang.fmt.error = private unnamed_addr constant [11 x i8] c"%d:%d: %s\0A\00"
+@lang.true = private unnamed_addr constant [5 x i8] c"true\00"
+@lang.false = private unnamed_addr constant [6 x i8] c"false\00"
+@lang.nil = private unnamed_addr constant [4 x i8] c"nil\00"
+@lang.division = private unnamed_addr constant [17 x i8] c"Division by zero\00"
+@lang.overflow = private unnamed_addr constant [15 x i8] c"Stack overflow\00"
+@lang.integer.overflow = private unnamed_addr constant [17 x i8] c"Integer overflow\00"
+
+define internal void @lang_print_int(i64 %n) {
+  %format = getelementptr inbounds [6 x i8], [6 x i8]* @lang.fmt.int, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %format, i64 %n)
+  ret void
+}
+
+define internal void @lang_print_str(i8* %s) {
+  %format = getelementptr inbounds [4 x i8], [4 x i8]* @lang.fmt.str, i64 0, i64 0
+  call i32 (i8*, ...) @printf(i8* %format, i8* %s)
+  ret void
+}
+
+define internal void @lang_print_bool(i1 %b) {
+  %true = getelementptr inbounds [5 x i8], [5 x i8]* @lang.true, i64 0, i64 0
+  %false = getelementptr inbounds [6 x i8], [6 x i8]* @lang.false, i64 0, i64 0
+  %s = select i1 %b, i8* %true, i8* %false
+  call void @lang_print_str(i8* %s)
+  ret void
+}
+
+define internal void @lang_print_nil() {
+  %s = getelementptr inbounds [4 x i8], [4 x i8]* @lang.nil, i64 0, i64 0
+  call void @lang_print_str(i8* %s)
+  ret void
+}
+
+define internal void @lang_error(i32 %line, i32 %column, i8* %message) noreturn {
+  %format = getelementptr inbounds [11 x i8], [11 x i8]* @lang.fmt.error, i64 0, i64 0
+  call i32 (i32, i8*, ...) @dprintf(i32 2, i8* %format, i32 %line, i32 %column, i8* %message)
+  call void @exit(i32 1)
+  unreachable
+}
+
+define internal i8* @lang_concat(i8* %a, i8* %b) {
+  %a.len = call i64 @strlen(i8* %a)
+  %b.len = call i64 @strlen(i8* %b)
+  %len = add i64 %a.len, %b.len
+  %size = add i64 %len, 1
+  %result = call i8* @malloc(i64 %size)
+  call i8* @memcpy(i8* %result, i8* %a, i64 %a.len)
+  %tail = getelementptr inbounds i8, i8* %result, i64 %a.len
+  %b.size = add i64 %b.len, 1
+  call i8* @memcpy(i8* %tail, i8* %b, i64 %b.size)
+  ret i8* %result
+}
+
+define internal i8* @lang_int_to_str(i64 %n) {
+  %buffer = call i8* @malloc(i64 21)
+  %format = getelementptr inbounds [5 x i8], [5 x i8]* @lang.fmt.digits, i64 0, i64 0
+  call i32 (i8*, i64, i8*, ...) @snprintf(i8* %buffer, i64 21, i8* %format, i64 %n)
+  ret i8* %buffer
+}
+
+define internal i1 @lang_str_eq(i8* %a, i8* %b) {
+  %order = call i32 @strcmp(i8* %a, i8* %b)
+  %equal = icmp eq i32 %order, 0
+  ret i1 %equal
+}
+
+define internal void @lang_integer_overflow(i32 %line, i32 %column) noreturn {
+  %message = getelementptr inbounds [17 x i8], [17 x i8]* @lang.integer.overflow, i64 0, i64 0
+  call void @lang_error(i32 %line, i32 %column, i8* %message)
+  unreachable
+}
+
+define internal i64 @lang_add(i64 %a, i64 %b, i32 %line, i32 %column) {
+entry:
+  %result = call { i64, i1 } @llvm.sadd.with.overflow.i64(i64 %a, i64 %b)
+  %overflow = extractvalue { i64, i1 } %result, 1
+  br i1 %overflow, label %error, label %done
+error:
+  call void @lang_integer_overflow(i32 %line, i32 %column)
+  unreachable
+done:
+  %sum = extractvalue { i64, i1 } %result, 0
+  ret i64 %sum
+}
+
+define internal i64 @lang_sub(i64 %a, i64 %b, i32 %line, i32 %column) {
+entry:
+  %result = call { i64, i1 } @llvm.ssub.with.overflow.i64(i64 %a, i64 %b)
+  %overflow = extractvalue { i64, i1 } %result, 1
+  br i1 %overflow, label %error, label %done
+error:
+  call void @lang_integer_overflow(i32 %line, i32 %column)
+  unreachable
+done:
+  %difference = extractvalue { i64, i1 } %result, 0
+  ret i64 %difference
+}
+
+define internal i64 @lang_mul(i64 %a, i64 %b, i32 %line, i32 %column) {
+entry:
+  %result = call { i64, i1 } @llvm.smul.with.overflow.i64(i64 %a, i64 %b)
+  %overflow = extractvalue { i64, i1 } %result, 1
+  br i1 %overflow, label %error, label %done
+error:
+  call void @lang_integer_overflow(i32 %line, i32 %column)
+  unreachable
+done:
+  %product = extractvalue { i64, i1 } %result, 0
+  ret i64 %product
+}
+
+define internal i64 @lang_div(i64 %a, i64 %b, i32 %line, i32 %column) {
+entry:
+  %is.zero = icmp eq i64 %b, 0
+  br i1 %is.zero, label %zero, label %nonzero
+zero:
+  %message = getelementptr inbounds [17 x i8], [17 x i8]* @lang.division, i64 0, i64 0
+  call void @lang_error(i32 %line, i32 %column, i8* %message)
+  unreachable
+nonzero:
+  ; i64::MIN / -1 is the only quotient that overflows.
+  %is.min = icmp eq i64 %a, -9223372036854775808
+  %is.minus.one = icmp eq i64 %b, -1
+  %overflows = and i1 %is.min, %is.minus.one
+  br i1 %overflows, label %error, label %divide
+error:
+  call void @lang_integer_overflow(i32 %line, i32 %column)
+  unreachable
+divide:
+  %quotient = sdiv i64 %a, %b
+  ret i64 %quotient
+}
+
+define internal void @lang_enter(i32 %line, i32 %column) {
+entry:
+  %depth = load i32, i32* @lang.depth
+  %full = icmp sge i32 %depth, 200
+  br i1 %full, label %overflow, label %ok
+overflow:
+  %message = getelementptr inbounds [15 x i8], [15 x i8]* @lang.overflow, i64 0, i64 0
+  call void @lang_error(i32 %line, i32 %column, i8* %message)
+  unreachable
+ok:
+  %next = add i32 %depth, 1
+  store i32 %next, i32* @lang.depth
+  ret void
+}
+
+define internal void @lang_leave() {
+  %depth = load i32, i32* @lang.depth
+  %next = sub i32 %depth, 1
+  store i32 %next, i32* @lang.depth
+  ret void
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser::Parser, resolver};
+
+    fn parse(input: &str) -> Program {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        resolver::resolve(&program).unwrap();
+        program
+    }
+
+    fn emit_ir(input: &str) -> String {
+        emit(&parse(input)).unwrap()
+    }
+
+    fn emit_error(input: &str) -> (String, u32, u32) {
+        let error = emit(&parse(input)).unwrap_err();
+        (error.message, error.line, error.column)
+    }
+
+    #[test]
+    fn test_function_ir() {
+        let ir = emit_ir("fn add(a, b) { return a + b; }\nprint add(1, 2);");
+        assert!(ir.contains(
+            "\
+define internal i64 @fn.add(i64 %arg.0, i64 %arg.1) {
+entry:
+  %a.1 = alloca i64
+  %b.2 = alloca i64
+  store i64 %arg.0, i64* %a.1
+  store i64 %arg.1, i64* %b.2
+  %t3 = load i64, i64* %a.1
+  %t4 = load i64, i64* %b.2
+  %t5 = call i64 @lang_add(i64 %t3, i64 %t4, i32 1, i32 25)
+  ret i64 %t5
+}
+"
+        ));
+        assert!(ir.contains(
+            "\
+define i32 @main() {
+entry:
+  call void @lang_enter(i32 2, i32 10)
+  %t1 = call i64 @fn.add(i64 1, i64 2)
+  call void @lang_leave()
+  call void @lang_print_int(i64 %t1)
+  ret i32 0
+}
+"
+        ));
+    }
+
+    #[test]
+    fn test_fall_through_is_a_runtime_error() {
+        let ir = emit_ir("fn f(n) { while (true) return n; }\nprint f(1);");
+        assert!(ir.contains("c\"Function 'f' ended without returning a number\\00\""));
+        assert!(ir.contains("call void @lang_error(i32 1, i32 4, "));
+
+        // Nothing is left to trap when every path has returned.
+        let ir = emit_ir("fn f(n) { if (n) return 1; else return 2; }\nprint f(true);");
+        assert!(!ir.contains("ended without returning"));
+    }
+
+    #[test]
+    fn test_control_flow_ir() {
+        let ir = emit_ir("let i = 0; while (i < 3) { if (i == 1) break; i = i + 1; }");
+        assert!(ir.contains("@g.i = internal global i64 0\n"));
+        assert!(ir.contains(
+            "\
+define i32 @main() {
+entry:
+  store i64 0, i64* @g.i
+  br label %while.1
+while.1:
+  %t1 = load i64, i64* @g.i
+  %t2 = icmp slt i64 %t1, 3
+  br i1 %t2, label %body.2, label %endwhile.3
+body.2:
+  %t3 = load i64, i64* @g.i
+  %t4 = icmp eq i64 %t3, 1
+  br i1 %t4, label %then.4, label %else.5
+then.4:
+  br label %endwhile.3
+dead.7:
+  br label %endif.6
+else.5:
+  br label %endif.6
+endif.6:
+  %t5 = load i64, i64* @g.i
+  %t6 = call i64 @lang_add(i64 %t5, i64 1, i32 1, i32 53)
+  store i64 %t6, i64* @g.i
+  br label %while.1
+endwhile.3:
+  ret i32 0
+}
+"
+        ));
+    }
+
+    #[test]
+    fn test_string_constants_are_escaped() {
+        let ir = emit_ir("print \"a\\\\b\\nc\";");
+        assert!(ir.contains(r#"@.str.0 = private unnamed_addr constant [6 x i8] c"a\5Cb\0Ac\00""#));
+    }
+
+    #[test]
+    fn test_unsupported_programs() {
+        assert_eq!(
+            emit_error("print 2 * 1.5;"),
+            ("Floats are not supported by the LLVM backend".to_string(), 1, 1)
+        );
+        assert_eq!(
+            emit_error("print 1 + \"a\";"),
+            ("Invalid operand types for '+': number and string".to_string(), 1, 9)
+        );
+        assert_eq!(
+            emit_error("print true and 1;"),
+            ("Operands of 'and' must be bools, got number".to_string(), 1, 12)
+        );
+        assert_eq!(
+            emit_error("let x = 1;\nx = \"a\";"),
+            ("Can't assign a string to 'x', which holds a number".to_string(), 2, 1)
+        );
+        assert_eq!(
+            emit_error("fn f() {\n  return fn() {};\n}\nf();"),
+            ("Anonymous functions are not supported by the LLVM backend".to_string(), 2, 3)
+        );
+        assert_eq!(
+            emit_error("fn f() {}\nlet g = f;"),
+            ("Function values are not supported by the LLVM backend".to_string(), 2, 9)
+        );
+        assert_eq!(
+            emit_error("fn f(a) {}\nf(1, 2);"),
+            ("Expected 1 arguments but got 2".to_string(), 2, 2)
+        );
+        assert_eq!(
+            emit_error("fn f(a) { if (a) return 1; return \"one\"; }\nf(true);"),
+            ("Function can't return both number and string".to_string(), 1, 28)
+        );
+    }
+}
@@ -0,0 +1,37 @@
+//! Ahead-of-time backends that translate a resolved program into source code
+//! for another toolchain. Each backend supports the subset of the language it
+//! can give a static representation, and reports anything else as a
+//! [`CodegenError`] at compile time.
+
+use std::fmt;
+
+pub mod c;
+pub mod llvm;
+mod typed;
+pub mod wat;
+pub mod x86_64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodegenError {
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl CodegenError {
+    fn new(message: String, line: u32, column: u32) -> Self {
+        CodegenError {
+            message,
+            line,
+            column,
+        }
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for CodegenError {}
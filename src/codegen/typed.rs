@@ -0,0 +1,806 @@
+//! The static typing shared by the backends that give every value a type
+//! known at compile time (`llvm` and `wat`). A [`Target`] only emits
+//! instructions; this module walks the program, checks the types and decides
+//! what to emit:
+//!
+//! - top-level `let`s become globals and must keep the type they were first
+//!   declared with; other `let`s become locals;
+//! - functions must be declared at the top level and may only be called by
+//!   name. They are instantiated once per combination of argument types at
+//!   their call sites, and their return type is the type of their `return`
+//!   statements;
+//! - floats, closures, anonymous functions and function values are not
+//!   supported.
+//!
+//! Operations whose operand types don't match are compile errors carrying the
+//! interpreter's runtime message.
+
+use std::{collections::HashMap, mem, rc::Rc};
+
+use super::CodegenError;
+use crate::{
+    BinaryOp, Expr, Function, InterpolationPart, Program, Stmt, Token, UnaryOp, always_returns,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int,
+    Bool,
+    Str,
+    /// The result of calling a function that returns nothing.
+    Nil,
+}
+
+impl Type {
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::Int => "number",
+            Type::Bool => "bool",
+            Type::Str => "string",
+            Type::Nil => "nil",
+        }
+    }
+}
+
+/// A value computed by the emitted code, with what the target needs to refer
+/// to it.
+pub struct Operand<V> {
+    pub ty: Type,
+    pub value: V,
+}
+
+impl<V> Operand<V> {
+    pub fn new(ty: Type, value: V) -> Self {
+        Operand { ty, value }
+    }
+}
+
+/// Where a variable lives: the slot a target returned for a local, or the
+/// source name of a global.
+pub enum Variable {
+    Local(String),
+    Global(String),
+}
+
+/// Instruction emission for a statically typed backend. Values of type nil
+/// are never stored, so targets only see them as results of calls to
+/// functions that return nothing, and as operands of `print`, `==`, `!=`,
+/// conditions and interpolation.
+pub trait Target {
+    /// How the backend is called in errors about what it doesn't support.
+    const NAME: &'static str;
+
+    /// Refers to a value the emitted code computed.
+    type Value;
+    /// The function being emitted.
+    type FunctionState;
+    /// An `if` being emitted.
+    type Branch;
+    /// A `while` or `for` loop being emitted.
+    type Loop;
+    /// An `and` or `or` whose right operand is being emitted.
+    type Logical;
+
+    fn number(&mut self, n: i64) -> Self::Value;
+    fn string(&mut self, s: &str) -> Self::Value;
+    fn boolean(&mut self, b: bool) -> Self::Value;
+    fn nil(&mut self) -> Self::Value;
+
+    fn negate(&mut self, value: Self::Value, line: u32, column: u32) -> Self::Value;
+    fn not(&mut self, value: Self::Value) -> Self::Value;
+    /// `+`, `-`, `*` or `/` on two numbers.
+    fn arithmetic(
+        &mut self,
+        operator: &BinaryOp,
+        left: Self::Value,
+        right: Self::Value,
+        line: u32,
+        column: u32,
+    ) -> Self::Value;
+    /// `<`, `<=`, `>` or `>=` on two numbers.
+    fn compare(
+        &mut self,
+        operator: &BinaryOp,
+        left: Self::Value,
+        right: Self::Value,
+    ) -> Self::Value;
+    /// `==`, or `!=` when `equal` is false, on operands of any types.
+    fn equality(
+        &mut self,
+        left: Operand<Self::Value>,
+        right: Operand<Self::Value>,
+        equal: bool,
+    ) -> Self::Value;
+    fn concat(&mut self, left: Self::Value, right: Self::Value) -> Self::Value;
+    /// The string `print` would show for `value`.
+    fn stringify(&mut self, value: Operand<Self::Value>) -> Self::Value;
+
+    /// Starts the right operand of `and`, or of `or` when `is_and` is false,
+    /// which only runs when it decides the result.
+    fn start_logical(&mut self, is_and: bool, left: Self::Value) -> Self::Logical;
+    fn end_logical(&mut self, logical: Self::Logical, right: Self::Value) -> Self::Value;
+
+    fn load(&mut self, variable: &Variable, ty: Type) -> Self::Value;
+    /// Assigns `value` to `variable`, returning the value again.
+    fn store(&mut self, variable: &Variable, value: Operand<Self::Value>) -> Self::Value;
+    fn define_global(&mut self, name: &str, value: Operand<Self::Value>);
+    /// Stores `value` in a new local, returning its slot.
+    fn define_local(&mut self, name: &str, value: Operand<Self::Value>) -> String;
+
+    fn call(
+        &mut self,
+        symbol: &str,
+        arguments: Vec<Operand<Self::Value>>,
+        return_type: Type,
+        line: u32,
+        column: u32,
+    ) -> Self::Value;
+
+    fn print(&mut self, value: Operand<Self::Value>);
+    /// Throws away the value of an expression statement.
+    fn discard(&mut self, value: Operand<Self::Value>);
+    /// Converts `value` to a bool using the interpreter's truthiness rules.
+    fn condition(&mut self, value: Operand<Self::Value>) -> Self::Value;
+
+    fn start_if(&mut self, condition: Self::Value) -> Self::Branch;
+    fn start_else(&mut self, branch: &mut Self::Branch);
+    fn end_if(&mut self, branch: Self::Branch);
+
+    /// Starts a loop, before its condition. A `for` loop's `continue` runs
+    /// its increment.
+    fn start_loop(&mut self, is_for: bool) -> Self::Loop;
+    /// Leaves the loop unless `condition` holds, where `None` always holds.
+    fn loop_condition(&mut self, frame: &Self::Loop, condition: Option<Self::Value>);
+    /// Ends the body of a `for` loop, before its increment.
+    fn start_increment(&mut self, frame: &Self::Loop);
+    fn end_loop(&mut self, frame: Self::Loop);
+    fn break_loop(&mut self, frame: &Self::Loop);
+    fn continue_loop(&mut self, frame: &Self::Loop);
+
+    /// Starts emitting a function, returning the state of the one it
+    /// interrupts.
+    fn start_function(&mut self) -> Self::FunctionState;
+    /// Declares the parameter at `index`, returning its slot.
+    fn parameter(&mut self, index: usize, name: &str, ty: Type) -> String;
+    fn return_value(&mut self, value: Operand<Self::Value>);
+    /// Ends the body of a function returning `return_type`. `fall_through`
+    /// carries the runtime error to report, and its position, when the body
+    /// may run off its end without a `return` giving it a value.
+    fn end_body(&mut self, return_type: Type, fall_through: Option<(String, u32, u32)>);
+    /// Adds the finished function to the output and goes back to emitting
+    /// `saved`.
+    fn finish_function(
+        &mut self,
+        saved: Self::FunctionState,
+        symbol: &str,
+        parameters: &[Type],
+        return_type: Type,
+    );
+}
+
+struct Local {
+    slot: String,
+    ty: Type,
+}
+
+enum Global {
+    Variable(Type),
+    Function(Rc<Function>),
+}
+
+/// One monomorphic copy of a source function.
+struct Instance {
+    name: String,
+    symbol: String,
+    // Unknown until the first `return` has been compiled.
+    return_type: Option<Type>,
+}
+
+struct Generator<'t, T: Target> {
+    target: &'t mut T,
+    globals: HashMap<String, Global>,
+    // Every global emitted for a variable, in declaration order.
+    global_types: Vec<(String, Type)>,
+    instances: Vec<Instance>,
+    instance_keys: HashMap<(*const Function, Vec<Type>), usize>,
+    // The instance being emitted, or `None` for the top level.
+    instance: Option<usize>,
+    scopes: Vec<HashMap<String, Local>>,
+    loops: Vec<T::Loop>,
+    line: u32,
+    column: u32,
+}
+
+/// Emits the top level of `program` into the target's current function, and
+/// every function it calls as separate definitions. Returns the global
+/// variables to declare, in declaration order.
+pub fn lower<T: Target>(
+    program: &Program,
+    target: &mut T,
+) -> Result<Vec<(String, Type)>, CodegenError> {
+    let mut generator = Generator {
+        target,
+        globals: HashMap::new(),
+        global_types: Vec::new(),
+        instances: Vec::new(),
+        instance_keys: HashMap::new(),
+        instance: None,
+        scopes: Vec::new(),
+        loops: Vec::new(),
+        line: 1,
+        column: 1,
+    };
+    for statement in &program.statements {
+        generator.statement(statement)?;
+    }
+    Ok(generator.global_types)
+}
+
+impl<T: Target> Generator<'_, T> {
+    fn error(&self, message: String, line: u32, column: u32) -> CodegenError {
+        CodegenError::new(message, line, column)
+    }
+
+    fn unsupported(&self, what: &str) -> CodegenError {
+        self.error(
+            format!("{what} are not supported by the {} backend", T::NAME),
+            self.line,
+            self.column,
+        )
+    }
+
+    fn set_position(&mut self, line: u32, column: u32) {
+        self.line = line;
+        self.column = column;
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), CodegenError> {
+        match statement {
+            Stmt::Expression(expr) => {
+                let value = self.expression(expr)?;
+                self.target.discard(value);
+            }
+            Stmt::Print {
+                value,
+                line,
+                column,
+            } => {
+                self.set_position(*line, *column);
+                let value = self.expression(value)?;
+                self.target.print(value);
+            }
+            Stmt::Let {
+                name, initializer, ..
+            } => {
+                self.set_position(name.line, name.column);
+                let value = self.expression(initializer)?;
+                if value.ty == Type::Nil {
+                    return Err(self.unsupported("Variables holding nil"));
+                }
+                self.declare(name, value)?;
+            }
+            Stmt::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.scopes.pop();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.condition(condition)?;
+                let mut branch = self.target.start_if(condition);
+                self.statement(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.target.start_else(&mut branch);
+                    self.statement(else_branch)?;
+                }
+                self.target.end_if(branch);
+            }
+            Stmt::While { condition, body } => {
+                let frame = self.target.start_loop(false);
+                let condition = self.condition(condition)?;
+                self.target.loop_condition(&frame, Some(condition));
+                let frame = self.loop_body(body, frame)?;
+                self.target.end_loop(frame);
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.scopes.push(HashMap::new());
+                if let Some(initializer) = initializer {
+                    self.statement(initializer)?;
+                }
+
+                let frame = self.target.start_loop(true);
+                let condition = match condition {
+                    Some(condition) => Some(self.condition(condition)?),
+                    None => None,
+                };
+                self.target.loop_condition(&frame, condition);
+                let frame = self.loop_body(body, frame)?;
+                self.target.start_increment(&frame);
+                if let Some(increment) = increment {
+                    let value = self.expression(increment)?;
+                    self.target.discard(value);
+                }
+                self.target.end_loop(frame);
+                self.scopes.pop();
+            }
+            Stmt::Break { .. } => self.target.break_loop(self.loops.last().unwrap()),
+            Stmt::Continue { .. } => self.target.continue_loop(self.loops.last().unwrap()),
+            Stmt::Function(function) => {
+                let name = function.name.as_ref().unwrap();
+                self.set_position(name.line, name.column);
+                if self.instance.is_some() || !self.scopes.is_empty() {
+                    return Err(self.unsupported("Functions declared outside the top level"));
+                }
+                self.globals
+                    .insert(name.lexeme.clone(), Global::Function(Rc::clone(function)));
+            }
+            Stmt::Return {
+                value,
+                line,
+                column,
+            } => {
+                self.set_position(*line, *column);
+                let value = match value {
+                    Some(value) => self.expression(value)?,
+                    None => Operand::new(Type::Nil, self.target.nil()),
+                };
+
+                let instance = &mut self.instances[self.instance.unwrap()];
+                match instance.return_type {
+                    None => instance.return_type = Some(value.ty),
+                    Some(ty) if ty != value.ty => {
+                        return Err(self.error(
+                            format!(
+                                "Function can't return both {} and {}",
+                                ty.name(),
+                                value.ty.name()
+                            ),
+                            *line,
+                            *column,
+                        ));
+                    }
+                    Some(_) => {}
+                }
+                self.target.return_value(value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits `body` with `frame` as the target of its `break`s and
+    /// `continue`s, handing the frame back.
+    fn loop_body(&mut self, body: &Stmt, frame: T::Loop) -> Result<T::Loop, CodegenError> {
+        self.loops.push(frame);
+        let result = self.statement(body);
+        let frame = self.loops.pop().unwrap();
+        result.map(|()| frame)
+    }
+
+    fn declare(&mut self, name: &Token, value: Operand<T::Value>) -> Result<(), CodegenError> {
+        let ty = value.ty;
+
+        if self.instance.is_none() && self.scopes.is_empty() {
+            match self.global_types.iter().find(|(n, _)| *n == name.lexeme) {
+                Some((_, existing)) if *existing != ty => {
+                    return Err(self.error(
+                        format!(
+                            "Global '{}' was declared as a {} and can't become a {}",
+                            name.lexeme,
+                            existing.name(),
+                            ty.name()
+                        ),
+                        name.line,
+                        name.column,
+                    ));
+                }
+                Some(_) => {}
+                None => self.global_types.push((name.lexeme.clone(), ty)),
+            }
+            self.globals
+                .insert(name.lexeme.clone(), Global::Variable(ty));
+            self.target.define_global(&name.lexeme, value);
+            return Ok(());
+        }
+
+        let slot = self.target.define_local(&name.lexeme, value);
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.lexeme.clone(), Local { slot, ty });
+        Ok(())
+    }
+
+    fn condition(&mut self, expr: &Expr) -> Result<T::Value, CodegenError> {
+        let value = self.expression(expr)?;
+        Ok(self.target.condition(value))
+    }
+
+    /// Finds where the variable `name` lives, along with its type.
+    fn variable(&self, name: &Token, is_local: bool) -> Result<(Variable, Type), CodegenError> {
+        if is_local {
+            return match self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(&name.lexeme))
+            {
+                Some(local) => Ok((Variable::Local(local.slot.clone()), local.ty)),
+                None => Err(self.error(
+                    format!("Closures are not supported by the {} backend", T::NAME),
+                    name.line,
+                    name.column,
+                )),
+            };
+        }
+
+        match self.globals.get(&name.lexeme) {
+            Some(Global::Variable(ty)) => Ok((Variable::Global(name.lexeme.clone()), *ty)),
+            Some(Global::Function(_)) => Err(self.error(
+                format!(
+                    "Function values are not supported by the {} backend",
+                    T::NAME
+                ),
+                name.line,
+                name.column,
+            )),
+            None => Err(self.error(
+                format!("Undefined variable '{}'", name.lexeme),
+                name.line,
+                name.column,
+            )),
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<Operand<T::Value>, CodegenError> {
+        match expr {
+            Expr::Number(n) => Ok(Operand::new(Type::Int, self.target.number(*n))),
+            Expr::Float(_) => Err(self.unsupported("Floats")),
+            Expr::String(s) => Ok(Operand::new(Type::Str, self.target.string(s))),
+            Expr::Bool(b) => Ok(Operand::new(Type::Bool, self.target.boolean(*b))),
+            Expr::Grouping(inner) => self.expression(inner),
+            Expr::Unary {
+                operator,
+                operand,
+                line,
+                column,
+            } => {
+                let value = self.expression(operand)?;
+                match (operator, value.ty) {
+                    (UnaryOp::Negate, Type::Int) => Ok(Operand::new(
+                        Type::Int,
+                        self.target.negate(value.value, *line, *column),
+                    )),
+                    (UnaryOp::Not, Type::Bool) => {
+                        Ok(Operand::new(Type::Bool, self.target.not(value.value)))
+                    }
+                    (UnaryOp::Negate, ty) => Err(self.error(
+                        format!("Operand of '-' must be a number, got {}", ty.name()),
+                        *line,
+                        *column,
+                    )),
+                    (UnaryOp::Not, ty) => Err(self.error(
+                        format!("Operand of '!' must be a bool, got {}", ty.name()),
+                        *line,
+                        *column,
+                    )),
+                }
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                line,
+                column,
+            } => self.binary(left, operator, right, *line, *column),
+            Expr::Variable { name, depth } => {
+                let (variable, ty) = self.variable(name, depth.get().is_some())?;
+                Ok(Operand::new(ty, self.target.load(&variable, ty)))
+            }
+            Expr::Assign { name, value, depth } => {
+                let value = self.expression(value)?;
+                let (variable, ty) = self.variable(name, depth.get().is_some())?;
+                if value.ty != ty {
+                    return Err(self.error(
+                        format!(
+                            "Can't assign a {} to '{}', which holds a {}",
+                            value.ty.name(),
+                            name.lexeme,
+                            ty.name()
+                        ),
+                        name.line,
+                        name.column,
+                    ));
+                }
+                Ok(Operand::new(ty, self.target.store(&variable, value)))
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                line,
+                column,
+            } => self.call(callee, arguments, *line, *column),
+            Expr::Function(_) => Err(self.unsupported("Anonymous functions")),
+            Expr::Interpolation(parts) => {
+                let mut result = None;
+                for part in parts {
+                    let text = match part {
+                        InterpolationPart::Literal(text) => self.target.string(text),
+                        InterpolationPart::Expr(expr) => {
+                            let value = self.expression(expr)?;
+                            self.target.stringify(value)
+                        }
+                    };
+                    result = Some(match result {
+                        Some(left) => self.target.concat(left, text),
+                        None => text,
+                    });
+                }
+                Ok(Operand::new(Type::Str, result.unwrap()))
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        left: &Expr,
+        operator: &BinaryOp,
+        right: &Expr,
+        line: u32,
+        column: u32,
+    ) -> Result<Operand<T::Value>, CodegenError> {
+        if matches!(operator, BinaryOp::And | BinaryOp::Or) {
+            return self.logical(left, operator, right, line, column);
+        }
+
+        let left = self.expression(left)?;
+        let right = self.expression(right)?;
+
+        let (ty, value) = match (operator, left.ty, right.ty) {
+            (BinaryOp::Equal | BinaryOp::NotEqual, ..) => {
+                let equal = matches!(operator, BinaryOp::Equal);
+                (Type::Bool, self.target.equality(left, right, equal))
+            }
+            (BinaryOp::Add, Type::Str, Type::Str) => {
+                (Type::Str, self.target.concat(left.value, right.value))
+            }
+            (
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div,
+                Type::Int,
+                Type::Int,
+            ) => (
+                Type::Int,
+                self.target
+                    .arithmetic(operator, left.value, right.value, line, column),
+            ),
+            (
+                BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual,
+                Type::Int,
+                Type::Int,
+            ) => (
+                Type::Bool,
+                self.target.compare(operator, left.value, right.value),
+            ),
+            (_, l, r) => {
+                return Err(self.error(
+                    format!(
+                        "Invalid operand types for '{}': {} and {}",
+                        operator.symbol(),
+                        l.name(),
+                        r.name()
+                    ),
+                    line,
+                    column,
+                ));
+            }
+        };
+        Ok(Operand::new(ty, value))
+    }
+
+    /// `and` and `or` only evaluate their right operand when it decides the
+    /// result, and both operands must be bools.
+    fn logical(
+        &mut self,
+        left: &Expr,
+        operator: &BinaryOp,
+        right: &Expr,
+        line: u32,
+        column: u32,
+    ) -> Result<Operand<T::Value>, CodegenError> {
+        let check = |generator: &Self, ty: Type| {
+            if ty == Type::Bool {
+                return Ok(());
+            }
+            Err(generator.error(
+                format!(
+                    "Operands of '{}' must be bools, got {}",
+                    operator.symbol(),
+                    ty.name()
+                ),
+                line,
+                column,
+            ))
+        };
+
+        let left = self.expression(left)?;
+        check(self, left.ty)?;
+        let is_and = matches!(operator, BinaryOp::And);
+        let logical = self.target.start_logical(is_and, left.value);
+        let right = self.expression(right)?;
+        check(self, right.ty)?;
+        Ok(Operand::new(
+            Type::Bool,
+            self.target.end_logical(logical, right.value),
+        ))
+    }
+
+    fn call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        line: u32,
+        column: u32,
+    ) -> Result<Operand<T::Value>, CodegenError> {
+        let function = match callee {
+            Expr::Variable { name, depth } if depth.get().is_none() => {
+                match self.globals.get(&name.lexeme) {
+                    Some(Global::Function(function)) => Rc::clone(function),
+                    Some(Global::Variable(ty)) => {
+                        return Err(self.error(
+                            format!("Can only call functions, got {}", ty.name()),
+                            line,
+                            column,
+                        ));
+                    }
+                    None => {
+                        return Err(self.error(
+                            format!("Undefined variable '{}'", name.lexeme),
+                            name.line,
+                            name.column,
+                        ));
+                    }
+                }
+            }
+            _ => {
+                self.set_position(line, column);
+                return Err(self.unsupported("Calls to function values"));
+            }
+        };
+
+        if arguments.len() != function.params.len() {
+            return Err(self.error(
+                format!(
+                    "Expected {} arguments but got {}",
+                    function.params.len(),
+                    arguments.len()
+                ),
+                line,
+                column,
+            ));
+        }
+
+        let mut values = Vec::new();
+        for argument in arguments {
+            values.push(self.expression(argument)?);
+        }
+        self.set_position(line, column);
+        if values.iter().any(|value| value.ty == Type::Nil) {
+            return Err(self.unsupported("Nil arguments"));
+        }
+
+        let types: Vec<Type> = values.iter().map(|value| value.ty).collect();
+        let index = self.instance(&function, types)?;
+        let Some(return_type) = self.instances[index].return_type else {
+            return Err(self.error(
+                "Can't infer the return type of a recursive call before the function's first \
+                 'return'"
+                    .to_string(),
+                line,
+                column,
+            ));
+        };
+
+        let symbol = &self.instances[index].symbol;
+        let value = self.target.call(symbol, values, return_type, line, column);
+        Ok(Operand::new(return_type, value))
+    }
+
+    /// Returns the instance of `function` for these argument types,
+    /// compiling it first if needed.
+    fn instance(
+        &mut self,
+        function: &Rc<Function>,
+        arguments: Vec<Type>,
+    ) -> Result<usize, CodegenError> {
+        let key = (Rc::as_ptr(function), arguments);
+        if let Some(index) = self.instance_keys.get(&key) {
+            return Ok(*index);
+        }
+
+        let name = function.name.as_ref().unwrap().lexeme.clone();
+        let symbol = match self.instances.iter().filter(|i| i.name == name).count() {
+            0 => format!("fn.{name}"),
+            n => format!("fn.{name}.{n}"),
+        };
+
+        let index = self.instances.len();
+        self.instances.push(Instance {
+            name,
+            symbol,
+            return_type: None,
+        });
+        self.instance_keys.insert(key.clone(), index);
+
+        let saved_state = self.target.start_function();
+        let saved = (
+            mem::take(&mut self.scopes),
+            mem::take(&mut self.loops),
+            self.instance.replace(index),
+            self.line,
+            self.column,
+        );
+        let result = self.function_body(function, &key.1);
+        (
+            self.scopes,
+            self.loops,
+            self.instance,
+            self.line,
+            self.column,
+        ) = saved;
+        result?;
+
+        let instance = &self.instances[index];
+        self.target.finish_function(
+            saved_state,
+            &instance.symbol,
+            &key.1,
+            instance.return_type.unwrap(),
+        );
+        Ok(index)
+    }
+
+    fn function_body(
+        &mut self,
+        function: &Function,
+        arguments: &[Type],
+    ) -> Result<(), CodegenError> {
+        let mut scope = HashMap::new();
+        for (index, (param, ty)) in function.params.iter().zip(arguments).enumerate() {
+            let slot = self.target.parameter(index, &param.lexeme, *ty);
+            scope.insert(param.lexeme.clone(), Local { slot, ty: *ty });
+        }
+        self.scopes.push(scope);
+        for statement in &function.body {
+            self.statement(statement)?;
+        }
+
+        let name = function.name.as_ref().unwrap();
+        let instance = &mut self.instances[self.instance.unwrap()];
+        let return_type = *instance.return_type.get_or_insert(Type::Nil);
+        // A `return` inside a loop may be all that stops the body from
+        // running off its end, where the interpreter would return nil.
+        let fall_through =
+            (return_type != Type::Nil && !always_returns(&function.body)).then(|| {
+                (
+                    format!(
+                        "Function '{}' ended without returning a {}",
+                        name.lexeme,
+                        return_type.name()
+                    ),
+                    name.line,
+                    name.column,
+                )
+            });
+        self.target.end_body(return_type, fall_through);
+        Ok(())
+    }
+}
@@ -0,0 +1,1471 @@
+//! Lowers a resolved program to a WebAssembly text module (`.wat`) for
+//! browsers and other JavaScript hosts.
+//!
+//! Numbers are `i64`s, and bools and strings `i32`s: strings are addresses in
+//! linear memory of a 4-byte length followed by that many bytes. Literals
+//! live in data segments, and concatenation allocates from a bump allocator
+//! that never frees. Which programs compile is decided by the static typing
+//! in [`super::typed`], shared with the LLVM backend.
+//!
+//! The module exports its `memory` and a `main` function running the
+//! program, and imports these functions from the host's `env` module:
+//!
+//! - `print_number(n)` prints a number, which JavaScript hosts receive as a
+//!   `BigInt`, on its own line;
+//! - `print_string(address, length)` prints that many UTF-8 bytes of memory
+//!   on their own line;
+//! - `error(line, column, address, length)` reports a runtime error and
+//!   should not return, by throwing for example.
+
+use std::{fmt::Write, mem};
+
+use super::{
+    CodegenError,
+    typed::{self, Operand, Target, Type, Variable},
+};
+use crate::{BinaryOp, Program};
+
+/// The bytes before this address are never used, so a string global read
+/// before it is assigned is empty.
+const DATA_START: u32 = 8;
+
+fn wasm_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Int => "i64",
+        _ => "i32",
+    }
+}
+
+struct Loop {
+    is_for: bool,
+    break_label: String,
+    loop_label: String,
+    // The inner block a `for` loop's `continue` leaves to run the increment,
+    // or the loop itself for `while`.
+    continue_label: String,
+}
+
+/// The function currently being emitted.
+struct FunctionState {
+    params: Vec<(String, Type)>,
+    locals: Vec<(String, Type)>,
+    body: String,
+    // Nesting of the blocks being emitted, for indentation.
+    depth: usize,
+    labels: usize,
+}
+
+impl FunctionState {
+    fn new() -> Self {
+        FunctionState {
+            params: Vec::new(),
+            locals: Vec::new(),
+            body: String::new(),
+            depth: 0,
+            labels: 0,
+        }
+    }
+}
+
+struct Generator {
+    state: FunctionState,
+    // Interned strings and their addresses.
+    strings: Vec<(String, u32)>,
+    data_end: u32,
+    definitions: Vec<String>,
+}
+
+/// Emits a complete module whose exported `main` runs `program`.
+pub fn emit(program: &Program) -> Result<String, CodegenError> {
+    let mut generator = Generator {
+        state: FunctionState::new(),
+        strings: Vec::new(),
+        data_end: DATA_START,
+        definitions: Vec::new(),
+    };
+
+    let runtime = generator.runtime();
+    let globals = typed::lower(program, &mut generator)?;
+
+    Ok(generator.finish(runtime, globals))
+}
+
+impl Generator {
+    fn finish(self, runtime: String, global_types: Vec<(String, Type)>) -> String {
+        let mut out = String::new();
+        out.push_str(";; Generated by lang\n(module\n");
+        out.push_str(IMPORTS);
+        out.push('\n');
+        writeln!(out, "  (memory (export \"memory\") 1)").unwrap();
+        for (s, address) in &self.strings {
+            let mut bytes = (s.len() as u32).to_le_bytes().to_vec();
+            bytes.extend_from_slice(s.as_bytes());
+            writeln!(out, "  (data (i32.const {address}) \"{}\")", escape(&bytes)).unwrap();
+        }
+        out.push('\n');
+
+        let heap = self.data_end.next_multiple_of(8);
+        writeln!(out, "  (global $lang_heap (mut i32) (i32.const {heap}))").unwrap();
+        writeln!(out, "  (global $lang_depth (mut i32) (i32.const 0))").unwrap();
+        for (name, ty) in global_types {
+            let ty = wasm_type(ty);
+            writeln!(out, "  (global $g.{name} (mut {ty}) ({ty}.const 0))").unwrap();
+        }
+
+        out.push_str(&runtime);
+        for definition in &self.definitions {
+            out.push('\n');
+            out.push_str(definition);
+        }
+        out.push('\n');
+        out.push_str(&function_text("$main (export \"main\")", &self.state, None));
+        out.push_str(")\n");
+        out
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        let indent = 4 + self.state.depth * 2;
+        writeln!(self.state.body, "{:indent$}{instruction}", "").unwrap();
+    }
+
+    /// Emits `block`, `loop` or `if`, indenting what follows until `end`.
+    fn open(&mut self, instruction: &str) {
+        self.emit(instruction);
+        self.state.depth += 1;
+    }
+
+    fn otherwise(&mut self) {
+        self.state.depth -= 1;
+        self.emit("else");
+        self.state.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.state.depth -= 1;
+        self.emit("end");
+    }
+
+    fn new_label(&mut self, prefix: &str) -> String {
+        self.state.labels += 1;
+        format!("${prefix}.{}", self.state.labels)
+    }
+
+    /// Returns the address of `s` in memory, adding it to the data segments
+    /// if needed.
+    fn string_constant(&mut self, s: &str) -> u32 {
+        if let Some((_, address)) = self.strings.iter().find(|(existing, _)| existing == s) {
+            return *address;
+        }
+        let address = self.data_end;
+        self.data_end = (address + 4 + s.len() as u32).next_multiple_of(4);
+        self.strings.push((s.to_string(), address));
+        address
+    }
+
+    /// Printing, strings and runtime errors on top of the host imports.
+    fn runtime(&mut self) -> String {
+        let true_ = self.string_constant("true");
+        let false_ = self.string_constant("false");
+        let nil = self.string_constant("nil");
+        let division = self.string_constant("Division by zero");
+        let overflow = self.string_constant("Stack overflow");
+        let integer_overflow = self.string_constant("Integer overflow");
+        RUNTIME
+            .replace("{true}", &true_.to_string())
+            .replace("{false}", &false_.to_string())
+            .replace("{nil}", &nil.to_string())
+            .replace("{division}", &division.to_string())
+            .replace("{overflow}", &overflow.to_string())
+            .replace("{integer_overflow}", &integer_overflow.to_string())
+    }
+}
+
+/// Values live on the operand stack, so every method emits code pushing its
+/// result, or nothing for nil.
+impl Target for Generator {
+    const NAME: &'static str = "WebAssembly";
+
+    type Value = ();
+    type FunctionState = FunctionState;
+    type Branch = ();
+    type Loop = Loop;
+    type Logical = bool;
+
+    fn number(&mut self, n: i64) {
+        self.emit(&format!("i64.const {n}"));
+    }
+
+    fn string(&mut self, s: &str) {
+        let address = self.string_constant(s);
+        self.emit(&format!("i32.const {address}"));
+    }
+
+    fn boolean(&mut self, b: bool) {
+        self.emit(&format!("i32.const {}", b as u8));
+    }
+
+    fn nil(&mut self) {}
+
+    fn negate(&mut self, _value: (), line: u32, column: u32) {
+        self.emit("i64.const -1");
+        self.emit(&format!("i32.const {line}"));
+        self.emit(&format!("i32.const {column}"));
+        self.emit("call $lang_multiply");
+    }
+
+    fn not(&mut self, _value: ()) {
+        self.emit("i32.eqz");
+    }
+
+    fn arithmetic(&mut self, operator: &BinaryOp, _left: (), _right: (), line: u32, column: u32) {
+        self.emit(&format!("i32.const {line}"));
+        self.emit(&format!("i32.const {column}"));
+        self.emit(match operator {
+            BinaryOp::Add => "call $lang_add",
+            BinaryOp::Sub => "call $lang_subtract",
+            BinaryOp::Mul => "call $lang_multiply",
+            _ => "call $lang_divide",
+        });
+    }
+
+    fn compare(&mut self, operator: &BinaryOp, _left: (), _right: ()) {
+        self.emit(match operator {
+            BinaryOp::Less => "i64.lt_s",
+            BinaryOp::LessEqual => "i64.le_s",
+            BinaryOp::Greater => "i64.gt_s",
+            _ => "i64.ge_s",
+        });
+    }
+
+    fn equality(&mut self, left: Operand<()>, right: Operand<()>, equal: bool) {
+        let (l, r) = (left.ty, right.ty);
+        if l != r || l == Type::Nil {
+            // Values of different types are never equal, and nil always
+            // equals nil.
+            for ty in [l, r] {
+                if ty != Type::Nil {
+                    self.emit("drop");
+                }
+            }
+            self.emit(&format!("i32.const {}", ((l == r) == equal) as u8));
+            return;
+        }
+        match (l, equal) {
+            (Type::Str, true) => self.emit("call $lang_string_equal"),
+            (Type::Str, false) => {
+                self.emit("call $lang_string_equal");
+                self.emit("i32.eqz");
+            }
+            (Type::Int, true) => self.emit("i64.eq"),
+            (Type::Int, false) => self.emit("i64.ne"),
+            (_, true) => self.emit("i32.eq"),
+            (_, false) => self.emit("i32.ne"),
+        }
+    }
+
+    fn concat(&mut self, _left: (), _right: ()) {
+        self.emit("call $lang_concat");
+    }
+
+    fn stringify(&mut self, value: Operand<()>) {
+        match value.ty {
+            Type::Int => self.emit("call $lang_number_to_string"),
+            Type::Bool => self.emit("call $lang_bool_to_string"),
+            Type::Str => {}
+            Type::Nil => self.string("nil"),
+        }
+    }
+
+    fn start_logical(&mut self, is_and: bool, _left: ()) -> bool {
+        self.open("if (result i32)");
+        if !is_and {
+            self.emit("i32.const 1");
+            self.otherwise();
+        }
+        is_and
+    }
+
+    fn end_logical(&mut self, is_and: bool, _right: ()) {
+        if is_and {
+            self.otherwise();
+            self.emit("i32.const 0");
+        }
+        self.close();
+    }
+
+    fn load(&mut self, variable: &Variable, _ty: Type) {
+        match variable {
+            Variable::Local(local) => self.emit(&format!("local.get {local}")),
+            Variable::Global(name) => self.emit(&format!("global.get $g.{name}")),
+        }
+    }
+
+    fn store(&mut self, variable: &Variable, _value: Operand<()>) {
+        match variable {
+            Variable::Local(local) => self.emit(&format!("local.tee {local}")),
+            Variable::Global(name) => {
+                self.emit(&format!("global.set $g.{name}"));
+                self.emit(&format!("global.get $g.{name}"));
+            }
+        }
+    }
+
+    fn define_global(&mut self, name: &str, _value: Operand<()>) {
+        self.emit(&format!("global.set $g.{name}"));
+    }
+
+    fn define_local(&mut self, name: &str, value: Operand<()>) -> String {
+        let local = format!(
+            "${name}.{}",
+            self.state.params.len() + self.state.locals.len() + 1
+        );
+        self.state.locals.push((local.clone(), value.ty));
+        self.emit(&format!("local.set {local}"));
+        local
+    }
+
+    fn call(
+        &mut self,
+        symbol: &str,
+        _arguments: Vec<Operand<()>>,
+        _return_type: Type,
+        line: u32,
+        column: u32,
+    ) {
+        self.emit(&format!("i32.const {line}"));
+        self.emit(&format!("i32.const {column}"));
+        self.emit("call $lang_enter");
+        self.emit(&format!("call ${symbol}"));
+        self.emit("call $lang_leave");
+    }
+
+    fn print(&mut self, value: Operand<()>) {
+        let printer = match value.ty {
+            Type::Int => "$print_number",
+            Type::Bool => "$lang_print_bool",
+            Type::Str => "$lang_print_string",
+            Type::Nil => "$lang_print_nil",
+        };
+        self.emit(&format!("call {printer}"));
+    }
+
+    fn discard(&mut self, value: Operand<()>) {
+        if value.ty != Type::Nil {
+            self.emit("drop");
+        }
+    }
+
+    /// Leaves 1 or 0 on the stack.
+    fn condition(&mut self, value: Operand<()>) {
+        match value.ty {
+            Type::Bool => {}
+            Type::Nil => self.emit("i32.const 0"),
+            Type::Int | Type::Str => {
+                self.emit("drop");
+                self.emit("i32.const 1");
+            }
+        }
+    }
+
+    fn start_if(&mut self, _condition: ()) {
+        self.open("if");
+    }
+
+    fn start_else(&mut self, _branch: &mut ()) {
+        self.otherwise();
+    }
+
+    fn end_if(&mut self, _branch: ()) {
+        self.close();
+    }
+
+    fn start_loop(&mut self, is_for: bool) -> Loop {
+        let break_label = self.new_label("break");
+        let frame = if is_for {
+            let loop_label = self.new_label("for");
+            let continue_label = self.new_label("continue");
+            Loop {
+                is_for,
+                break_label,
+                loop_label,
+                continue_label,
+            }
+        } else {
+            let continue_label = self.new_label("continue");
+            Loop {
+                is_for,
+                break_label,
+                loop_label: continue_label.clone(),
+                continue_label,
+            }
+        };
+        self.open(&format!("block {}", frame.break_label));
+        self.open(&format!("loop {}", frame.loop_label));
+        frame
+    }
+
+    fn loop_condition(&mut self, frame: &Loop, condition: Option<()>) {
+        if condition.is_some() {
+            self.emit("i32.eqz");
+            self.emit(&format!("br_if {}", frame.break_label));
+        }
+        if frame.is_for {
+            self.open(&format!("block {}", frame.continue_label));
+        }
+    }
+
+    fn start_increment(&mut self, _frame: &Loop) {
+        self.close();
+    }
+
+    fn end_loop(&mut self, frame: Loop) {
+        self.emit(&format!("br {}", frame.loop_label));
+        self.close();
+        self.close();
+    }
+
+    fn break_loop(&mut self, frame: &Loop) {
+        self.emit(&format!("br {}", frame.break_label));
+    }
+
+    fn continue_loop(&mut self, frame: &Loop) {
+        self.emit(&format!("br {}", frame.continue_label));
+    }
+
+    fn start_function(&mut self) -> FunctionState {
+        mem::replace(&mut self.state, FunctionState::new())
+    }
+
+    fn parameter(&mut self, _index: usize, name: &str, ty: Type) -> String {
+        let param = format!("${name}.{}", self.state.params.len() + 1);
+        self.state.params.push((param.clone(), ty));
+        param
+    }
+
+    fn return_value(&mut self, _value: Operand<()>) {
+        self.emit("return");
+    }
+
+    fn end_body(&mut self, return_type: Type, fall_through: Option<(String, u32, u32)>) {
+        if return_type == Type::Nil {
+            return;
+        }
+        if let Some((message, line, column)) = fall_through {
+            let message = self.string_constant(&message);
+            self.emit(&format!("i32.const {line}"));
+            self.emit(&format!("i32.const {column}"));
+            self.emit(&format!("i32.const {message}"));
+            self.emit("call $lang_error");
+        }
+        // Tells the validator the end is never reached.
+        self.emit("unreachable");
+    }
+
+    fn finish_function(
+        &mut self,
+        saved: FunctionState,
+        symbol: &str,
+        _parameters: &[Type],
+        return_type: Type,
+    ) {
+        let state = mem::replace(&mut self.state, saved);
+        self.definitions.push(function_text(
+            &format!("${symbol}"),
+            &state,
+            Some(return_type).filter(|ty| *ty != Type::Nil),
+        ));
+    }
+}
+
+/// Formats a function definition.
+fn function_text(name: &str, state: &FunctionState, result: Option<Type>) -> String {
+    let mut out = format!("  (func {name}");
+    for (param, ty) in &state.params {
+        write!(out, " (param {param} {})", wasm_type(*ty)).unwrap();
+    }
+    if let Some(result) = result {
+        write!(out, " (result {})", wasm_type(result)).unwrap();
+    }
+    out.push('\n');
+    for (local, ty) in &state.locals {
+        writeln!(out, "    (local {local} {})", wasm_type(*ty)).unwrap();
+    }
+    out.push_str(&state.body);
+    out.push_str("  )\n");
+    out
+}
+
+/// Escapes `bytes` for a WAT string.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        if (byte.is_ascii_graphic() || byte == b' ') && byte != b'"' && byte != b'\\' {
+            escaped.push(byte as char);
+        } else {
+            write!(escaped, "\\{byte:02x}").unwrap();
+        }
+    }
+    escaped
+}
+
+const IMPORTS: &str = r#"  (import "env" "print_number" (func $print_number (param i64)))
+  (import "env" "print_string" (func $print_string (param i32 i32)))
+  (import "env" "error" (func $error (param i32 i32 i32 i32)))
+"#;
+
+/// The `{...}` placeholders are replaced by addresses of the strings the
+/// runtime prints.
+const RUNTIME: &str = r#"
+  (func $lang_print_string (param $string i32)
+    local.get $string
+    i32.const 4
+    i32.add
+    local.get $string
+    i32.load
+    call $print_string
+  )
+
+  (func $lang_print_bool (param $bool i32)
+    i32.const {true}
+    i32.const {false}
+    local.get $bool
+    select
+    call $lang_print_string
+  )
+
+  (func $lang_bool_to_string (param $bool i32) (result i32)
+    i32.const {true}
+    i32.const {false}
+    local.get $bool
+    select
+  )
+
+  ;; Writes the digits of `n` into a new string, after a `-` if it's negative.
+  (func $lang_number_to_string (param $n i64) (result i32)
+    (local $length i32)
+    (local $rest i64)
+    (local $digit i64)
+    (local $string i32)
+    (local $end i32)
+    local.get $n
+    i64.const 0
+    i64.lt_s
+    local.set $length
+    local.get $n
+    local.set $rest
+    loop $count
+      local.get $length
+      i32.const 1
+      i32.add
+      local.set $length
+      local.get $rest
+      i64.const 10
+      i64.div_s
+      local.tee $rest
+      i64.const 0
+      i64.ne
+      br_if $count
+    end
+    local.get $length
+    i32.const 4
+    i32.add
+    call $lang_alloc
+    local.tee $string
+    local.get $length
+    i32.store
+    local.get $string
+    i32.const 4
+    i32.add
+    local.get $length
+    i32.add
+    local.set $end
+    local.get $n
+    local.set $rest
+    ;; Remainders of negative numbers are negative, so each digit is the
+    ;; remainder's absolute value.
+    loop $digits
+      local.get $end
+      i32.const 1
+      i32.sub
+      local.tee $end
+      local.get $rest
+      i64.const 10
+      i64.rem_s
+      local.set $digit
+      i64.const 0
+      local.get $digit
+      i64.sub
+      local.get $digit
+      local.get $digit
+      i64.const 0
+      i64.lt_s
+      select
+      i32.wrap_i64
+      i32.const 48
+      i32.add
+      i32.store8
+      local.get $rest
+      i64.const 10
+      i64.div_s
+      local.tee $rest
+      i64.const 0
+      i64.ne
+      br_if $digits
+    end
+    local.get $n
+    i64.const 0
+    i64.lt_s
+    if
+      local.get $string
+      i32.const 4
+      i32.add
+      i32.const 45
+      i32.store8
+    end
+    local.get $string
+  )
+
+  (func $lang_print_nil
+    i32.const {nil}
+    call $lang_print_string
+  )
+
+  (func $lang_error (param $line i32) (param $column i32) (param $message i32)
+    local.get $line
+    local.get $column
+    local.get $message
+    i32.const 4
+    i32.add
+    local.get $message
+    i32.load
+    call $error
+    unreachable
+  )
+
+  ;; Returns `size` bytes of fresh memory, growing it if needed.
+  (func $lang_alloc (param $size i32) (result i32)
+    (local $address i32)
+    global.get $lang_heap
+    local.set $address
+    global.get $lang_heap
+    local.get $size
+    i32.add
+    i32.const 3
+    i32.add
+    i32.const -4
+    i32.and
+    global.set $lang_heap
+    block $done
+      loop $grow
+        global.get $lang_heap
+        memory.size
+        i32.const 16
+        i32.shl
+        i32.le_u
+        br_if $done
+        i32.const 1
+        memory.grow
+        i32.const -1
+        i32.eq
+        if
+          unreachable
+        end
+        br $grow
+      end
+    end
+    local.get $address
+  )
+
+  (func $lang_copy (param $to i32) (param $from i32) (param $length i32)
+    block $done
+      loop $copy
+        local.get $length
+        i32.eqz
+        br_if $done
+        local.get $to
+        local.get $from
+        i32.load8_u
+        i32.store8
+        local.get $to
+        i32.const 1
+        i32.add
+        local.set $to
+        local.get $from
+        i32.const 1
+        i32.add
+        local.set $from
+        local.get $length
+        i32.const 1
+        i32.sub
+        local.set $length
+        br $copy
+      end
+    end
+  )
+
+  (func $lang_concat (param $a i32) (param $b i32) (result i32)
+    (local $length i32)
+    (local $result i32)
+    local.get $a
+    i32.load
+    local.get $b
+    i32.load
+    i32.add
+    local.set $length
+    local.get $length
+    i32.const 4
+    i32.add
+    call $lang_alloc
+    local.set $result
+    local.get $result
+    local.get $length
+    i32.store
+    local.get $result
+    i32.const 4
+    i32.add
+    local.get $a
+    i32.const 4
+    i32.add
+    local.get $a
+    i32.load
+    call $lang_copy
+    local.get $result
+    i32.const 4
+    i32.add
+    local.get $a
+    i32.load
+    i32.add
+    local.get $b
+    i32.const 4
+    i32.add
+    local.get $b
+    i32.load
+    call $lang_copy
+    local.get $result
+  )
+
+  (func $lang_string_equal (param $a i32) (param $b i32) (result i32)
+    (local $index i32)
+    local.get $a
+    i32.load
+    local.get $b
+    i32.load
+    i32.ne
+    if
+      i32.const 0
+      return
+    end
+    block $done
+      loop $compare
+        local.get $index
+        local.get $a
+        i32.load
+        i32.ge_u
+        br_if $done
+        local.get $a
+        local.get $index
+        i32.add
+        i32.load8_u offset=4
+        local.get $b
+        local.get $index
+        i32.add
+        i32.load8_u offset=4
+        i32.ne
+        if
+          i32.const 0
+          return
+        end
+        local.get $index
+        i32.const 1
+        i32.add
+        local.set $index
+        br $compare
+      end
+    end
+    i32.const 1
+  )
+
+  (func $lang_overflow (param $line i32) (param $column i32)
+    local.get $line
+    local.get $column
+    i32.const {integer_overflow}
+    call $lang_error
+  )
+
+  (func $lang_add (param $a i64) (param $b i64) (param $line i32) (param $column i32) (result i64)
+    (local $result i64)
+    local.get $a
+    local.get $b
+    i64.add
+    local.set $result
+    ;; The sum overflowed if its sign differs from both operands'.
+    local.get $a
+    local.get $result
+    i64.xor
+    local.get $b
+    local.get $result
+    i64.xor
+    i64.and
+    i64.const 0
+    i64.lt_s
+    if
+      local.get $line
+      local.get $column
+      call $lang_overflow
+    end
+    local.get $result
+  )
+
+  (func $lang_subtract (param $a i64) (param $b i64) (param $line i32) (param $column i32) (result i64)
+    (local $result i64)
+    local.get $a
+    local.get $b
+    i64.sub
+    local.set $result
+    ;; The difference overflowed if the operands' signs differ and its sign
+    ;; differs from `a`'s.
+    local.get $a
+    local.get $b
+    i64.xor
+    local.get $a
+    local.get $result
+    i64.xor
+    i64.and
+    i64.const 0
+    i64.lt_s
+    if
+      local.get $line
+      local.get $column
+      call $lang_overflow
+    end
+    local.get $result
+  )
+
+  (func $lang_multiply (param $a i64) (param $b i64) (param $line i32) (param $column i32) (result i64)
+    (local $result i64)
+    ;; -1 * i64::MIN overflows, and would trap in the division below.
+    local.get $a
+    i64.const -1
+    i64.eq
+    local.get $b
+    i64.const -9223372036854775808
+    i64.eq
+    i32.and
+    if
+      local.get $line
+      local.get $column
+      call $lang_overflow
+    end
+    local.get $a
+    local.get $b
+    i64.mul
+    local.set $result
+    ;; Otherwise the product overflowed if dividing it by `a` doesn't give
+    ;; back `b`.
+    local.get $a
+    i64.eqz
+    i32.eqz
+    if
+      local.get $result
+      local.get $a
+      i64.div_s
+      local.get $b
+      i64.ne
+      if
+        local.get $line
+        local.get $column
+        call $lang_overflow
+      end
+    end
+    local.get $result
+  )
+
+  (func $lang_divide (param $a i64) (param $b i64) (param $line i32) (param $column i32) (result i64)
+    local.get $b
+    i64.eqz
+    if
+      local.get $line
+      local.get $column
+      i32.const {division}
+      call $lang_error
+    end
+    ;; i64::MIN / -1 overflows, and traps in `i64.div_s`.
+    local.get $a
+    i64.const -9223372036854775808
+    i64.eq
+    local.get $b
+    i64.const -1
+    i64.eq
+    i32.and
+    if
+      local.get $line
+      local.get $column
+      call $lang_overflow
+    end
+    local.get $a
+    local.get $b
+    i64.div_s
+  )
+
+  (func $lang_enter (param $line i32) (param $column i32)
+    global.get $lang_depth
+    i32.const 200
+    i32.ge_s
+    if
+      local.get $line
+      local.get $column
+      i32.const {overflow}
+      call $lang_error
+    end
+    global.get $lang_depth
+    i32.const 1
+    i32.add
+    global.set $lang_depth
+  )
+
+  (func $lang_leave
+    global.get $lang_depth
+    i32.const 1
+    i32.sub
+    global.set $lang_depth
+  )
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{lexer, parser::Parser, resolver};
+
+    fn parse(input: &str) -> Program {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        resolver::resolve(&program).unwrap();
+        program
+    }
+
+    fn emit_wat(input: &str) -> String {
+        let wat = emit(&parse(input)).unwrap();
+        validate(&wat);
+        wat
+    }
+
+    fn emit_error(input: &str) -> (String, u32, u32) {
+        let error = emit(&parse(input)).unwrap_err();
+        (error.message, error.line, error.column)
+    }
+
+    enum Sexp {
+        Atom(String),
+        List(Vec<Sexp>),
+    }
+
+    impl Sexp {
+        fn atoms(&self) -> Vec<&str> {
+            match self {
+                Sexp::List(items) => items
+                    .iter()
+                    .filter_map(|item| match item {
+                        Sexp::Atom(atom) => Some(atom.as_str()),
+                        Sexp::List(_) => None,
+                    })
+                    .collect(),
+                Sexp::Atom(_) => Vec::new(),
+            }
+        }
+
+        fn lists(&self, head: &str) -> Vec<&Sexp> {
+            match self {
+                Sexp::List(items) => items
+                    .iter()
+                    .filter(|item| matches!(item, Sexp::List(_)) && item.atoms().first() == Some(&head))
+                    .collect(),
+                Sexp::Atom(_) => Vec::new(),
+            }
+        }
+    }
+
+    fn parse_sexp(tokens: &mut std::iter::Peekable<std::vec::IntoIter<String>>) -> Sexp {
+        let token = tokens.next().expect("unbalanced parentheses");
+        if token != "(" {
+            assert_ne!(token, ")", "unbalanced parentheses");
+            return Sexp::Atom(token);
+        }
+        let mut items = Vec::new();
+        while tokens.peek().map(String::as_str) != Some(")") {
+            items.push(parse_sexp(tokens));
+        }
+        tokens.next();
+        Sexp::List(items)
+    }
+
+    fn tokenize(wat: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut chars = wat.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                ';' if chars.peek() == Some(&';') => {
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                '(' | ')' => tokens.push(c.to_string()),
+                '"' => {
+                    let mut s = String::from('"');
+                    while let Some(c) = chars.next() {
+                        s.push(c);
+                        match c {
+                            '\\' => s.push(chars.next().unwrap()),
+                            '"' => break,
+                            _ => {}
+                        }
+                    }
+                    tokens.push(s);
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    let mut atom = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || c == '(' || c == ')' {
+                            break;
+                        }
+                        atom.push(c);
+                        chars.next();
+                    }
+                    tokens.push(atom);
+                }
+            }
+        }
+        tokens
+    }
+
+    fn value_type(ty: &str) -> &'static str {
+        match ty {
+            "i32" => "i32",
+            "i64" => "i64",
+            _ => panic!("unknown value type {ty}"),
+        }
+    }
+
+    // Value types of the (params, results) of every function, including
+    // imports.
+    type Signature = (Vec<&'static str>, Vec<&'static str>);
+
+    fn signatures(module: &Sexp) -> HashMap<String, Signature> {
+        let mut signatures = HashMap::new();
+        let mut add = |func: &Sexp| {
+            let Sexp::List(items) = func else { return };
+            // Only the lists before the first instruction belong to the
+            // signature, not the `(result i32)` of blocks in the body.
+            let header = items
+                .iter()
+                .skip(2)
+                .take_while(|item| matches!(item, Sexp::List(_)));
+            let (mut params, mut results) = (Vec::new(), Vec::new());
+            for list in header {
+                let atoms = list.atoms();
+                // Named params have a name before their type.
+                let types = atoms[1..]
+                    .iter()
+                    .filter(|atom| !atom.starts_with('$'))
+                    .map(|atom| value_type(atom));
+                match atoms[0] {
+                    "param" => params.extend(types),
+                    "result" => results.extend(types),
+                    _ => {}
+                }
+            }
+            let name = func.atoms()[1].to_string();
+            assert!(signatures.insert(name, (params, results)).is_none());
+        };
+        for import in module.lists("import") {
+            add(import.lists("func")[0]);
+        }
+        for func in module.lists("func") {
+            add(func);
+        }
+        signatures
+    }
+
+    // Checks what a wasm validator would for this module's instructions:
+    // every name is defined, every instruction finds operands of the right
+    // types and every block leaves the right values.
+    fn validate(wat: &str) {
+        let mut tokens = tokenize(wat).into_iter().peekable();
+        let module = parse_sexp(&mut tokens);
+        assert!(tokens.next().is_none());
+        assert_eq!(module.atoms(), ["module"]);
+        assert_eq!(module.lists("memory").len(), 1);
+
+        let signatures = signatures(&module);
+        let globals: HashMap<&str, &str> = module
+            .lists("global")
+            .into_iter()
+            .map(|global| {
+                (
+                    global.atoms()[1],
+                    value_type(global.lists("mut")[0].atoms()[1]),
+                )
+            })
+            .collect();
+        assert!(
+            module
+                .lists("func")
+                .iter()
+                .any(|func| func.atoms()[1] == "$main")
+        );
+
+        for func in module.lists("func") {
+            let name = func.atoms()[1];
+            let mut locals = HashMap::new();
+            for list in func.lists("param").into_iter().chain(func.lists("local")) {
+                let atoms = list.atoms();
+                locals.insert(atoms[1], value_type(atoms[2]));
+            }
+            let results = signatures[name].1.clone();
+
+            // (label, height at entry, results, whether it's a loop) and
+            // whether the rest of the block is unreachable.
+            let mut blocks = vec![(String::new(), 0, results.clone(), false)];
+            let mut stack: Vec<&str> = Vec::new();
+            let mut unreachable = false;
+            let Sexp::List(items) = func else {
+                unreachable!()
+            };
+            // Skip `func`, the name and the header lists; the `(result ...)`
+            // lists after `block` and `if` are picked up below.
+            let mut items = items.iter().skip(2).peekable();
+            while matches!(items.peek(), Some(Sexp::List(_))) {
+                items.next();
+            }
+            let mut atoms = items
+                .flat_map(|item| match item {
+                    Sexp::Atom(atom) => vec![atom.as_str()],
+                    Sexp::List(_) if item.atoms().first() == Some(&"result") => item.atoms(),
+                    Sexp::List(_) => panic!("{name}: unexpected list"),
+                })
+                .peekable();
+            // Pops values of the types `pops` and pushes `pushes`. Below an
+            // unreachable instruction missing operands can have any type.
+            let operands = |stack: &mut Vec<&'static str>,
+                            unreachable: bool,
+                            pops: &[&str],
+                            pushes: &[&'static str],
+                            base: usize,
+                            at: &str| {
+                for expected in pops.iter().rev() {
+                    if stack.len() > base {
+                        let actual = stack.pop().unwrap();
+                        assert!(
+                            *expected == "any" || actual == *expected,
+                            "{name}: '{at}' expects {expected}, got {actual}"
+                        );
+                    } else {
+                        assert!(unreachable, "{name}: '{at}' is missing operands");
+                    }
+                }
+                stack.extend(pushes);
+            };
+
+            while let Some(instruction) = atoms.next() {
+                let base = blocks.last().unwrap().1;
+                let (pops, pushes): (Vec<&str>, Vec<&'static str>) = match instruction {
+                    "i32.const" => {
+                        atoms.next().unwrap().parse::<i32>().unwrap();
+                        (vec![], vec!["i32"])
+                    }
+                    "i64.const" => {
+                        atoms.next().unwrap().parse::<i64>().unwrap();
+                        (vec![], vec!["i64"])
+                    }
+                    "local.get" | "local.set" | "local.tee" => {
+                        let local = atoms.next().unwrap();
+                        let local = *locals
+                            .get(local)
+                            .unwrap_or_else(|| panic!("{name}: unknown local {local}"));
+                        match instruction {
+                            "local.get" => (vec![], vec![local]),
+                            "local.set" => (vec![local], vec![]),
+                            _ => (vec![local], vec![local]),
+                        }
+                    }
+                    "global.get" | "global.set" => {
+                        let global = atoms.next().unwrap();
+                        let global = *globals
+                            .get(global)
+                            .unwrap_or_else(|| panic!("{name}: unknown global {global}"));
+                        if instruction == "global.get" {
+                            (vec![], vec![global])
+                        } else {
+                            (vec![global], vec![])
+                        }
+                    }
+                    "call" => {
+                        let callee = atoms.next().unwrap();
+                        let (params, results) = signatures
+                            .get(callee)
+                            .unwrap_or_else(|| panic!("{name}: unknown function {callee}"));
+                        (params.clone(), results.clone())
+                    }
+                    "i32.add" | "i32.sub" | "i32.mul" | "i32.div_s" | "i32.and" | "i32.shl"
+                    | "i32.eq" | "i32.ne" | "i32.lt_s" | "i32.le_s" | "i32.gt_s" | "i32.ge_s"
+                    | "i32.le_u" | "i32.ge_u" => (vec!["i32", "i32"], vec!["i32"]),
+                    "i64.add" | "i64.sub" | "i64.mul" | "i64.div_s" | "i64.rem_s" | "i64.and"
+                    | "i64.xor" => {
+                        (vec!["i64", "i64"], vec!["i64"])
+                    }
+                    "i64.eq" | "i64.ne" | "i64.lt_s" | "i64.le_s" | "i64.gt_s" | "i64.ge_s" => {
+                        (vec!["i64", "i64"], vec!["i32"])
+                    }
+                    "i32.eqz" | "memory.grow" => (vec!["i32"], vec!["i32"]),
+                    "i64.eqz" | "i32.wrap_i64" => (vec!["i64"], vec!["i32"]),
+                    "i32.load" | "i32.load8_u" => {
+                        if atoms.peek().is_some_and(|atom| atom.starts_with("offset=")) {
+                            atoms.next();
+                        }
+                        (vec!["i32"], vec!["i32"])
+                    }
+                    "i32.store" | "i32.store8" => (vec!["i32", "i32"], vec![]),
+                    "memory.size" => (vec![], vec!["i32"]),
+                    "select" => {
+                        operands(&mut stack, unreachable, &["i32"], &[], base, instruction);
+                        let chosen = stack.last().copied().unwrap_or("i32");
+                        (vec![chosen, chosen], vec![chosen])
+                    }
+                    "drop" => (vec!["any"], vec![]),
+                    "block" | "loop" | "if" => {
+                        if instruction == "if" {
+                            operands(&mut stack, unreachable, &["i32"], &[], base, instruction);
+                        }
+                        let mut label = String::new();
+                        if atoms.peek().is_some_and(|atom| atom.starts_with('$')) {
+                            label = atoms.next().unwrap().to_string();
+                        }
+                        let mut results = Vec::new();
+                        if atoms.peek() == Some(&"result") {
+                            atoms.next();
+                            results.push(value_type(atoms.next().unwrap()));
+                        }
+                        blocks.push((label, stack.len(), results, instruction == "loop"));
+                        unreachable = false;
+                        continue;
+                    }
+                    "else" | "end" => {
+                        let (label, entry, results, is_loop) = blocks.pop().unwrap();
+                        if !unreachable {
+                            assert_eq!(
+                                stack[entry..],
+                                results[..],
+                                "{name}: block {label} leaves the wrong values"
+                            );
+                        }
+                        stack.truncate(entry);
+                        unreachable = false;
+                        if instruction == "else" {
+                            blocks.push((label, entry, results, is_loop));
+                        } else {
+                            stack.extend(results);
+                        }
+                        continue;
+                    }
+                    "br" | "br_if" => {
+                        let label = atoms.next().unwrap();
+                        // Branches to a loop restart it and carry no values.
+                        let target = blocks
+                            .iter()
+                            .find(|(name, ..)| name == label)
+                            .unwrap_or_else(|| panic!("{name}: unknown label {label}"));
+                        let carried = if target.3 {
+                            Vec::new()
+                        } else {
+                            target.2.clone()
+                        };
+                        if instruction == "br_if" {
+                            operands(&mut stack, unreachable, &["i32"], &[], base, instruction);
+                            (carried.clone(), carried)
+                        } else {
+                            operands(&mut stack, unreachable, &carried, &[], base, instruction);
+                            unreachable = true;
+                            (vec![], vec![])
+                        }
+                    }
+                    "return" => {
+                        operands(&mut stack, unreachable, &results, &[], base, instruction);
+                        unreachable = true;
+                        (vec![], vec![])
+                    }
+                    "unreachable" => {
+                        unreachable = true;
+                        (vec![], vec![])
+                    }
+                    _ => panic!("{name}: unexpected instruction {instruction}"),
+                };
+                operands(&mut stack, unreachable, &pops, &pushes, base, instruction);
+            }
+            assert_eq!(blocks.len(), 1, "{name}: unclosed block");
+            if !unreachable {
+                assert_eq!(stack, results, "{name}: returns the wrong values");
+            }
+        }
+    }
+
+    #[test]
+    fn test_function_code() {
+        let wat = emit_wat("fn add(a, b) { return a + b; }\nprint add(1, 2);");
+        assert!(wat.contains(
+            "
+  (func $fn.add (param $a.1 i64) (param $b.2 i64) (result i64)
+    local.get $a.1
+    local.get $b.2
+    i32.const 1
+    i32.const 25
+    call $lang_add
+    return
+    unreachable
+  )
+"
+        ));
+        assert!(wat.contains(
+            "
+  (func $main (export \"main\")
+    i64.const 1
+    i64.const 2
+    i32.const 2
+    i32.const 10
+    call $lang_enter
+    call $fn.add
+    call $lang_leave
+    call $print_number
+  )
+"
+        ));
+    }
+
+    #[test]
+    fn test_fall_through_is_a_runtime_error() {
+        let wat = emit_wat("fn f(n) { while (true) return n; }\nprint f(1);");
+        assert!(wat.contains("Function 'f' ended without returning a number"));
+        assert!(wat.contains("    i32.const 1\n    i32.const 4\n"));
+
+        let wat = emit_wat("fn f(n) { if (n) return 1; else return 2; }\nprint f(true);");
+        assert!(!wat.contains("ended without returning"));
+    }
+
+    #[test]
+    fn test_control_flow_code() {
+        let wat = emit_wat("let i = 0; while (i < 3) { if (i == 1) break; i = i + 1; }");
+        assert!(wat.contains("  (global $g.i (mut i64) (i64.const 0))\n"));
+        assert!(wat.contains(
+            "
+  (func $main (export \"main\")
+    i64.const 0
+    global.set $g.i
+    block $break.1
+      loop $continue.2
+        global.get $g.i
+        i64.const 3
+        i64.lt_s
+        i32.eqz
+        br_if $break.1
+        global.get $g.i
+        i64.const 1
+        i64.eq
+        if
+          br $break.1
+        end
+        global.get $g.i
+        i64.const 1
+        i32.const 1
+        i32.const 53
+        call $lang_add
+        global.set $g.i
+        global.get $g.i
+        drop
+        br $continue.2
+      end
+    end
+  )
+"
+        ));
+    }
+
+    #[test]
+    fn test_strings_are_data_segments() {
+        let wat = emit_wat("print \"a\\\\b\\nc\"; print \"a\\\\b\\nc\";");
+        assert!(wat.contains("  (data (i32.const 8) \"\\04\\00\\00\\00true\")\n"));
+        assert!(wat.contains("  (data (i32.const 96) \"\\05\\00\\00\\00a\\5cb\\0ac\")\n"));
+        assert_eq!(wat.matches("i32.const 96\n").count(), 2);
+        assert!(wat.contains("(global $lang_heap (mut i32) (i32.const 112))"));
+    }
+
+    #[test]
+    fn test_modules_validate() {
+        // Covers what tests/backends.rs runs, for hosts without `wat2wasm`.
+        emit_wat(
+            r#"let s = "foo"; print s + "bar"; print s == "foo"; print 1 == "1";
+               print 7 / 2; print 4294967296 * -2147483648; print -(1 - 2);
+               print 1 < 2 and !(3 >= 4) or false;
+               fn show(n) { return "n=${n}"; } fn nothing() {}
+               print show(-42); print "${true} ${"s"}${0} [${nothing()}]";
+               let sum = 0;
+               for (let i = 0; i < 10; i = i + 1) {
+                   if (i == 3) continue;
+                   if (i == 8) break;
+                   let doubled = i * 2;
+                   sum = sum + doubled;
+               }
+               while (true) { sum = sum + 1; if (sum > 100) break; }
+               if (0) print 1; else print 2;
+               fn fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+               fn sign(n) { if (n < 0) { return -1; } else { return 1; } }
+               fn greet(name) { print "hello " + name; }
+               print fib(20); print sign(-5); greet("world"); print greet("again");"#,
+        );
+    }
+
+    #[test]
+    fn test_validator_rejects_broken_modules() {
+        let valid = emit_wat("print 1;");
+        for (from, to) in [
+            ("call $print_number", "call $print_missing"),
+            ("i64.const 1\n    call $print_number", "call $print_number"),
+            ("i64.const 1\n    call $print_number", "i64.const 1\n    i64.const 1\n    call $print_number"),
+            ("i64.const 1\n    call $print_number", "i32.const 1\n    call $print_number"),
+            ("    i64.add\n", "    i32.add\n"),
+            ("    local.get $bool", "    local.get $boolean"),
+            ("br $grow", "br $nowhere"),
+            ("  )\n)", "  )\n"),
+        ] {
+            let broken = valid.replacen(from, to, 1);
+            assert_ne!(broken, valid);
+            assert!(std::panic::catch_unwind(|| validate(&broken)).is_err(), "accepted {to}");
+        }
+    }
+
+    #[test]
+    fn test_unsupported_programs() {
+        assert_eq!(
+            emit_error("print 1 + \"a\";"),
+            ("Invalid operand types for '+': number and string".to_string(), 1, 9)
+        );
+        assert_eq!(
+            emit_error("let x = 1;\nx = \"a\";"),
+            ("Can't assign a string to 'x', which holds a number".to_string(), 2, 1)
+        );
+        assert_eq!(
+            emit_error("fn f() {\n  return fn() {};\n}\nf();"),
+            ("Anonymous functions are not supported by the WebAssembly backend".to_string(), 2, 3)
+        );
+        assert_eq!(
+            emit_error("fn f() {}\nlet g = f;"),
+            ("Function values are not supported by the WebAssembly backend".to_string(), 2, 9)
+        );
+        assert_eq!(
+            emit_error("fn f(a) { if (a) return 1; return \"one\"; }\nf(true);"),
+            ("Function can't return both number and string".to_string(), 1, 28)
+        );
+    }
+}
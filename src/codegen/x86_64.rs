@@ -0,0 +1,1068 @@
+//! Compiles integer-only programs to x86-64 GNU assembler for Linux,
+//! following the System V calling convention. The output is freestanding:
+//! it brings its own `_start` and a small runtime built on raw system calls,
+//! so `as` and `ld` are enough to build it.
+//!
+//! Values are numbers; bools only appear as the result of comparisons and
+//! logical operators, and can be printed or used as conditions but not
+//! stored. Strings, floats, closures and function values are not supported,
+//! and functions must be declared at the top level and called by name.
+//!
+//! Code generation is stack based: every expression leaves its value in
+//! `%rax`, and binary operators save the left operand on the stack while the
+//! right one is evaluated. Locals and parameters each get a slot in the
+//! function's frame. Runtime errors match the interpreter's, printed as
+//! `line:column: message` on stderr with exit status 1.
+
+use std::{collections::HashMap, fmt::Write, rc::Rc};
+
+use super::CodegenError;
+use crate::{BinaryOp, Expr, Function, Program, Stmt, Token, UnaryOp, always_returns};
+
+const ARGUMENT_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Int,
+    Bool,
+    /// The result of calling a function that returns nothing.
+    Nil,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Int => "number",
+            Type::Bool => "bool",
+            Type::Nil => "nil",
+        }
+    }
+}
+
+struct CompiledFunction {
+    function: Rc<Function>,
+    symbol: String,
+    returns_value: bool,
+}
+
+enum Global {
+    Variable,
+    Function(usize),
+}
+
+/// The function whose body is being written.
+struct FunctionState {
+    // `None` for the script.
+    function: Option<usize>,
+    code: String,
+    // Frame slots handed out so far, each 8 bytes below `%rbp`.
+    slots: usize,
+    scopes: Vec<HashMap<String, usize>>,
+    // (continue, break) labels of the enclosing loops.
+    loops: Vec<(String, String)>,
+    // Values pushed on the stack by the expression being evaluated, to keep
+    // `%rsp` aligned at calls.
+    depth: usize,
+}
+
+impl FunctionState {
+    fn new(function: Option<usize>) -> Self {
+        FunctionState {
+            function,
+            code: String::new(),
+            slots: 0,
+            scopes: Vec::new(),
+            loops: Vec::new(),
+            depth: 0,
+        }
+    }
+}
+
+struct Generator {
+    state: FunctionState,
+    functions: Vec<CompiledFunction>,
+    globals: HashMap<String, Global>,
+    // Names of every global variable, in declaration order.
+    global_names: Vec<String>,
+    strings: Vec<String>,
+    labels: usize,
+    line: u32,
+    column: u32,
+}
+
+/// Emits an assembly file for `program`.
+pub fn emit(program: &Program) -> Result<String, CodegenError> {
+    let mut generator = Generator {
+        state: FunctionState::new(None),
+        functions: Vec::new(),
+        globals: HashMap::new(),
+        global_names: Vec::new(),
+        strings: Vec::new(),
+        labels: 0,
+        line: 1,
+        column: 1,
+    };
+
+    for statement in &program.statements {
+        generator.statement(statement)?;
+    }
+    generator.instruction("xorl %eax, %eax");
+    let mut text = generator.finish_function("lang_main");
+
+    // Functions are compiled after the script so that they see every global
+    // it declares.
+    for index in 0..generator.functions.len() {
+        generator.state = FunctionState::new(Some(index));
+        let function = Rc::clone(&generator.functions[index].function);
+        generator.function_body(&function)?;
+        let symbol = generator.functions[index].symbol.clone();
+        text.push_str(&generator.finish_function(&symbol));
+    }
+
+    let mut out = String::new();
+    out.push_str("# Generated by lang\n\n    .text\n");
+    out.push_str(&text);
+    out.push_str(RUNTIME);
+
+    out.push_str("\n    .bss\n    .p2align 3\n");
+    for name in &generator.global_names {
+        writeln!(out, "lang_g_{name}:\n    .zero 8").unwrap();
+        writeln!(out, "lang_g_{name}_defined:\n    .zero 1").unwrap();
+        out.push_str("    .p2align 3\n");
+    }
+
+    out.push_str("\n    .section .rodata\n");
+    for (index, s) in generator.strings.iter().enumerate() {
+        writeln!(out, ".Lstr{index}:\n    .asciz \"{}\"", escape(s)).unwrap();
+    }
+    Ok(out)
+}
+
+impl Generator {
+    fn error(&self, message: String, line: u32, column: u32) -> CodegenError {
+        CodegenError::new(message, line, column)
+    }
+
+    fn unsupported(&self, what: &str) -> CodegenError {
+        self.error(
+            format!("{what} are not supported by the x86-64 backend"),
+            self.line,
+            self.column,
+        )
+    }
+
+    fn set_position(&mut self, line: u32, column: u32) {
+        self.line = line;
+        self.column = column;
+    }
+
+    fn instruction(&mut self, instruction: &str) {
+        writeln!(self.state.code, "    {instruction}").unwrap();
+    }
+
+    fn new_label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.state.code, "{label}:").unwrap();
+    }
+
+    fn push(&mut self) {
+        self.instruction("pushq %rax");
+        self.state.depth += 1;
+    }
+
+    fn pop(&mut self, register: &str) {
+        self.instruction(&format!("popq {register}"));
+        self.state.depth -= 1;
+    }
+
+    /// Calls a runtime function, padding the stack to keep it 16-byte
+    /// aligned.
+    fn call_runtime(&mut self, symbol: &str) {
+        if self.state.depth % 2 == 1 {
+            self.instruction("subq $8, %rsp");
+            self.instruction(&format!("call {symbol}"));
+            self.instruction("addq $8, %rsp");
+        } else {
+            self.instruction(&format!("call {symbol}"));
+        }
+    }
+
+    fn string(&mut self, s: String) -> String {
+        let index = match self.strings.iter().position(|existing| *existing == s) {
+            Some(index) => index,
+            None => {
+                self.strings.push(s);
+                self.strings.len() - 1
+            }
+        };
+        format!(".Lstr{index}")
+    }
+
+    /// Emits a call to `lang_error`, which doesn't return.
+    fn runtime_error(&mut self, message: String, line: u32, column: u32) {
+        let message = self.string(message);
+        self.instruction(&format!("movl ${line}, %edi"));
+        self.instruction(&format!("movl ${column}, %esi"));
+        self.instruction(&format!("leaq {message}(%rip), %rdx"));
+        self.call_runtime("lang_error");
+    }
+
+    /// Wraps the current function's code in a prologue and epilogue.
+    fn finish_function(&mut self, symbol: &str) -> String {
+        // Round the frame up to keep `%rsp` 16-byte aligned.
+        let frame = (self.state.slots * 8).div_ceil(16) * 16;
+        let mut out = format!("\n    .globl {symbol}\n{symbol}:\n");
+        out.push_str("    pushq %rbp\n    movq %rsp, %rbp\n");
+        if frame > 0 {
+            writeln!(out, "    subq ${frame}, %rsp").unwrap();
+        }
+        out.push_str(&self.state.code);
+        out.push_str("    leave\n    ret\n");
+        out
+    }
+
+    fn allocate_local(&mut self, name: &Token) -> usize {
+        self.state.slots += 1;
+        let offset = self.state.slots * 8;
+        self.state
+            .scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.lexeme.clone(), offset);
+        offset
+    }
+
+    fn is_global_scope(&self) -> bool {
+        self.state.function.is_none() && self.state.scopes.is_empty()
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), CodegenError> {
+        match statement {
+            Stmt::Expression(expr) => {
+                self.expression(expr)?;
+            }
+            Stmt::Print {
+                value,
+                line,
+                column,
+            } => {
+                self.set_position(*line, *column);
+                let printer = match self.expression(value)? {
+                    Type::Int => "lang_print_int",
+                    Type::Bool => "lang_print_bool",
+                    Type::Nil => "lang_print_nil",
+                };
+                self.instruction("movq %rax, %rdi");
+                self.call_runtime(printer);
+            }
+            Stmt::Let {
+                name, initializer, ..
+            } => {
+                self.set_position(name.line, name.column);
+                self.expect_int(initializer, "Variables holding non-numbers")?;
+                if self.is_global_scope() {
+                    if !self.global_names.contains(&name.lexeme) {
+                        self.global_names.push(name.lexeme.clone());
+                    }
+                    self.globals.insert(name.lexeme.clone(), Global::Variable);
+                    self.instruction(&format!("movq %rax, lang_g_{}(%rip)", name.lexeme));
+                    self.instruction(&format!("movb $1, lang_g_{}_defined(%rip)", name.lexeme));
+                } else {
+                    let offset = self.allocate_local(name);
+                    self.instruction(&format!("movq %rax, -{offset}(%rbp)"));
+                }
+            }
+            Stmt::Block(statements) => {
+                self.state.scopes.push(HashMap::new());
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.state.scopes.pop();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let else_label = self.new_label();
+                let end_label = self.new_label();
+                self.condition(condition, &else_label)?;
+                self.statement(then_branch)?;
+                self.instruction(&format!("jmp {end_label}"));
+                self.label(&else_label);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.label(&end_label);
+            }
+            Stmt::While { condition, body } => {
+                let start_label = self.new_label();
+                let end_label = self.new_label();
+                self.label(&start_label);
+                self.condition(condition, &end_label)?;
+                self.loop_body(body, &start_label, &end_label)?;
+                self.instruction(&format!("jmp {start_label}"));
+                self.label(&end_label);
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.state.scopes.push(HashMap::new());
+                if let Some(initializer) = initializer {
+                    self.statement(initializer)?;
+                }
+
+                let start_label = self.new_label();
+                let increment_label = self.new_label();
+                let end_label = self.new_label();
+                self.label(&start_label);
+                if let Some(condition) = condition {
+                    self.condition(condition, &end_label)?;
+                }
+                self.loop_body(body, &increment_label, &end_label)?;
+                self.label(&increment_label);
+                if let Some(increment) = increment {
+                    self.expression(increment)?;
+                }
+                self.instruction(&format!("jmp {start_label}"));
+                self.label(&end_label);
+                self.state.scopes.pop();
+            }
+            Stmt::Break { .. } => {
+                let label = self.state.loops.last().unwrap().1.clone();
+                self.instruction(&format!("jmp {label}"));
+            }
+            Stmt::Continue { .. } => {
+                let label = self.state.loops.last().unwrap().0.clone();
+                self.instruction(&format!("jmp {label}"));
+            }
+            Stmt::Function(function) => {
+                let name = function.name.as_ref().unwrap();
+                self.set_position(name.line, name.column);
+                if !self.is_global_scope() {
+                    return Err(self.unsupported("Functions declared outside the top level"));
+                }
+
+                let copies = self
+                    .functions
+                    .iter()
+                    .filter(|compiled| {
+                        compiled.function.name.as_ref().unwrap().lexeme == name.lexeme
+                    })
+                    .count();
+                let symbol = match copies {
+                    0 => format!("lang_fn_{}", name.lexeme),
+                    n => format!("lang_fn_{}_{n}", name.lexeme),
+                };
+                self.functions.push(CompiledFunction {
+                    function: Rc::clone(function),
+                    symbol,
+                    returns_value: returns_value(&function.body),
+                });
+                self.globals.insert(
+                    name.lexeme.clone(),
+                    Global::Function(self.functions.len() - 1),
+                );
+            }
+            Stmt::Return {
+                value,
+                line,
+                column,
+            } => {
+                self.set_position(*line, *column);
+                if let Some(value) = value {
+                    self.expect_int(value, "Functions returning non-numbers")?;
+                }
+                self.instruction("leave");
+                self.instruction("ret");
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_body(
+        &mut self,
+        body: &Stmt,
+        continue_label: &str,
+        break_label: &str,
+    ) -> Result<(), CodegenError> {
+        self.state
+            .loops
+            .push((continue_label.to_string(), break_label.to_string()));
+        let result = self.statement(body);
+        self.state.loops.pop();
+        result
+    }
+
+    fn function_body(&mut self, function: &Function) -> Result<(), CodegenError> {
+        self.state.scopes.push(HashMap::new());
+        for (index, param) in function.params.iter().enumerate() {
+            let offset = self.allocate_local(param);
+            match ARGUMENT_REGISTERS.get(index) {
+                Some(register) => {
+                    self.instruction(&format!("movq {register}, -{offset}(%rbp)"));
+                }
+                None => {
+                    let argument = 16 + (index - ARGUMENT_REGISTERS.len()) * 8;
+                    self.instruction(&format!("movq {argument}(%rbp), %rax"));
+                    self.instruction(&format!("movq %rax, -{offset}(%rbp)"));
+                }
+            }
+        }
+
+        for statement in &function.body {
+            self.statement(statement)?;
+        }
+
+        let name = function.name.as_ref().unwrap();
+        if self.functions[self.state.function.unwrap()].returns_value
+            && !always_returns(&function.body)
+        {
+            // The interpreter would return nil, which isn't a number.
+            self.runtime_error(
+                format!(
+                    "Function '{}' ended without returning a number",
+                    name.lexeme
+                ),
+                name.line,
+                name.column,
+            );
+        }
+        Ok(())
+    }
+
+    /// Evaluates `expr` and jumps to `false_label` unless it is truthy.
+    fn condition(&mut self, expr: &Expr, false_label: &str) -> Result<(), CodegenError> {
+        match self.expression(expr)? {
+            Type::Bool => {
+                self.instruction("testl %eax, %eax");
+                self.instruction(&format!("jz {false_label}"));
+            }
+            Type::Nil => self.instruction(&format!("jmp {false_label}")),
+            Type::Int => {}
+        }
+        Ok(())
+    }
+
+    fn expect_int(&mut self, expr: &Expr, what: &str) -> Result<(), CodegenError> {
+        match self.expression(expr)? {
+            Type::Int => Ok(()),
+            _ => Err(self.unsupported(what)),
+        }
+    }
+
+    /// Returns the frame offset of the local `name`, or `None` if it
+    /// belongs to an enclosing function.
+    fn local(&self, name: &str) -> Option<usize> {
+        self.state
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    /// Checks the global `name` has been defined when this code runs.
+    fn check_global(&mut self, name: &Token, message: String) -> Result<(), CodegenError> {
+        match self.globals.get(&name.lexeme) {
+            Some(Global::Variable) => {
+                let defined = self.new_label();
+                self.instruction(&format!("cmpb $0, lang_g_{}_defined(%rip)", name.lexeme));
+                self.instruction(&format!("jne {defined}"));
+                self.runtime_error(message, name.line, name.column);
+                self.label(&defined);
+                Ok(())
+            }
+            Some(Global::Function(_)) => Err(self.error(
+                "Function values are not supported by the x86-64 backend".to_string(),
+                name.line,
+                name.column,
+            )),
+            None => Err(self.error(
+                format!("Undefined variable '{}'", name.lexeme),
+                name.line,
+                name.column,
+            )),
+        }
+    }
+
+    fn variable_slot(
+        &mut self,
+        name: &Token,
+        is_local: bool,
+        message: String,
+    ) -> Result<String, CodegenError> {
+        if !is_local {
+            self.check_global(name, message)?;
+            return Ok(format!("lang_g_{}(%rip)", name.lexeme));
+        }
+        match self.local(&name.lexeme) {
+            Some(offset) => Ok(format!("-{offset}(%rbp)")),
+            None => Err(self.error(
+                "Closures are not supported by the x86-64 backend".to_string(),
+                name.line,
+                name.column,
+            )),
+        }
+    }
+
+    /// Emits code leaving the value of `expr` in `%rax`.
+    fn expression(&mut self, expr: &Expr) -> Result<Type, CodegenError> {
+        match expr {
+            Expr::Number(n) => {
+                // Only `movabsq` takes a full 64-bit immediate.
+                let mov = if i32::try_from(*n).is_ok() {
+                    "movq"
+                } else {
+                    "movabsq"
+                };
+                self.instruction(&format!("{mov} ${n}, %rax"));
+                Ok(Type::Int)
+            }
+            Expr::Bool(b) => {
+                self.instruction(&format!("movl ${}, %eax", *b as u8));
+                Ok(Type::Bool)
+            }
+            Expr::String(_) | Expr::Interpolation(_) => Err(self.unsupported("Strings")),
+            Expr::Float(_) => Err(self.unsupported("Floats")),
+            Expr::Grouping(inner) => self.expression(inner),
+            Expr::Unary {
+                operator,
+                operand,
+                line,
+                column,
+            } => match (operator, self.expression(operand)?) {
+                (UnaryOp::Negate, Type::Int) => {
+                    self.instruction("negq %rax");
+                    self.check_overflow(*line, *column);
+                    Ok(Type::Int)
+                }
+                (UnaryOp::Not, Type::Bool) => {
+                    self.instruction("xorl $1, %eax");
+                    Ok(Type::Bool)
+                }
+                (UnaryOp::Negate, ty) => Err(self.error(
+                    format!("Operand of '-' must be a number, got {}", ty.name()),
+                    *line,
+                    *column,
+                )),
+                (UnaryOp::Not, ty) => Err(self.error(
+                    format!("Operand of '!' must be a bool, got {}", ty.name()),
+                    *line,
+                    *column,
+                )),
+            },
+            Expr::Binary {
+                left,
+                operator: operator @ (BinaryOp::And | BinaryOp::Or),
+                right,
+                line,
+                column,
+            } => {
+                let check = |generator: &Self, ty: Type| {
+                    if ty == Type::Bool {
+                        return Ok(());
+                    }
+                    Err(generator.error(
+                        format!(
+                            "Operands of '{}' must be bools, got {}",
+                            operator.symbol(),
+                            ty.name()
+                        ),
+                        *line,
+                        *column,
+                    ))
+                };
+
+                let ty = self.expression(left)?;
+                check(self, ty)?;
+                let end_label = self.new_label();
+                self.instruction("testl %eax, %eax");
+                let jump = if matches!(operator, BinaryOp::And) {
+                    "jz"
+                } else {
+                    "jnz"
+                };
+                self.instruction(&format!("{jump} {end_label}"));
+                let ty = self.expression(right)?;
+                check(self, ty)?;
+                self.label(&end_label);
+                Ok(Type::Bool)
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                line,
+                column,
+            } => {
+                let left_type = self.expression(left)?;
+                self.push();
+                let right_type = self.expression(right)?;
+                self.instruction("movq %rax, %rcx");
+                self.pop("%rax");
+                self.binary(operator, left_type, right_type, *line, *column)
+            }
+            Expr::Variable { name, depth } => {
+                let slot = self.variable_slot(
+                    name,
+                    depth.get().is_some(),
+                    format!("Undefined variable '{}'", name.lexeme),
+                )?;
+                self.instruction(&format!("movq {slot}, %rax"));
+                Ok(Type::Int)
+            }
+            Expr::Assign { name, value, depth } => {
+                self.set_position(name.line, name.column);
+                self.expect_int(value, "Variables holding non-numbers")?;
+                let slot = self.variable_slot(
+                    name,
+                    depth.get().is_some(),
+                    format!("Cannot assign to undeclared variable '{}'", name.lexeme),
+                )?;
+                self.instruction(&format!("movq %rax, {slot}"));
+                Ok(Type::Int)
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                line,
+                column,
+            } => self.call(callee, arguments, *line, *column),
+            Expr::Function(_) => Err(self.unsupported("Anonymous functions")),
+        }
+    }
+
+    /// Jumps to a runtime error if the last arithmetic instruction
+    /// overflowed.
+    fn check_overflow(&mut self, line: u32, column: u32) {
+        let ok = self.new_label();
+        self.instruction(&format!("jno {ok}"));
+        self.runtime_error("Integer overflow".to_string(), line, column);
+        self.label(&ok);
+    }
+
+    /// Combines `%rax` (left) and `%rcx` (right) into `%rax`.
+    fn binary(
+        &mut self,
+        operator: &BinaryOp,
+        left: Type,
+        right: Type,
+        line: u32,
+        column: u32,
+    ) -> Result<Type, CodegenError> {
+        let condition = match operator {
+            BinaryOp::Equal | BinaryOp::NotEqual => {
+                let equal = matches!(operator, BinaryOp::Equal);
+                if left != right || left == Type::Nil {
+                    // Values of different types are never equal, and nil
+                    // always equals nil.
+                    let result = (left == right) == equal;
+                    self.instruction(&format!("movl ${}, %eax", result as u8));
+                    return Ok(Type::Bool);
+                }
+                if equal { "e" } else { "ne" }
+            }
+            BinaryOp::Less => "l",
+            BinaryOp::LessEqual => "le",
+            BinaryOp::Greater => "g",
+            BinaryOp::GreaterEqual => "ge",
+            _ => "",
+        };
+
+        let is_equality = matches!(operator, BinaryOp::Equal | BinaryOp::NotEqual);
+        if !is_equality && (left != Type::Int || right != Type::Int) {
+            return Err(self.error(
+                format!(
+                    "Invalid operand types for '{}': {} and {}",
+                    operator.symbol(),
+                    left.name(),
+                    right.name()
+                ),
+                line,
+                column,
+            ));
+        }
+
+        if !condition.is_empty() {
+            self.instruction("cmpq %rcx, %rax");
+            self.instruction(&format!("set{condition} %al"));
+            self.instruction("movzbl %al, %eax");
+            return Ok(Type::Bool);
+        }
+
+        match operator {
+            BinaryOp::Add => self.instruction("addq %rcx, %rax"),
+            BinaryOp::Sub => self.instruction("subq %rcx, %rax"),
+            BinaryOp::Mul => self.instruction("imulq %rcx, %rax"),
+            BinaryOp::Div => {
+                let nonzero = self.new_label();
+                let divide = self.new_label();
+                let done = self.new_label();
+                self.instruction("testq %rcx, %rcx");
+                self.instruction(&format!("jnz {nonzero}"));
+                self.runtime_error("Division by zero".to_string(), line, column);
+                self.label(&nonzero);
+                // `idivq` faults on i64::MIN / -1, so dividing by -1 is done
+                // by negating, which overflows in the same case.
+                self.instruction("cmpq $-1, %rcx");
+                self.instruction(&format!("jne {divide}"));
+                self.instruction("negq %rax");
+                self.check_overflow(line, column);
+                self.instruction(&format!("jmp {done}"));
+                self.label(&divide);
+                self.instruction("cqto");
+                self.instruction("idivq %rcx");
+                self.label(&done);
+                return Ok(Type::Int);
+            }
+            _ => unreachable!(),
+        }
+        self.check_overflow(line, column);
+        Ok(Type::Int)
+    }
+
+    fn call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        line: u32,
+        column: u32,
+    ) -> Result<Type, CodegenError> {
+        self.set_position(line, column);
+        let index = match callee {
+            Expr::Variable { name, depth } if depth.get().is_none() => {
+                match self.globals.get(&name.lexeme) {
+                    Some(Global::Function(index)) => *index,
+                    Some(Global::Variable) => {
+                        return Err(self.error(
+                            "Can only call functions, got number".to_string(),
+                            line,
+                            column,
+                        ));
+                    }
+                    None => {
+                        return Err(self.error(
+                            format!("Undefined variable '{}'", name.lexeme),
+                            name.line,
+                            name.column,
+                        ));
+                    }
+                }
+            }
+            _ => return Err(self.unsupported("Calls to function values")),
+        };
+
+        let arity = self.functions[index].function.params.len();
+        if arguments.len() != arity {
+            return Err(self.error(
+                format!("Expected {arity} arguments but got {}", arguments.len()),
+                line,
+                column,
+            ));
+        }
+
+        // Arguments are evaluated left to right onto the stack. Those past
+        // the sixth are then pushed again in reverse, as the ABI wants them,
+        // and the first six are loaded into registers.
+        let count = arguments.len();
+        let on_stack = count.saturating_sub(ARGUMENT_REGISTERS.len());
+        let padding = (self.state.depth + count + on_stack) % 2;
+        if padding == 1 {
+            self.instruction("subq $8, %rsp");
+            self.state.depth += 1;
+        }
+        for argument in arguments {
+            self.expect_int(argument, "Non-number arguments")?;
+            self.push();
+        }
+        for (pushed, index) in (ARGUMENT_REGISTERS.len()..count).rev().enumerate() {
+            let offset = (count - 1 - index + pushed) * 8;
+            self.instruction(&format!("pushq {offset}(%rsp)"));
+            self.state.depth += 1;
+        }
+        for (index, register) in ARGUMENT_REGISTERS.iter().enumerate().take(count) {
+            let offset = (count - 1 - index + on_stack) * 8;
+            self.instruction(&format!("movq {offset}(%rsp), {register}"));
+        }
+
+        let ok = self.new_label();
+        self.instruction("cmpl $200, lang_depth(%rip)");
+        self.instruction(&format!("jl {ok}"));
+        self.runtime_error("Stack overflow".to_string(), line, column);
+        self.label(&ok);
+        self.instruction("incl lang_depth(%rip)");
+        let symbol = self.functions[index].symbol.clone();
+        self.instruction(&format!("call {symbol}"));
+        self.instruction("decl lang_depth(%rip)");
+
+        let pushed = count + on_stack + padding;
+        if pushed > 0 {
+            self.instruction(&format!("addq ${}, %rsp", pushed * 8));
+            self.state.depth -= pushed;
+        }
+
+        Ok(if self.functions[index].returns_value {
+            Type::Int
+        } else {
+            Type::Nil
+        })
+    }
+}
+
+/// Whether any `return` in `statements` has a value. Functions without one
+/// return nil.
+fn returns_value(statements: &[Stmt]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Stmt::Return { value, .. } => value.is_some(),
+        Stmt::Block(statements) => returns_value(statements),
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            returns_value(std::slice::from_ref(then_branch))
+                || else_branch
+                    .as_ref()
+                    .is_some_and(|branch| returns_value(std::slice::from_ref(branch)))
+        }
+        Stmt::While { body, .. } | Stmt::For { body, .. } => {
+            returns_value(std::slice::from_ref(body))
+        }
+        _ => false,
+    })
+}
+
+/// Escapes `s` for an `.asciz` directive.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' => write!(escaped, "\\{}", byte as char).unwrap(),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => write!(escaped, "\\{byte:03o}").unwrap(),
+        }
+    }
+    escaped
+}
+
+/// Program entry point, printing and errors, using Linux system calls.
+const RUNTIME: &str = r#"
+    .globl _start
+_start:
+    call lang_main
+    xorl %edi, %edi
+    movl $231, %eax             # exit_group
+    syscall
+
+# Writes the decimal form of %rsi to file descriptor %edi.
+lang_write_int:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $32, %rsp
+    movl %edi, %r8d
+    movq %rsi, %rax
+    movq %rax, %r9
+    testq %rax, %rax
+    jns 1f
+    negq %rax
+1:  movq %rbp, %rsi
+    movl $10, %ecx
+2:  xorl %edx, %edx
+    divq %rcx
+    addb $'0', %dl
+    decq %rsi
+    movb %dl, (%rsi)
+    testq %rax, %rax
+    jnz 2b
+    testq %r9, %r9
+    jns 3f
+    decq %rsi
+    movb $'-', (%rsi)
+3:  movq %rbp, %rdx
+    subq %rsi, %rdx
+    movl %r8d, %edi
+    movl $1, %eax               # write
+    syscall
+    leave
+    ret
+
+# Writes the NUL-terminated string at %rsi to file descriptor %edi.
+lang_write_str:
+    movq %rsi, %rdx
+1:  cmpb $0, (%rdx)
+    je 2f
+    incq %rdx
+    jmp 1b
+2:  subq %rsi, %rdx
+    movl $1, %eax               # write
+    syscall
+    ret
+
+lang_print_int:
+    movq %rdi, %rsi
+    movl $1, %edi
+    call lang_write_int
+    movl $1, %edi
+    leaq lang_newline(%rip), %rsi
+    jmp lang_write_str
+
+lang_print_bool:
+    leaq lang_true(%rip), %rsi
+    leaq lang_false(%rip), %rax
+    testl %edi, %edi
+    cmovzq %rax, %rsi
+    movl $1, %edi
+    jmp lang_write_str
+
+lang_print_nil:
+    leaq lang_nil(%rip), %rsi
+    movl $1, %edi
+    jmp lang_write_str
+
+# Reports "line:column: message" from %edi, %esi and %rdx and exits.
+lang_error:
+    movl %esi, %r12d
+    movq %rdx, %r13
+    movl %edi, %esi
+    movl $2, %edi
+    call lang_write_int
+    movl $2, %edi
+    leaq lang_colon(%rip), %rsi
+    call lang_write_str
+    movl %r12d, %esi
+    movl $2, %edi
+    call lang_write_int
+    movl $2, %edi
+    leaq lang_separator(%rip), %rsi
+    call lang_write_str
+    movl $2, %edi
+    movq %r13, %rsi
+    call lang_write_str
+    movl $2, %edi
+    leaq lang_newline(%rip), %rsi
+    call lang_write_str
+    movl $1, %edi
+    movl $231, %eax             # exit_group
+    syscall
+
+    .data
+    .p2align 2
+lang_depth:
+    .long 0
+
+    .section .rodata
+lang_newline:
+    .asciz "\n"
+lang_colon:
+    .asciz ":"
+lang_separator:
+    .asciz ": "
+lang_true:
+    .asciz "true\n"
+lang_false:
+    .asciz "false\n"
+lang_nil:
+    .asciz "nil\n"
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser::Parser, resolver};
+
+    fn parse(input: &str) -> Program {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        resolver::resolve(&program).unwrap();
+        program
+    }
+
+    fn emit_error(input: &str) -> (String, u32, u32) {
+        let error = emit(&parse(input)).unwrap_err();
+        (error.message, error.line, error.column)
+    }
+
+    #[test]
+    fn test_function_code() {
+        let code = emit(&parse("fn add(a, b) { return a + b; }\nprint add(1, 2);")).unwrap();
+        assert!(code.contains(
+            "
+    .globl lang_fn_add
+lang_fn_add:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $16, %rsp
+    movq %rdi, -8(%rbp)
+    movq %rsi, -16(%rbp)
+    movq -8(%rbp), %rax
+    pushq %rax
+    movq -16(%rbp), %rax
+    movq %rax, %rcx
+    popq %rax
+    addq %rcx, %rax
+    jno .L2
+    movl $1, %edi
+    movl $25, %esi
+    leaq .Lstr1(%rip), %rdx
+    call lang_error
+.L2:
+    leave
+    ret
+"
+        ));
+        assert!(code.contains(
+            "
+    movq $1, %rax
+    pushq %rax
+    movq $2, %rax
+    pushq %rax
+    movq 8(%rsp), %rdi
+    movq 0(%rsp), %rsi
+"
+        ));
+    }
+
+    #[test]
+    fn test_fall_through_is_a_runtime_error() {
+        let code = emit(&parse("fn f(n) { while (true) return n; }\nprint f(1);")).unwrap();
+        assert!(code.contains("    .asciz \"Function 'f' ended without returning a number\"\n"));
+
+        let code = emit(&parse("fn f(n) { if (n) return 1; else return 2; }\nprint f(1);")).unwrap();
+        assert!(!code.contains("ended without returning"));
+    }
+
+    #[test]
+    fn test_unsupported_programs() {
+        assert_eq!(
+            emit_error("print \"a\";"),
+            ("Strings are not supported by the x86-64 backend".to_string(), 1, 1)
+        );
+        assert_eq!(
+            emit_error("let b = true;"),
+            ("Variables holding non-numbers are not supported by the x86-64 backend".to_string(), 1, 5)
+        );
+        assert_eq!(
+            emit_error("print 1 < true;"),
+            ("Invalid operand types for '<': number and bool".to_string(), 1, 9)
+        );
+        assert_eq!(
+            emit_error("fn f() {}\nlet g = f;"),
+            ("Function values are not supported by the x86-64 backend".to_string(), 2, 9)
+        );
+        assert_eq!(
+            emit_error("{\n  fn f() {}\n}"),
+            ("Functions declared outside the top level are not supported by the x86-64 backend".to_string(), 2, 6)
+        );
+        assert_eq!(
+            emit_error("fn f(a) {}\nf(1, 2);"),
+            ("Expected 1 arguments but got 2".to_string(), 2, 2)
+        );
+    }
+}
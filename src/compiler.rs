@@ -0,0 +1,775 @@
+use std::rc::Rc;
+
+use crate::{
+    BinaryOp, Expr, Function, InterpolationPart, Program, Stmt, SyntaxError, Token, UnaryOp,
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+};
+
+/// Locals and upvalues are addressed with a single byte.
+const MAX_SLOTS: usize = u8::MAX as usize + 1;
+
+struct Local {
+    name: String,
+    depth: usize,
+    is_captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    index: u8,
+    is_local: bool,
+}
+
+struct LoopContext {
+    // Locals live when the loop started; `break` and `continue` discard any
+    // declared after this point.
+    local_count: usize,
+    break_jumps: Vec<usize>,
+    // Start of the loop for `while`, where `continue` can jump straight
+    // back to. `for` loops continue at the increment, which is compiled
+    // after the body, so those jumps are patched later.
+    continue_target: Option<usize>,
+    continue_jumps: Vec<usize>,
+}
+
+struct FunctionState {
+    name: Option<String>,
+    arity: u8,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    loops: Vec<LoopContext>,
+}
+
+impl FunctionState {
+    fn new(name: Option<String>, arity: u8, scope_depth: usize) -> Self {
+        FunctionState {
+            name,
+            arity,
+            chunk: Chunk::new(),
+            // Slot 0 holds the function being called.
+            locals: vec![Local {
+                name: String::new(),
+                depth: scope_depth,
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth,
+            loops: Vec::new(),
+        }
+    }
+}
+
+struct Compiler {
+    // Innermost function last.
+    states: Vec<FunctionState>,
+    line: u32,
+    column: u32,
+}
+
+/// Lowers a resolved program into bytecode for [`crate::vm::Vm`].
+pub fn compile(program: &Program) -> Result<Rc<FunctionProto>, SyntaxError> {
+    let mut compiler = Compiler {
+        states: vec![FunctionState::new(None, 0, 0)],
+        line: 1,
+        column: 1,
+    };
+
+    for statement in &program.statements {
+        compiler.statement(statement)?;
+    }
+
+    let (function, _) = compiler.end_function();
+    Ok(Rc::new(function))
+}
+
+impl Compiler {
+    fn state(&mut self) -> &mut FunctionState {
+        self.states.last_mut().unwrap()
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state().chunk
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<(), SyntaxError> {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.expression(expr)?;
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Print {
+                value,
+                line,
+                column,
+            } => {
+                self.expression(value)?;
+                self.set_position(*line, *column);
+                self.emit_op(OpCode::Print);
+            }
+            Stmt::Let {
+                name, initializer, ..
+            } => {
+                self.expression(initializer)?;
+                self.define_variable(name)?;
+            }
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch)?;
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit_op(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+            Stmt::While { condition, body } => {
+                let loop_start = self.chunk().code.len();
+                self.expression(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+
+                self.begin_loop(Some(loop_start));
+                self.statement(body)?;
+                let context = self.state().loops.pop().unwrap();
+                self.emit_loop(loop_start)?;
+
+                self.patch_jump(exit_jump)?;
+                self.emit_op(OpCode::Pop);
+                self.patch_jumps(&context.break_jumps)?;
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.begin_scope();
+                if let Some(initializer) = initializer {
+                    self.statement(initializer)?;
+                }
+
+                let loop_start = self.chunk().code.len();
+                let exit_jump = match condition {
+                    Some(condition) => {
+                        self.expression(condition)?;
+                        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        self.emit_op(OpCode::Pop);
+                        Some(exit_jump)
+                    }
+                    None => None,
+                };
+
+                self.begin_loop(None);
+                self.statement(body)?;
+                let context = self.state().loops.pop().unwrap();
+
+                self.patch_jumps(&context.continue_jumps)?;
+                if let Some(increment) = increment {
+                    self.expression(increment)?;
+                    self.emit_op(OpCode::Pop);
+                }
+                self.emit_loop(loop_start)?;
+
+                if let Some(exit_jump) = exit_jump {
+                    self.patch_jump(exit_jump)?;
+                    self.emit_op(OpCode::Pop);
+                }
+                self.patch_jumps(&context.break_jumps)?;
+                self.end_scope();
+            }
+            Stmt::Break { line, column } => {
+                self.set_position(*line, *column);
+                self.discard_loop_locals();
+                let jump = self.emit_jump(OpCode::Jump);
+                self.state()
+                    .loops
+                    .last_mut()
+                    .unwrap()
+                    .break_jumps
+                    .push(jump);
+            }
+            Stmt::Continue { line, column } => {
+                self.set_position(*line, *column);
+                self.discard_loop_locals();
+                match self.state().loops.last().unwrap().continue_target {
+                    Some(target) => self.emit_loop(target)?,
+                    None => {
+                        let jump = self.emit_jump(OpCode::Jump);
+                        let context = self.state().loops.last_mut().unwrap();
+                        context.continue_jumps.push(jump);
+                    }
+                }
+            }
+            Stmt::Function(function) => {
+                let name = function.name.as_ref().unwrap();
+                if self.state().scope_depth > 0 {
+                    // Declared before the body is compiled so the function
+                    // can refer to itself.
+                    self.add_local(name)?;
+                    self.closure(function)?;
+                } else {
+                    self.closure(function)?;
+                    self.define_variable(name)?;
+                }
+            }
+            Stmt::Return {
+                value,
+                line,
+                column,
+            } => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.emit_op(OpCode::Nil),
+                }
+                self.set_position(*line, *column);
+                self.emit_op(OpCode::Return);
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<(), SyntaxError> {
+        match expr {
+            Expr::Number(n) => self.emit_constant(Constant::Number(*n))?,
+            Expr::Float(n) => self.emit_constant(Constant::Float(*n))?,
+            Expr::String(s) => self.emit_constant(Constant::String(s.as_str().into()))?,
+            Expr::Bool(true) => self.emit_op(OpCode::True),
+            Expr::Bool(false) => self.emit_op(OpCode::False),
+            Expr::Grouping(inner) => self.expression(inner)?,
+            Expr::Unary {
+                operator,
+                operand,
+                line,
+                column,
+            } => {
+                self.expression(operand)?;
+                self.set_position(*line, *column);
+                self.emit_op(match operator {
+                    UnaryOp::Negate => OpCode::Negate,
+                    UnaryOp::Not => OpCode::Not,
+                });
+            }
+            Expr::Binary {
+                left,
+                operator: BinaryOp::And,
+                right,
+                line,
+                column,
+            } => {
+                self.expression(left)?;
+                self.set_position(*line, *column);
+                self.emit_bytes(OpCode::CheckBool as u8, 0);
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.expression(right)?;
+                self.set_position(*line, *column);
+                self.emit_bytes(OpCode::CheckBool as u8, 0);
+                self.patch_jump(end_jump)?;
+            }
+            Expr::Binary {
+                left,
+                operator: BinaryOp::Or,
+                right,
+                line,
+                column,
+            } => {
+                self.expression(left)?;
+                self.set_position(*line, *column);
+                self.emit_bytes(OpCode::CheckBool as u8, 1);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump)?;
+                self.emit_op(OpCode::Pop);
+                self.expression(right)?;
+                self.set_position(*line, *column);
+                self.emit_bytes(OpCode::CheckBool as u8, 1);
+                self.patch_jump(end_jump)?;
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                line,
+                column,
+            } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.set_position(*line, *column);
+                self.emit_op(match operator {
+                    BinaryOp::Add => OpCode::Add,
+                    BinaryOp::Sub => OpCode::Subtract,
+                    BinaryOp::Mul => OpCode::Multiply,
+                    BinaryOp::Div => OpCode::Divide,
+                    BinaryOp::Less => OpCode::Less,
+                    BinaryOp::LessEqual => OpCode::LessEqual,
+                    BinaryOp::Greater => OpCode::Greater,
+                    BinaryOp::GreaterEqual => OpCode::GreaterEqual,
+                    BinaryOp::Equal => OpCode::Equal,
+                    BinaryOp::NotEqual => OpCode::NotEqual,
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                });
+            }
+            Expr::Variable { name, .. } => {
+                self.set_position(name.line, name.column);
+                let (op, operand) = self.resolve_variable(name, false)?;
+                self.emit_variable_op(op, operand);
+            }
+            Expr::Assign { name, value, .. } => {
+                self.expression(value)?;
+                self.set_position(name.line, name.column);
+                let (op, operand) = self.resolve_variable(name, true)?;
+                self.emit_variable_op(op, operand);
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                line,
+                column,
+            } => {
+                self.expression(callee)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                self.set_position(*line, *column);
+                self.emit_bytes(OpCode::Call as u8, arguments.len() as u8);
+            }
+            Expr::Function(function) => self.closure(function)?,
+            Expr::Interpolation(parts) => {
+                // A string joined from the first 255 values counts as one
+                // value of the next batch, so any number of parts fits.
+                let mut count = 0;
+                for part in parts {
+                    if count == u8::MAX {
+                        self.emit_bytes(OpCode::Interpolate as u8, count);
+                        count = 1;
+                    }
+                    match part {
+                        InterpolationPart::Literal(text) => {
+                            self.emit_constant(Constant::String(text.as_str().into()))?;
+                        }
+                        InterpolationPart::Expr(expr) => self.expression(expr)?,
+                    }
+                    count += 1;
+                }
+                self.emit_bytes(OpCode::Interpolate as u8, count);
+            }
+        }
+        Ok(())
+    }
+
+    fn closure(&mut self, function: &Function) -> Result<(), SyntaxError> {
+        let name = function.name.as_ref().map(|name| name.lexeme.clone());
+        let scope_depth = self.state().scope_depth + 1;
+        self.states.push(FunctionState::new(
+            name,
+            function.params.len() as u8,
+            scope_depth,
+        ));
+
+        for param in &function.params {
+            self.add_local(param)?;
+        }
+        for statement in &function.body {
+            self.statement(statement)?;
+        }
+
+        let (proto, upvalues) = self.end_function();
+        let index = self.make_constant(Constant::Function(Rc::new(proto)))?;
+        self.emit_op(OpCode::Closure);
+        self.emit_u16(index);
+        for upvalue in upvalues {
+            self.emit_bytes(upvalue.is_local as u8, upvalue.index);
+        }
+        Ok(())
+    }
+
+    fn end_function(&mut self) -> (FunctionProto, Vec<UpvalueRef>) {
+        self.emit_op(OpCode::Nil);
+        self.emit_op(OpCode::Return);
+
+        let state = self.states.pop().unwrap();
+        let proto = FunctionProto {
+            name: state.name,
+            arity: state.arity,
+            upvalue_count: state.upvalues.len() as u8,
+            chunk: state.chunk,
+        };
+        (proto, state.upvalues)
+    }
+
+    fn define_variable(&mut self, name: &Token) -> Result<(), SyntaxError> {
+        if self.state().scope_depth > 0 {
+            // The value already sits in the new local's stack slot.
+            return self.add_local(name);
+        }
+
+        self.set_position(name.line, name.column);
+        let index = self.make_constant(Constant::String(name.lexeme.as_str().into()))?;
+        self.emit_op(OpCode::DefineGlobal);
+        self.emit_u16(index);
+        Ok(())
+    }
+
+    fn add_local(&mut self, name: &Token) -> Result<(), SyntaxError> {
+        if self.state().locals.len() >= MAX_SLOTS {
+            return Err(SyntaxError::new(
+                "Too many local variables in function".to_string(),
+                name.line,
+                name.column,
+            ));
+        }
+
+        let depth = self.state().scope_depth;
+        self.state().locals.push(Local {
+            name: name.lexeme.clone(),
+            depth,
+            is_captured: false,
+        });
+        Ok(())
+    }
+
+    /// Picks the get or set instruction and operand for `name`, looking
+    /// through enclosing functions before falling back to a global.
+    fn resolve_variable(
+        &mut self,
+        name: &Token,
+        is_set: bool,
+    ) -> Result<(OpCode, u16), SyntaxError> {
+        let innermost = self.states.len() - 1;
+
+        if let Some(slot) = self.resolve_local(innermost, &name.lexeme) {
+            let op = if is_set {
+                OpCode::SetLocal
+            } else {
+                OpCode::GetLocal
+            };
+            return Ok((op, slot as u16));
+        }
+        if let Some(index) = self.resolve_upvalue(innermost, name)? {
+            let op = if is_set {
+                OpCode::SetUpvalue
+            } else {
+                OpCode::GetUpvalue
+            };
+            return Ok((op, index as u16));
+        }
+
+        let index = self.make_constant(Constant::String(name.lexeme.as_str().into()))?;
+        let op = if is_set {
+            OpCode::SetGlobal
+        } else {
+            OpCode::GetGlobal
+        };
+        Ok((op, index))
+    }
+
+    fn emit_variable_op(&mut self, op: OpCode, operand: u16) {
+        self.emit_op(op);
+        match op {
+            OpCode::GetGlobal | OpCode::SetGlobal => self.emit_u16(operand),
+            _ => self.emit_byte(operand as u8),
+        }
+    }
+
+    fn resolve_local(&self, state: usize, name: &str) -> Option<u8> {
+        // Slot 0 is unnamed, so it never matches.
+        self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    fn resolve_upvalue(&mut self, state: usize, name: &Token) -> Result<Option<u8>, SyntaxError> {
+        if state == 0 {
+            return Ok(None);
+        }
+
+        if let Some(slot) = self.resolve_local(state - 1, &name.lexeme) {
+            self.states[state - 1].locals[slot as usize].is_captured = true;
+            return self.add_upvalue(state, slot, true, name).map(Some);
+        }
+
+        match self.resolve_upvalue(state - 1, name)? {
+            Some(index) => self.add_upvalue(state, index, false, name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(
+        &mut self,
+        state: usize,
+        index: u8,
+        is_local: bool,
+        name: &Token,
+    ) -> Result<u8, SyntaxError> {
+        let upvalue = UpvalueRef { index, is_local };
+        let upvalues = &mut self.states[state].upvalues;
+
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(existing as u8);
+        }
+        if upvalues.len() >= MAX_SLOTS {
+            return Err(SyntaxError::new(
+                "Too many closure variables in function".to_string(),
+                name.line,
+                name.column,
+            ));
+        }
+
+        upvalues.push(upvalue);
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.state().scope_depth -= 1;
+
+        let scope_depth = self.state().scope_depth;
+        while let Some(local) = self.state().locals.last() {
+            if local.depth <= scope_depth {
+                break;
+            }
+            let op = if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.emit_op(op);
+            self.state().locals.pop();
+        }
+    }
+
+    fn begin_loop(&mut self, continue_target: Option<usize>) {
+        let local_count = self.state().locals.len();
+        self.state().loops.push(LoopContext {
+            local_count,
+            break_jumps: Vec::new(),
+            continue_target,
+            continue_jumps: Vec::new(),
+        });
+    }
+
+    /// Emits the pops for locals declared inside the innermost loop without
+    /// forgetting them, as code after a `break` or `continue` in the same
+    /// block still refers to their slots.
+    fn discard_loop_locals(&mut self) {
+        let local_count = self.state().loops.last().unwrap().local_count;
+        let captured: Vec<bool> = self.state().locals[local_count..]
+            .iter()
+            .rev()
+            .map(|local| local.is_captured)
+            .collect();
+
+        for is_captured in captured {
+            self.emit_op(if is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+        }
+    }
+
+    fn set_position(&mut self, line: u32, column: u32) {
+        self.line = line;
+        self.column = column;
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let (line, column) = (self.line, self.column);
+        self.chunk().write(byte, line, column);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_bytes(&mut self, first: u8, second: u8) {
+        self.emit_byte(first);
+        self.emit_byte(second);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        self.emit_bytes(high, low);
+    }
+
+    fn make_constant(&mut self, constant: Constant) -> Result<u16, SyntaxError> {
+        let index = self.chunk().add_constant(constant);
+        u16::try_from(index).map_err(|_| self.error("Too many constants in one chunk"))
+    }
+
+    fn emit_constant(&mut self, constant: Constant) -> Result<(), SyntaxError> {
+        let index = self.make_constant(constant)?;
+        self.emit_op(OpCode::Constant);
+        self.emit_u16(index);
+        Ok(())
+    }
+
+    /// Emits a jump with a placeholder offset, returning the offset's
+    /// position for [`Compiler::patch_jump`].
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_u16(u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, operand: usize) -> Result<(), SyntaxError> {
+        let distance = self.chunk().code.len() - operand - 2;
+        let distance =
+            u16::try_from(distance).map_err(|_| self.error("Too much code to jump over"))?;
+
+        let [high, low] = distance.to_be_bytes();
+        self.chunk().code[operand] = high;
+        self.chunk().code[operand + 1] = low;
+        Ok(())
+    }
+
+    fn patch_jumps(&mut self, operands: &[usize]) -> Result<(), SyntaxError> {
+        operands
+            .iter()
+            .try_for_each(|operand| self.patch_jump(*operand))
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> Result<(), SyntaxError> {
+        self.emit_op(OpCode::Loop);
+        let distance = self.chunk().code.len() - loop_start + 2;
+        let distance = u16::try_from(distance).map_err(|_| self.error("Loop body too large"))?;
+        self.emit_u16(distance);
+        Ok(())
+    }
+
+    fn error(&self, message: &str) -> SyntaxError {
+        SyntaxError::new(message.to_string(), self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser::Parser};
+
+    fn compile_source(input: &str) -> Rc<FunctionProto> {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        compile(&program).unwrap()
+    }
+
+    fn ops(chunk: &Chunk) -> Vec<u8> {
+        chunk.code.clone()
+    }
+
+    #[test]
+    fn test_expression_statement() {
+        let script = compile_source("print 1 + 2;");
+        assert_eq!(
+            ops(&script.chunk),
+            vec![
+                OpCode::Constant as u8, 0, 0,
+                OpCode::Constant as u8, 0, 1,
+                OpCode::Add as u8,
+                OpCode::Print as u8,
+                OpCode::Nil as u8,
+                OpCode::Return as u8,
+            ]
+        );
+        assert_eq!(script.chunk.constants, vec![Constant::Number(1), Constant::Number(2)]);
+    }
+
+    #[test]
+    fn test_operator_positions() {
+        let script = compile_source("print 1 +\n  2 / 3;");
+        let divide = script
+            .chunk
+            .code
+            .iter()
+            .position(|byte| *byte == OpCode::Divide as u8)
+            .unwrap();
+        assert_eq!(script.chunk.position(divide), (2, 5));
+    }
+
+    #[test]
+    fn test_locals_use_stack_slots() {
+        let script = compile_source("{ let a = 1; print a; }");
+        assert_eq!(
+            ops(&script.chunk),
+            vec![
+                OpCode::Constant as u8, 0, 0,
+                OpCode::GetLocal as u8, 1,
+                OpCode::Print as u8,
+                OpCode::Pop as u8,
+                OpCode::Nil as u8,
+                OpCode::Return as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_globals_use_name_constants() {
+        let script = compile_source("let a = 1; a = a;");
+        assert_eq!(
+            script.chunk.constants,
+            vec![Constant::Number(1), Constant::String("a".into())]
+        );
+        assert_eq!(script.chunk.code[3], OpCode::DefineGlobal as u8);
+    }
+
+    #[test]
+    fn test_closure_upvalues() {
+        let script = compile_source("fn outer() { let x = 1; fn inner() { return x; } return inner; }");
+        let outer = match &script.chunk.constants[0] {
+            Constant::Function(function) => Rc::clone(function),
+            _ => panic!("Expected function constant"),
+        };
+        assert_eq!(outer.name.as_deref(), Some("outer"));
+
+        let inner = match &outer.chunk.constants[1] {
+            Constant::Function(function) => Rc::clone(function),
+            _ => panic!("Expected function constant"),
+        };
+        assert_eq!(inner.upvalue_count, 1);
+
+        // Closure <index>, is_local = 1, slot = 1
+        let closure = outer
+            .chunk
+            .code
+            .iter()
+            .position(|byte| *byte == OpCode::Closure as u8)
+            .unwrap();
+        assert_eq!(&outer.chunk.code[closure + 3..closure + 5], &[1, 1]);
+    }
+
+    #[test]
+    fn test_too_many_locals() {
+        let mut source = String::from("{");
+        for i in 0..256 {
+            source.push_str(&format!("let v{i} = {i};"));
+        }
+        source.push('}');
+
+        let tokens = lexer::scan(&source).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        let err = compile(&program).unwrap_err();
+        assert_eq!(err.message, "Too many local variables in function");
+    }
+}
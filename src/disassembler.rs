@@ -0,0 +1,190 @@
+use std::fmt::Write;
+
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode};
+
+/// Renders `function` and every function nested in its constant pool as a
+/// human-readable listing, one instruction per line.
+pub fn disassemble(function: &FunctionProto) -> String {
+    let mut out = String::new();
+    disassemble_function(function, "<script>", &mut out);
+    out
+}
+
+fn disassemble_function(function: &FunctionProto, anonymous: &str, out: &mut String) {
+    let name = function.name.as_deref().unwrap_or(anonymous);
+    writeln!(out, "== {name} ==").unwrap();
+
+    let chunk = &function.chunk;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(chunk, offset, out);
+    }
+
+    for constant in &chunk.constants {
+        if let Constant::Function(nested) = constant {
+            writeln!(out).unwrap();
+            disassemble_function(nested, "<fn>", out);
+        }
+    }
+}
+
+/// Writes the instruction at `offset` to `out` and returns the offset of the
+/// next one.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize, out: &mut String) -> usize {
+    write!(out, "{offset:04} ").unwrap();
+
+    let position = chunk.position(offset);
+    if offset > 0 && chunk.position(offset - 1) == position {
+        write!(out, "{:>9} ", "|").unwrap();
+    } else {
+        write!(out, "{:>9} ", format!("{}:{}", position.0, position.1)).unwrap();
+    }
+
+    let byte = chunk.code[offset];
+    let Some(op) = OpCode::from_byte(byte) else {
+        writeln!(out, "Unknown opcode {byte}").unwrap();
+        return offset + 1;
+    };
+    let name = format!("{op:?}");
+
+    match op {
+        OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal => {
+            let index = chunk.read_u16(offset + 1);
+            let constant = describe_constant(&chunk.constants[index as usize]);
+            writeln!(out, "{name:<16} {index:4} {constant}").unwrap();
+            offset + 3
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Interpolate
+        | OpCode::Call => {
+            let operand = chunk.code[offset + 1];
+            writeln!(out, "{name:<16} {operand:4}").unwrap();
+            offset + 2
+        }
+        OpCode::CheckBool => {
+            let operator = if chunk.code[offset + 1] == 0 {
+                "and"
+            } else {
+                "or"
+            };
+            writeln!(out, "{name:<16} {operator:>4}").unwrap();
+            offset + 2
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+            let distance = chunk.read_u16(offset + 1) as usize;
+            let target = if op == OpCode::Loop {
+                offset + 3 - distance
+            } else {
+                offset + 3 + distance
+            };
+            writeln!(out, "{name:<16} {offset:04} -> {target:04}").unwrap();
+            offset + 3
+        }
+        OpCode::Closure => {
+            let index = chunk.read_u16(offset + 1);
+            let constant = &chunk.constants[index as usize];
+            writeln!(out, "{name:<16} {index:4} {}", describe_constant(constant)).unwrap();
+
+            let upvalue_count = match constant {
+                Constant::Function(function) => function.upvalue_count as usize,
+                _ => 0,
+            };
+            let mut offset = offset + 3;
+            for _ in 0..upvalue_count {
+                let kind = if chunk.code[offset] == 1 {
+                    "local"
+                } else {
+                    "upvalue"
+                };
+                let index = chunk.code[offset + 1];
+                writeln!(out, "{offset:04} {:>9} {:<16} {kind} {index}", "|", "").unwrap();
+                offset += 2;
+            }
+            offset
+        }
+        OpCode::Nil
+        | OpCode::True
+        | OpCode::False
+        | OpCode::Pop
+        | OpCode::Equal
+        | OpCode::NotEqual
+        | OpCode::Greater
+        | OpCode::GreaterEqual
+        | OpCode::Less
+        | OpCode::LessEqual
+        | OpCode::Add
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide
+        | OpCode::Not
+        | OpCode::Negate
+        | OpCode::Print
+        | OpCode::CloseUpvalue
+        | OpCode::Return => {
+            writeln!(out, "{name}").unwrap();
+            offset + 1
+        }
+    }
+}
+
+fn describe_constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Number(n) => n.to_string(),
+        Constant::Float(n) => format!("{n:?}"),
+        Constant::String(s) => format!("{:?}", s),
+        Constant::Function(function) => match &function.name {
+            Some(name) => format!("<fn {name}>"),
+            None => "<fn>".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler, lexer, parser::Parser};
+
+    fn dump(input: &str) -> String {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        disassemble(&compiler::compile(&program).unwrap())
+    }
+
+    #[test]
+    fn test_simple_listing() {
+        assert_eq!(
+            dump("print 1 +\n  \"two\";\nlet x = 2;"),
+            "\
+== <script> ==
+0000       1:1 Constant            0 1
+0003         | Constant            1 \"two\"
+0006       1:9 Add
+0007       1:1 Print
+0008         | Constant            2 2
+0011       3:5 DefineGlobal        3 \"x\"
+0014         | Nil
+0015         | Return
+"
+        );
+    }
+
+    #[test]
+    fn test_jumps_show_targets() {
+        let listing = dump("while (false) {}");
+        assert!(listing.contains("JumpIfFalse      0001 -> 0008"));
+        assert!(listing.contains("Loop             0005 -> 0000"));
+    }
+
+    #[test]
+    fn test_nested_functions_and_upvalues() {
+        let listing = dump("fn outer() { let x = 1; return fn() { return x; }; }");
+        assert!(listing.contains("== outer =="));
+        assert!(listing.contains("== <fn> =="));
+        assert!(listing.contains("Closure             1 <fn>"));
+        assert!(listing.contains("local 1"));
+        assert!(listing.contains("GetUpvalue          0"));
+    }
+}
@@ -0,0 +1,1067 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    io::Write,
+    mem, ptr,
+    rc::Rc,
+};
+
+use crate::{
+    BinaryOp, Expr, Function, InterpolationPart, Program, RuntimeError, Stmt, Token, UnaryOp,
+};
+
+/// Deepest call nesting allowed before reporting a stack overflow, well
+/// within what the host stack can hold.
+const MAX_CALL_DEPTH: usize = 200;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(i64),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    Nil,
+    Function(Rc<Closure>),
+}
+
+/// A function together with the environment it was defined in.
+pub struct Closure {
+    function: Rc<Function>,
+    environment: Rc<RefCell<Environment>>,
+}
+
+impl Closure {
+    fn arity(&self) -> usize {
+        self.function.params.len()
+    }
+}
+
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl fmt::Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function.name {
+            Some(name) => write!(f, "<fn {}>", name.lexeme),
+            None => write!(f, "<fn>"),
+        }
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::Float(l), Value::Float(r)) => l == r,
+            // Ints and floats are compared by value, so `1 == 1.0`.
+            (Value::Number(i), Value::Float(f)) | (Value::Float(f), Value::Number(i)) => {
+                *i as f64 == *f
+            }
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Function(l), Value::Function(r)) => l == r,
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    /// `false` and `nil` are falsy; every other value is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Bool(false) | Value::Nil)
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Nil => "nil",
+            Value::Function(_) => "function",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{n}"),
+            // The shortest digits that read back as `n`, keeping a `.0` on
+            // whole numbers so they don't print like ints.
+            Value::Float(n) => write!(f, "{n:?}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Nil => write!(f, "nil"),
+            Value::Function(closure) => write!(f, "{closure}"),
+        }
+    }
+}
+
+/// Reasons for abandoning the statement currently being executed.
+enum Unwind {
+    Error(RuntimeError),
+    Break,
+    Continue,
+    Return(Value),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing,
+        }
+    }
+
+    fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    /// The environment `depth` scopes out from `environment`, as computed by
+    /// the resolver.
+    fn ancestor(environment: &Rc<RefCell<Environment>>, depth: usize) -> Rc<RefCell<Environment>> {
+        let mut environment = Rc::clone(environment);
+        for _ in 0..depth {
+            let enclosing = Rc::clone(
+                environment
+                    .borrow()
+                    .enclosing
+                    .as_ref()
+                    .expect("resolved depth exceeds scope chain"),
+            );
+            environment = enclosing;
+        }
+        environment
+    }
+
+    fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        match self.values.get(&name.lexeme) {
+            Some(value) => Ok(value.clone()),
+            None => Err(RuntimeError::new(
+                format!("Undefined variable '{}'", name.lexeme),
+                name.line,
+                name.column,
+            )),
+        }
+    }
+
+    fn assign(&mut self, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        match self.values.get_mut(&name.lexeme) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(RuntimeError::new(
+                format!("Cannot assign to undeclared variable '{}'", name.lexeme),
+                name.line,
+                name.column,
+            )),
+        }
+    }
+}
+
+/// Runs resolved programs; see [`crate::resolver::resolve`].
+pub struct Interpreter<W: Write> {
+    out: W,
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    call_depth: usize,
+}
+
+impl<W: Write> Interpreter<W> {
+    pub fn new(out: W) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new(None)));
+        Interpreter {
+            out,
+            environment: Rc::clone(&globals),
+            globals,
+            call_depth: 0,
+        }
+    }
+
+    pub fn into_output(self) -> W {
+        self.out
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        for statement in &program.statements {
+            match self.execute(statement) {
+                Ok(()) => {}
+                Err(Unwind::Error(error)) => return Err(error),
+                // The parser rejects `break`, `continue` and `return` outside
+                // of the loops and functions they unwind to.
+                Err(Unwind::Break | Unwind::Continue | Unwind::Return(_)) => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Unwind> {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Print {
+                value,
+                line,
+                column,
+            } => {
+                let value = self.evaluate(value)?;
+                writeln!(self.out, "{value}").map_err(|error| {
+                    RuntimeError::new(format!("Failed to print: {error}"), *line, *column)
+                })?;
+            }
+            Stmt::Let {
+                name, initializer, ..
+            } => {
+                let value = self.evaluate(initializer)?;
+                self.environment.borrow_mut().define(&name.lexeme, value);
+            }
+            Stmt::Block(statements) => {
+                self.execute_block(statements, self.new_scope())?;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::While { condition, body } => {
+                while self.evaluate(condition)?.is_truthy() {
+                    match self.execute(body) {
+                        Ok(()) | Err(Unwind::Continue) => {}
+                        Err(Unwind::Break) => break,
+                        Err(error) => return Err(error),
+                    }
+                }
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                let scope = self.new_scope();
+                let previous = mem::replace(&mut self.environment, scope);
+                let result = self.execute_for(initializer, condition, increment, body);
+                self.environment = previous;
+                result?;
+            }
+            Stmt::Break { .. } => return Err(Unwind::Break),
+            Stmt::Continue { .. } => return Err(Unwind::Continue),
+            Stmt::Function(function) => {
+                let closure = self.closure(function);
+                let name = &function.name.as_ref().unwrap().lexeme;
+                self.environment.borrow_mut().define(name, closure);
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Err(Unwind::Return(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn closure(&self, function: &Rc<Function>) -> Value {
+        Value::Function(Rc::new(Closure {
+            function: Rc::clone(function),
+            environment: Rc::clone(&self.environment),
+        }))
+    }
+
+    fn call(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        line: u32,
+        column: u32,
+    ) -> Result<Value, RuntimeError> {
+        let closure = match callee {
+            Value::Function(closure) => closure,
+            callee => {
+                return Err(RuntimeError::new(
+                    format!("Can only call functions, got {}", callee.type_name()),
+                    line,
+                    column,
+                ));
+            }
+        };
+
+        if arguments.len() != closure.arity() {
+            return Err(RuntimeError::new(
+                format!(
+                    "Expected {} arguments but got {}",
+                    closure.arity(),
+                    arguments.len()
+                ),
+                line,
+                column,
+            ));
+        }
+
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(
+                "Stack overflow".to_string(),
+                line,
+                column,
+            ));
+        }
+
+        let mut environment = Environment::new(Some(Rc::clone(&closure.environment)));
+        for (param, argument) in closure.function.params.iter().zip(arguments) {
+            environment.define(&param.lexeme, argument);
+        }
+
+        self.call_depth += 1;
+        let result = self.execute_block(&closure.function.body, Rc::new(RefCell::new(environment)));
+        self.call_depth -= 1;
+
+        match result {
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+            // `break` and `continue` can't cross a function boundary.
+            Err(Unwind::Break | Unwind::Continue) => unreachable!(),
+        }
+    }
+
+    fn execute_for(
+        &mut self,
+        initializer: &Option<Box<Stmt>>,
+        condition: &Option<Expr>,
+        increment: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<(), Unwind> {
+        if let Some(initializer) = initializer {
+            self.execute(initializer)?;
+        }
+
+        loop {
+            if let Some(condition) = condition
+                && !self.evaluate(condition)?.is_truthy()
+            {
+                break;
+            }
+
+            match self.execute(body) {
+                Ok(()) | Err(Unwind::Continue) => {}
+                Err(Unwind::Break) => break,
+                Err(error) => return Err(error),
+            }
+
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scope_of(&self, depth: &Cell<Option<usize>>) -> Rc<RefCell<Environment>> {
+        match depth.get() {
+            Some(depth) => Environment::ancestor(&self.environment, depth),
+            None => Rc::clone(&self.globals),
+        }
+    }
+
+    fn new_scope(&self) -> Rc<RefCell<Environment>> {
+        Rc::new(RefCell::new(Environment::new(Some(Rc::clone(
+            &self.environment,
+        )))))
+    }
+
+    fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<(), Unwind> {
+        let previous = mem::replace(&mut self.environment, environment);
+
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+
+        self.environment = previous;
+        result
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Number(n) => Ok(Value::Number(*n)),
+            Expr::Float(n) => Ok(Value::Float(*n)),
+            Expr::String(s) => Ok(Value::String(s.clone())),
+            Expr::Bool(b) => Ok(Value::Bool(*b)),
+            Expr::Grouping(inner) => self.evaluate(inner),
+            Expr::Variable { name, depth } => self.scope_of(depth).borrow().get(name),
+            Expr::Assign { name, value, depth } => {
+                let value = self.evaluate(value)?;
+                self.scope_of(depth)
+                    .borrow_mut()
+                    .assign(name, value.clone())?;
+                Ok(value)
+            }
+            Expr::Function(function) => Ok(self.closure(function)),
+            Expr::Interpolation(parts) => {
+                let mut s = String::new();
+                for part in parts {
+                    match part {
+                        InterpolationPart::Literal(text) => s.push_str(text),
+                        InterpolationPart::Expr(expr) => {
+                            s.push_str(&self.evaluate(expr)?.to_string());
+                        }
+                    }
+                }
+                Ok(Value::String(s))
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                line,
+                column,
+            } => {
+                let callee = self.evaluate(callee)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(callee, arguments, *line, *column)
+            }
+            Expr::Unary {
+                operator,
+                operand,
+                line,
+                column,
+            } => {
+                let operand = self.evaluate(operand)?;
+                match (operator, operand) {
+                    (UnaryOp::Negate, Value::Number(n)) => match n.checked_neg() {
+                        Some(n) => Ok(Value::Number(n)),
+                        None => Err(RuntimeError::new(
+                            "Integer overflow".to_string(),
+                            *line,
+                            *column,
+                        )),
+                    },
+                    (UnaryOp::Negate, Value::Float(n)) => Ok(Value::Float(-n)),
+                    (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                    (UnaryOp::Negate, operand) => Err(RuntimeError::new(
+                        format!(
+                            "Operand of '-' must be a number, got {}",
+                            operand.type_name()
+                        ),
+                        *line,
+                        *column,
+                    )),
+                    (UnaryOp::Not, operand) => Err(RuntimeError::new(
+                        format!("Operand of '!' must be a bool, got {}", operand.type_name()),
+                        *line,
+                        *column,
+                    )),
+                }
+            }
+            Expr::Binary {
+                left,
+                operator: operator @ (BinaryOp::And | BinaryOp::Or),
+                right,
+                line,
+                column,
+            } => {
+                let left = expect_bool(self.evaluate(left)?, operator, *line, *column)?;
+
+                // Short-circuit: the right operand is only evaluated when it can
+                // change the result.
+                match (operator, left) {
+                    (BinaryOp::And, false) => Ok(Value::Bool(false)),
+                    (BinaryOp::Or, true) => Ok(Value::Bool(true)),
+                    _ => Ok(Value::Bool(expect_bool(
+                        self.evaluate(right)?,
+                        operator,
+                        *line,
+                        *column,
+                    )?)),
+                }
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                line,
+                column,
+            } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                binary(operator, left, right, *line, *column)
+            }
+        }
+    }
+}
+
+fn binary(
+    operator: &BinaryOp,
+    left: Value,
+    right: Value,
+    line: u32,
+    column: u32,
+) -> Result<Value, RuntimeError> {
+    match (operator, left, right) {
+        (BinaryOp::Equal, left, right) => Ok(Value::Bool(left == right)),
+        (BinaryOp::NotEqual, left, right) => Ok(Value::Bool(left != right)),
+
+        (BinaryOp::Add, Value::String(l), Value::String(r)) => Ok(Value::String(l + &r)),
+
+        (BinaryOp::Div, Value::Number(_), Value::Number(0)) => Err(RuntimeError::new(
+            "Division by zero".to_string(),
+            line,
+            column,
+        )),
+
+        (operator, Value::Number(l), Value::Number(r)) => {
+            let result = match operator {
+                BinaryOp::Add => l.checked_add(r),
+                BinaryOp::Sub => l.checked_sub(r),
+                BinaryOp::Mul => l.checked_mul(r),
+                BinaryOp::Div => l.checked_div(r),
+                BinaryOp::Less => return Ok(Value::Bool(l < r)),
+                BinaryOp::LessEqual => return Ok(Value::Bool(l <= r)),
+                BinaryOp::Greater => return Ok(Value::Bool(l > r)),
+                BinaryOp::GreaterEqual => return Ok(Value::Bool(l >= r)),
+                BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::And | BinaryOp::Or => {
+                    unreachable!()
+                }
+            };
+            result
+                .map(Value::Number)
+                .ok_or_else(|| RuntimeError::new("Integer overflow".to_string(), line, column))
+        }
+
+        // Any other mix of ints and floats is computed on floats.
+        (
+            operator,
+            left @ (Value::Number(_) | Value::Float(_)),
+            right @ (Value::Number(_) | Value::Float(_)),
+        ) => {
+            let (l, r) = (as_float(&left), as_float(&right));
+            Ok(match operator {
+                BinaryOp::Add => Value::Float(l + r),
+                BinaryOp::Sub => Value::Float(l - r),
+                BinaryOp::Mul => Value::Float(l * r),
+                BinaryOp::Div => Value::Float(l / r),
+                BinaryOp::Less => Value::Bool(l < r),
+                BinaryOp::LessEqual => Value::Bool(l <= r),
+                BinaryOp::Greater => Value::Bool(l > r),
+                BinaryOp::GreaterEqual => Value::Bool(l >= r),
+                BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::And | BinaryOp::Or => {
+                    unreachable!()
+                }
+            })
+        }
+
+        (operator, left, right) => Err(RuntimeError::new(
+            format!(
+                "Invalid operand types for '{}': {} and {}",
+                operator.symbol(),
+                left.type_name(),
+                right.type_name()
+            ),
+            line,
+            column,
+        )),
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Number(n) => *n as f64,
+        Value::Float(n) => *n,
+        _ => unreachable!(),
+    }
+}
+
+fn expect_bool(
+    value: Value,
+    operator: &BinaryOp,
+    line: u32,
+    column: u32,
+) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        value => Err(RuntimeError::new(
+            format!(
+                "Operands of '{}' must be bools, got {}",
+                operator.symbol(),
+                value.type_name()
+            ),
+            line,
+            column,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{lexer, parser::Parser, resolver};
+
+    fn eval(input: &str) -> Result<Value, RuntimeError> {
+        let tokens = lexer::scan(input).unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        resolver::resolve_expr(&expr).unwrap();
+        Interpreter::new(io::sink()).evaluate(&expr)
+    }
+
+    fn evaluate(expr: &Expr) -> Result<Value, RuntimeError> {
+        Interpreter::new(io::sink()).evaluate(expr)
+    }
+
+    fn run(input: &str) -> Result<String, RuntimeError> {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        resolver::resolve(&program).unwrap();
+        let mut interpreter = Interpreter::new(Vec::new());
+        interpreter.run(&program)?;
+        Ok(String::from_utf8(interpreter.into_output()).unwrap())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("1 + 2 * 3").unwrap(), Value::Number(7));
+        assert_eq!(eval("(1 + 2) * 3").unwrap(), Value::Number(9));
+        assert_eq!(eval("10 - 4 - 3").unwrap(), Value::Number(3));
+        assert_eq!(eval("7 / 2").unwrap(), Value::Number(3));
+    }
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(eval("1.5 * 2").unwrap(), Value::Float(3.0));
+        assert_eq!(eval("7 / 2.0").unwrap(), Value::Float(3.5));
+        assert_eq!(eval("-.5").unwrap(), Value::Float(-0.5));
+        assert_eq!(eval("1 / 0.0").unwrap(), Value::Float(f64::INFINITY));
+        assert_eq!(eval("2 == 2.0").unwrap(), Value::Bool(true));
+        assert_eq!(eval("1 < 1.5").unwrap(), Value::Bool(true));
+
+        let output = run("print 0.1 + 0.2; print 2.0; print 1e-9; print 1e16; print 3;").unwrap();
+        assert_eq!(output, "0.30000000000000004\n2.0\n1e-9\n1e16\n3\n");
+
+        let err = eval("1.5 + \"a\"").unwrap_err();
+        assert_eq!(err.message, "Invalid operand types for '+': float and string");
+    }
+
+    #[test]
+    fn test_unary() {
+        assert_eq!(eval("-5").unwrap(), Value::Number(-5));
+        assert_eq!(eval("--5").unwrap(), Value::Number(5));
+        assert_eq!(eval("!true").unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn test_string_concatenation() {
+        assert_eq!(
+            eval("\"foo\" + \"bar\"").unwrap(),
+            Value::String("foobar".to_string())
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let err = eval("1 / 0").unwrap_err();
+        assert_eq!(err.message, "Division by zero");
+        assert_eq!(err.line, 1);
+        assert_eq!(err.column, 3);
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let err = eval("1 + \"a\"").unwrap_err();
+        assert!(err.message.contains("Invalid operand types"));
+        assert_eq!(err.column, 3);
+
+        let err = eval("-\"a\"").unwrap_err();
+        assert!(err.message.contains("must be a number"));
+        assert_eq!(err.column, 1);
+    }
+
+    #[test]
+    fn test_error_position_multiline() {
+        let err = eval("(1 +\n  2) /\n0").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.column, 6);
+    }
+
+    #[test]
+    fn test_comparison_and_logic() {
+        assert_eq!(eval("1 < 2").unwrap(), Value::Bool(true));
+        assert_eq!(eval("2 <= 1").unwrap(), Value::Bool(false));
+        assert_eq!(eval("1 + 1 != 2").unwrap(), Value::Bool(false));
+        assert_eq!(eval("1 < 2 and 3 > 4").unwrap(), Value::Bool(false));
+        assert_eq!(eval("1 > 2 or 3 >= 3").unwrap(), Value::Bool(true));
+        assert_eq!(eval("1 + 1 == 2 == true").unwrap(), Value::Bool(true));
+    }
+
+    #[test]
+    fn test_binary_ops() {
+        let expr = |operator, left, right| Expr::Binary {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+            line: 1,
+            column: 1,
+        };
+
+        assert_eq!(
+            evaluate(&expr(BinaryOp::Less, Expr::Number(1), Expr::Number(2))).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            evaluate(&expr(
+                BinaryOp::GreaterEqual,
+                Expr::Number(1),
+                Expr::Number(2)
+            ))
+            .unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            evaluate(&expr(
+                BinaryOp::Equal,
+                Expr::Number(1),
+                Expr::String("1".to_string())
+            ))
+            .unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            evaluate(&expr(
+                BinaryOp::NotEqual,
+                Expr::Bool(true),
+                Expr::Bool(false)
+            ))
+            .unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            evaluate(&expr(BinaryOp::Or, Expr::Bool(false), Expr::Bool(true))).unwrap(),
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_logical_short_circuit() {
+        let division_by_zero = Expr::Binary {
+            left: Box::new(Expr::Number(1)),
+            operator: BinaryOp::Div,
+            right: Box::new(Expr::Number(0)),
+            line: 1,
+            column: 1,
+        };
+        let expr = Expr::Binary {
+            left: Box::new(Expr::Bool(false)),
+            operator: BinaryOp::And,
+            right: Box::new(division_by_zero),
+            line: 1,
+            column: 1,
+        };
+        assert_eq!(evaluate(&expr).unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn test_logical_requires_bools() {
+        let expr = Expr::Binary {
+            left: Box::new(Expr::Number(1)),
+            operator: BinaryOp::Or,
+            right: Box::new(Expr::Bool(true)),
+            line: 3,
+            column: 7,
+        };
+        let err = evaluate(&expr).unwrap_err();
+        assert!(err.message.contains("must be bools"));
+        assert_eq!((err.line, err.column), (3, 7));
+    }
+
+    #[test]
+    fn test_print_statements() {
+        assert_eq!(
+            run("print 1 + 2; print \"hi\"; 3; print 1 < 2;").unwrap(),
+            "3\nhi\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn test_runtime_error_stops_program() {
+        let err = run("print 1;\nprint 1 / 0;\nprint 2;").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 9));
+    }
+
+    #[test]
+    fn test_global_variables() {
+        assert_eq!(
+            run("let a = 1; let b = a + 2; print b; a = b = 5; print a + b;").unwrap(),
+            "3\n10\n"
+        );
+    }
+
+    #[test]
+    fn test_block_scoping_and_shadowing() {
+        let source = "
+            let a = \"global\";
+            let b = 1;
+            {
+                let a = \"outer\";
+                {
+                    let a = \"inner\";
+                    print a;
+                    b = b + 1;
+                }
+                print a;
+            }
+            print a;
+            print b;
+        ";
+        assert_eq!(run(source).unwrap(), "inner\nouter\nglobal\n2\n");
+    }
+
+    #[test]
+    fn test_block_locals_do_not_leak() {
+        let err = run("{ let x = 1; }\nprint x;").unwrap_err();
+        assert_eq!(err.message, "Undefined variable 'x'");
+        assert_eq!((err.line, err.column), (2, 7));
+    }
+
+    #[test]
+    fn test_assign_before_declare() {
+        let err = run("let a = 1;\n  b = a;").unwrap_err();
+        assert_eq!(err.message, "Cannot assign to undeclared variable 'b'");
+        assert_eq!((err.line, err.column), (2, 3));
+    }
+
+    #[test]
+    fn test_scope_restored_after_error() {
+        let tokens = lexer::scan("let a = 1; { let a = 2; print 1 / 0; }").unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        resolver::resolve(&program).unwrap();
+        let mut interpreter = Interpreter::new(Vec::new());
+        assert!(interpreter.run(&program).is_err());
+
+        let tokens = lexer::scan("a").unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(interpreter.evaluate(&expr).unwrap(), Value::Number(1));
+    }
+
+    #[test]
+    fn test_truthiness() {
+        assert!(!Value::Bool(false).is_truthy());
+        assert!(!Value::Nil.is_truthy());
+        assert!(Value::Bool(true).is_truthy());
+        assert!(Value::Number(0).is_truthy());
+        assert!(Value::String(String::new()).is_truthy());
+    }
+
+    #[test]
+    fn test_if_else() {
+        let source = "
+            if (1 < 2) print \"then\"; else print \"else\";
+            if (false) print 1; else if (0) print 2; else print 3;
+            if (\"\") { print \"empty string is truthy\"; }
+        ";
+        assert_eq!(run(source).unwrap(), "then\n2\nempty string is truthy\n");
+    }
+
+    #[test]
+    fn test_while_loop() {
+        let source = "
+            let i = 0;
+            let sum = 0;
+            while (i < 5) {
+                i = i + 1;
+                if (i == 2) continue;
+                if (i == 4) break;
+                sum = sum + i;
+            }
+            print sum;
+        ";
+        assert_eq!(run(source).unwrap(), "4\n");
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let source = "
+            for (let i = 0; i < 6; i = i + 1) {
+                if (i == 1) continue;
+                if (i == 4) break;
+                print i;
+            }
+        ";
+        assert_eq!(run(source).unwrap(), "0\n2\n3\n");
+    }
+
+    #[test]
+    fn test_for_loop_scope() {
+        let err = run("for (let i = 0; i < 1; i = i + 1) {}\nprint i;").unwrap_err();
+        assert_eq!(err.message, "Undefined variable 'i'");
+
+        assert_eq!(
+            run("let i = 10; for (i = 0; i < 2; i = i + 1) {} print i;").unwrap(),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_loop_break() {
+        let source = "
+            for (let i = 0; i < 3; i = i + 1) {
+                for (let j = 0; ; j = j + 1) {
+                    if (j > i) break;
+                    print i * 10 + j;
+                }
+            }
+        ";
+        assert_eq!(run(source).unwrap(), "0\n10\n11\n20\n21\n22\n");
+    }
+
+    #[test]
+    fn test_function_call() {
+        let source = "
+            fn add(a, b) { return a + b; }
+            fn greet(name) { print \"hi \" + name; }
+            print add(1, 2);
+            print greet(\"bob\");
+            print add;
+        ";
+        assert_eq!(run(source).unwrap(), "3\nhi bob\nnil\n<fn add>\n");
+    }
+
+    #[test]
+    fn test_recursion() {
+        let source = "
+            fn fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            print fib(15);
+        ";
+        assert_eq!(run(source).unwrap(), "610\n");
+    }
+
+    #[test]
+    fn test_return_from_loop() {
+        let source = "
+            fn first_over(limit) {
+                for (let i = 0; ; i = i + 1) {
+                    while (true) {
+                        if (i * i > limit) return i;
+                        break;
+                    }
+                }
+            }
+            print first_over(50);
+        ";
+        assert_eq!(run(source).unwrap(), "8\n");
+    }
+
+    #[test]
+    fn test_closures() {
+        let source = "
+            fn make_counter() {
+                let count = 0;
+                return fn() {
+                    count = count + 1;
+                    return count;
+                };
+            }
+            let a = make_counter();
+            let b = make_counter();
+            print a();
+            print a();
+            print b();
+        ";
+        assert_eq!(run(source).unwrap(), "1\n2\n1\n");
+    }
+
+    #[test]
+    fn test_closure_captures_defining_environment() {
+        let source = "
+            let x = \"global\";
+            fn show() { print x; }
+            {
+                let x = \"local\";
+                show();
+            }
+            let apply = fn(f, v) { return f(v); };
+            print apply(fn(n) { return n * 2; }, 21);
+        ";
+        assert_eq!(run(source).unwrap(), "global\n42\n");
+    }
+
+    #[test]
+    fn test_arity_mismatch() {
+        let err = run("fn f(a) {}\nf(1,\n  2);").unwrap_err();
+        assert_eq!(err.message, "Expected 1 arguments but got 2");
+        assert_eq!((err.line, err.column), (2, 2));
+    }
+
+    #[test]
+    fn test_call_non_callable() {
+        let err = run("let x = 1;\n  x();").unwrap_err();
+        assert_eq!(err.message, "Can only call functions, got number");
+        assert_eq!((err.line, err.column), (2, 4));
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let err = run("fn f(n) { return f(n + 1); }\nf(0);").unwrap_err();
+        assert_eq!(err.message, "Stack overflow");
+        assert_eq!(err.line, 1);
+    }
+
+    #[test]
+    fn test_closure_binding_is_static() {
+        let source = "
+            let a = \"global\";
+            {
+                fn show() { print a; }
+                show();
+                let a = \"block\";
+                show();
+                print a;
+            }
+        ";
+        assert_eq!(run(source).unwrap(), "global\nglobal\nblock\n");
+    }
+
+    #[test]
+    fn test_anonymous_function_expression_locals() {
+        assert_eq!(eval("fn(x) { return x * 2; }(21)").unwrap(), Value::Number(42));
+    }
+}
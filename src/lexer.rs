@@ -2,8 +2,34 @@ use std::{iter::Peekable, mem, str::Chars};
 
 use crate::{SyntaxError, Token, TokenType};
 
+/// A string literal being scanned. Interpolation splits it into several
+/// tokens, and it waits on the lexer's `interpolations` while an embedded
+/// expression is scanned.
+struct StringLiteral {
+    /// Position of the opening quote.
+    line: u32,
+    column: u32,
+    /// The `{`s opened inside the embedded expression that aren't closed yet.
+    braces: u32,
+    /// The source lines of a triple-quoted string, or `None` for a string
+    /// between single quotes.
+    lines: Option<Vec<TextLine>>,
+}
+
+/// A source line of a triple-quoted string.
+struct TextLine {
+    /// Index of the token the line starts in, and where in its text.
+    token: usize,
+    start: usize,
+    /// The spaces and tabs the line starts with.
+    indent: String,
+    /// Whether the line has nothing but its indentation.
+    blank: bool,
+}
+
 struct Lexer<'s> {
     tokens: Vec<Token>,
+    interpolations: Vec<StringLiteral>,
     current_lexeme: String,
     chars: Peekable<Chars<'s>>,
     line: u32,
@@ -21,6 +47,12 @@ impl<'s> Lexer<'s> {
         )
     }
 
+    /// An error pointing at the next character, or just past the end of the
+    /// input.
+    fn error_at_next(&self, message: String) -> SyntaxError {
+        SyntaxError::new(message, self.line, self.column + 1)
+    }
+
     fn consume_if(&mut self, ch: char) -> bool {
         match self.chars.peek() {
             Some(c) if *c == ch => {
@@ -32,25 +64,11 @@ impl<'s> Lexer<'s> {
     }
 
     fn track_line_column(&mut self, character: char) {
-        match character {
-            '\n' => {
-                self.line += 1;
-                self.column = 0;
-            }
-            '\t' => {
-                self.column += 4;
-            }
-            _ => {
-                self.column += 1;
-            }
-        };
+        (self.line, self.column) = advance(self.line, self.column, character);
     }
 
     fn consume(&mut self) -> Option<char> {
-        let character = match self.chars.next() {
-            Some(character) => character,
-            None => return None,
-        };
+        let character = self.chars.next()?;
 
         self.track_line_column(character);
         self.current_lexeme.push(character);
@@ -65,14 +83,49 @@ impl<'s> Lexer<'s> {
 
         let character = match self.consume() {
             Some(character) => character,
-            None => return Ok(false),
+            None => match self.interpolations.last() {
+                Some(string) => {
+                    return Err(SyntaxError::new(
+                        "Invalid String".to_string(),
+                        string.line,
+                        string.column,
+                    ));
+                }
+                None => return Ok(false),
+            },
         };
 
         match character {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
+            '{' => {
+                if let Some(string) = self.interpolations.last_mut() {
+                    string.braces += 1;
+                }
+                self.add_token(TokenType::LeftBrace);
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(string) if string.braces == 0 => {
+                    let literal = self.interpolations.pop().unwrap();
+                    self.string(literal, true)?;
+                }
+                Some(string) => {
+                    string.braces -= 1;
+                    self.add_token(TokenType::RightBrace);
+                }
+                None => self.add_token(TokenType::RightBrace),
+            },
+            ',' => self.add_token(TokenType::Comma),
+            ';' => self.add_token(TokenType::Semicolon),
+            ':' => self.add_token(TokenType::Colon),
             '+' => self.add_token(TokenType::Plus),
-            '-' => self.add_token(TokenType::Minus),
+            '-' => {
+                if self.consume_if('>') {
+                    self.add_token(TokenType::Arrow);
+                } else {
+                    self.add_token(TokenType::Minus);
+                }
+            }
             '*' => self.add_token(TokenType::Star),
             '/' => self.add_token(TokenType::Slash),
             '!' => {
@@ -82,6 +135,13 @@ impl<'s> Lexer<'s> {
                     self.add_token(TokenType::Bang);
                 }
             }
+            '=' => {
+                if self.consume_if('=') {
+                    self.add_token(TokenType::EqualEqual);
+                } else {
+                    self.add_token(TokenType::Equal);
+                }
+            }
             '>' => {
                 if self.consume_if('=') {
                     self.add_token(TokenType::GreaterEqual);
@@ -101,36 +161,31 @@ impl<'s> Lexer<'s> {
             '\t' => {}
             '\n' => {}
             '"' => {
-                let mut is_string = false;
-
-                while let Some(character) = self.consume() {
-                    if character == '"' {
-                        is_string = true;
-                        break;
-                    }
-                }
-
-                if !is_string {
-                    return Err(self.new_error("Invalid String".to_string()));
+                let triple = self.chars.clone().take(2).eq(['"', '"']);
+                if triple {
+                    self.consume();
+                    self.consume();
                 }
-
-                self.add_token(TokenType::String(
-                    self.current_lexeme[1..self.current_lexeme.len() - 1].to_string(),
-                ));
+                let literal = StringLiteral {
+                    line: self.token_start_line,
+                    column: self.token_start_column,
+                    braces: 0,
+                    lines: triple.then(|| {
+                        vec![TextLine {
+                            token: self.tokens.len(),
+                            start: 0,
+                            indent: String::new(),
+                            blank: true,
+                        }]
+                    }),
+                };
+                self.string(literal, false)?;
             }
             character => {
-                if character.is_digit(10) {
-                    while let Some(character) = self.chars.peek() {
-                        if character.is_digit(10) {
-                            self.consume();
-                        } else {
-                            break;
-                        };
-                    }
-
-                    self.add_token(TokenType::Number(
-                        self.current_lexeme.parse::<i32>().unwrap(),
-                    ));
+                if character.is_ascii_digit() || character == '.' && self.peek_is_digit(0) {
+                    self.number(character)?;
+                } else if character == 'r' && matches!(self.chars.peek(), Some('"' | '#')) {
+                    self.raw_string()?;
                 } else if character.is_ascii_alphabetic() || character == '_' {
                     while let Some(character) = self.chars.peek() {
                         if character.is_ascii_alphanumeric() || *character == '_' {
@@ -140,11 +195,9 @@ impl<'s> Lexer<'s> {
                         };
                     }
 
-                    match self.current_lexeme.as_str() {
-                        "and" => self.add_token(TokenType::And),
-                        "or" => self.add_token(TokenType::Or),
-                        _ => {}
-                    };
+                    let token_type = keyword(&self.current_lexeme)
+                        .unwrap_or_else(|| TokenType::Identifier(self.current_lexeme.clone()));
+                    self.add_token(token_type);
                 } else {
                     return Err(self.new_error(format!("Unexpected Token: {character}")));
                 }
@@ -154,6 +207,311 @@ impl<'s> Lexer<'s> {
         Ok(true)
     }
 
+    /// Scans the rest of a string literal after its opening quotes, decoding
+    /// escape sequences. The token's lexeme keeps the literal as written.
+    ///
+    /// A `${` stops the scan with an interpolation token, and the embedded
+    /// expression is scanned as ordinary tokens until its closing `}`, which
+    /// resumes the string with `continued` set.
+    fn string(&mut self, mut literal: StringLiteral, continued: bool) -> Result<(), SyntaxError> {
+        let mut value = String::new();
+        loop {
+            let Some(character) = self.consume() else {
+                return Err(SyntaxError::new(
+                    "Invalid String".to_string(),
+                    literal.line,
+                    literal.column,
+                ));
+            };
+            if character == '"' {
+                if literal.lines.is_none() {
+                    break;
+                }
+                if self.chars.clone().take(2).eq(['"', '"']) {
+                    self.consume();
+                    self.consume();
+                    break;
+                }
+            }
+
+            if let Some(lines) = &mut literal.lines {
+                let line = lines.last_mut().unwrap();
+                if character == '\n' {
+                    value.push(character);
+                    lines.push(TextLine {
+                        token: self.tokens.len(),
+                        start: value.len(),
+                        indent: String::new(),
+                        blank: true,
+                    });
+                    continue;
+                }
+                if line.blank && matches!(character, ' ' | '\t') {
+                    line.indent.push(character);
+                } else {
+                    line.blank = false;
+                }
+            }
+
+            match character {
+                '\\' => value.push(self.escape()?),
+                '$' if self.consume_if('{') => {
+                    self.interpolations.push(literal);
+                    self.add_token(if continued {
+                        TokenType::InterpolationMiddle(value)
+                    } else {
+                        TokenType::InterpolationStart(value)
+                    });
+                    return Ok(());
+                }
+                character => value.push(character),
+            }
+        }
+
+        self.add_token(if continued {
+            TokenType::InterpolationEnd(value)
+        } else {
+            TokenType::String(value)
+        });
+        if let Some(lines) = literal.lines {
+            self.strip_indentation(&lines);
+        }
+        Ok(())
+    }
+
+    /// Removes the indentation shared by the non-blank lines of a
+    /// triple-quoted string from the text of its tokens, and empties its
+    /// blank lines. Only the spaces and tabs the lines start with in the
+    /// same order are shared, so a tab never stands in for spaces. A blank line right after the opening quotes or before the
+    /// closing ones is dropped along with its line break, so the text can
+    /// start and end on lines of its own.
+    fn strip_indentation(&mut self, lines: &[TextLine]) {
+        let mut indents = lines
+            .iter()
+            .filter(|line| !line.blank)
+            .map(|line| line.indent.as_str());
+        let first = indents.next().unwrap_or("");
+        let common = indents
+            .fold(first, |common, indent| {
+                let shared = common
+                    .bytes()
+                    .zip(indent.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                &common[..shared]
+            })
+            .len();
+        let last = lines.len() - 1;
+        let drop_first = last > 0 && lines[0].blank;
+        let drop_last = last > 0 && lines[last].blank;
+
+        // Backwards, so removing text doesn't move the lines still to do.
+        for (i, line) in lines.iter().enumerate().rev() {
+            let range = if !line.blank {
+                line.start..line.start + common
+            } else if i == 0 && drop_first {
+                line.start..line.start + line.indent.len() + 1
+            } else if i == last && drop_last && (last > 1 || !drop_first) {
+                line.start - 1..line.start + line.indent.len()
+            } else {
+                line.start..line.start + line.indent.len()
+            };
+            let (TokenType::String(text)
+            | TokenType::InterpolationStart(text)
+            | TokenType::InterpolationMiddle(text)
+            | TokenType::InterpolationEnd(text)) = &mut self.tokens[line.token].token_type
+            else {
+                unreachable!("Lines of a string start in its tokens");
+            };
+            text.replace_range(range, "");
+        }
+    }
+
+    /// Scans a raw string after its `r`: `r"..."`, or `r#"..."#` with any
+    /// number of `#`s, which can contain `"`s not followed by as many `#`s.
+    /// Nothing in a raw string is an escape or an interpolation.
+    fn raw_string(&mut self) -> Result<(), SyntaxError> {
+        let mut hashes = 0;
+        while self.consume_if('#') {
+            hashes += 1;
+        }
+        if !self.consume_if('"') {
+            return Err(self.error_at_next("Expected '\"' to start a raw string".to_string()));
+        }
+
+        let closing = "#".repeat(hashes);
+        let mut value = String::new();
+        loop {
+            match self.consume() {
+                Some('"') if self.chars.clone().take(hashes).eq(closing.chars()) => {
+                    for _ in 0..hashes {
+                        self.consume();
+                    }
+                    break;
+                }
+                Some(character) => value.push(character),
+                None => return Err(self.new_error("Invalid String".to_string())),
+            }
+        }
+
+        self.add_token(TokenType::String(value));
+        Ok(())
+    }
+
+    /// Decodes the escape sequence after a `\` in a string: `\n`, `\t`, `\\`,
+    /// `\"`, `\$` or `\u{...}` with 1 to 6 hex digits. Errors point at the
+    /// `\`.
+    fn escape(&mut self) -> Result<char, SyntaxError> {
+        let (line, column) = (self.line, self.column);
+        let error = |message: &str| SyntaxError::new(message.to_string(), line, column);
+
+        match self.consume() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('\\') => Ok('\\'),
+            Some('"') => Ok('"'),
+            Some('$') => Ok('$'),
+            Some('u') => {
+                if !self.consume_if('{') {
+                    return Err(error("Expected '{' after '\\u'"));
+                }
+                let start = self.current_lexeme.len();
+                while self.chars.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                    self.consume();
+                }
+                let digits = self.current_lexeme[start..].to_string();
+                if !self.consume_if('}') {
+                    return Err(error("Unterminated unicode escape"));
+                }
+                if digits.is_empty() || digits.len() > 6 {
+                    return Err(error("Unicode escape must have 1 to 6 hex digits"));
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        error(&format!(
+                            "'\\u{{{digits}}}' is not a valid unicode character"
+                        ))
+                    })
+            }
+            Some(other) => Err(error(&format!("Invalid escape sequence '\\{other}'"))),
+            None => Err(self.new_error("Invalid String".to_string())),
+        }
+    }
+
+    /// Whether the character `n` places past the next one is a digit.
+    fn peek_is_digit(&self, n: usize) -> bool {
+        self.chars.clone().nth(n).is_some_and(|c| c.is_ascii_digit())
+    }
+
+    /// Consumes digits in `radix` continuing the current lexeme. Digits may be
+    /// separated by single `_`s, as in `1_000_000`, but a `_` must be
+    /// followed by a digit.
+    fn digits(&mut self, radix: u32) -> Result<(), SyntaxError> {
+        while let Some(&character) = self.chars.peek() {
+            if character == '_' && self.current_lexeme.ends_with('_') {
+                return Err(self.error_at_next("Expected a digit after '_'".to_string()));
+            }
+            let after_digit = self.current_lexeme.ends_with(|c: char| c.is_digit(radix));
+            if character.is_digit(radix) || character == '_' && after_digit {
+                self.consume();
+            } else {
+                break;
+            }
+        }
+
+        if self.current_lexeme.ends_with('_') {
+            return Err(self.error_at_next("Expected a digit after '_'".to_string()));
+        }
+        Ok(())
+    }
+
+    fn too_large(&self) -> SyntaxError {
+        self.new_error(format!(
+            "Number {} doesn't fit in 64 bits",
+            self.current_lexeme
+        ))
+    }
+
+    /// Scans the rest of a number literal after its first character. A
+    /// fraction or an exponent makes it a float: `3.14`, `1.`, `.5`, `1e-9`.
+    /// An `e` right after the digits always starts an exponent.
+    /// A `.` followed by another `.` is left alone, so `1..2` isn't read as
+    /// `1.` followed by `.2`.
+    fn number(&mut self, first: char) -> Result<(), SyntaxError> {
+        if first == '0'
+            && let Some(&prefix @ ('x' | 'o' | 'b')) = self.chars.peek()
+        {
+            return self.radix_number(prefix);
+        }
+
+        self.digits(10)?;
+        let mut is_float = self.current_lexeme.starts_with('.');
+        if !is_float && self.chars.peek() == Some(&'.') && self.chars.clone().nth(1) != Some('.')
+        {
+            self.consume();
+            self.digits(10)?;
+            is_float = true;
+        }
+        if matches!(self.chars.peek(), Some('e' | 'E')) {
+            self.consume();
+            if matches!(self.chars.peek(), Some('+' | '-')) {
+                self.consume();
+            }
+            if !self.peek_is_digit(0) {
+                return Err(self.error_at_next("Expected digits in exponent".to_string()));
+            }
+            self.digits(10)?;
+            is_float = true;
+        }
+
+        let digits = self.current_lexeme.replace('_', "");
+        let token_type = if is_float {
+            let number = digits.parse::<f64>().unwrap();
+            if number.is_infinite() {
+                return Err(self.new_error(format!(
+                    "Float literal {} is out of range",
+                    self.current_lexeme
+                )));
+            }
+            TokenType::Float(number)
+        } else {
+            TokenType::Number(digits.parse().map_err(|_| self.too_large())?)
+        };
+        self.add_token(token_type);
+        Ok(())
+    }
+
+    /// Scans a `0x`, `0o` or `0b` integer literal after its `0`. These give
+    /// the number's 64 bits directly, so `0xFFFF_FFFF_FFFF_FFFF` is -1.
+    fn radix_number(&mut self, prefix: char) -> Result<(), SyntaxError> {
+        let (radix, name) = match prefix {
+            'x' => (16, "hex"),
+            'o' => (8, "octal"),
+            _ => (2, "binary"),
+        };
+        self.consume();
+        if !self.chars.peek().is_some_and(|c| c.is_digit(radix)) {
+            return Err(self.error_at_next(format!(
+                "Expected {name} digits after '0{prefix}'"
+            )));
+        }
+        self.digits(radix)?;
+        if let Some(&character) = self.chars.peek()
+            && character.is_ascii_alphanumeric()
+        {
+            return Err(self.error_at_next(format!(
+                "Invalid digit '{character}' in {name} literal"
+            )));
+        }
+
+        let digits = self.current_lexeme[2..].replace('_', "");
+        let bits = u64::from_str_radix(&digits, radix).map_err(|_| self.too_large())?;
+        self.add_token(TokenType::Number(bits as i64));
+        Ok(())
+    }
+
     fn add_token(&mut self, token_type: TokenType) {
         let lexeme = mem::take(&mut self.current_lexeme);
 
@@ -166,10 +524,53 @@ impl<'s> Lexer<'s> {
     }
 }
 
+fn keyword(lexeme: &str) -> Option<TokenType> {
+    match lexeme {
+        "and" => Some(TokenType::And),
+        "or" => Some(TokenType::Or),
+        "true" => Some(TokenType::True),
+        "false" => Some(TokenType::False),
+        "print" => Some(TokenType::Print),
+        "let" => Some(TokenType::Let),
+        "if" => Some(TokenType::If),
+        "else" => Some(TokenType::Else),
+        "while" => Some(TokenType::While),
+        "for" => Some(TokenType::For),
+        "break" => Some(TokenType::Break),
+        "continue" => Some(TokenType::Continue),
+        "fn" => Some(TokenType::Fn),
+        "return" => Some(TokenType::Return),
+        _ => None,
+    }
+}
+
+/// The line and column after `character`, given the columns already taken
+/// on the line. A tab takes four columns.
+fn advance(line: u32, column: u32, character: char) -> (u32, u32) {
+    match character {
+        '\n' => (line + 1, 0),
+        '\t' => (line, column + 4),
+        _ => (line, column + 1),
+    }
+}
+
+/// The position of the `${` that ends an `InterpolationStart` or
+/// `InterpolationMiddle` token, which only records where its text starts.
+pub fn interpolation_opening(token: &Token) -> (u32, u32) {
+    let text = token.lexeme.strip_suffix("${").unwrap_or(&token.lexeme);
+    let (line, column) = text
+        .chars()
+        .fold((token.line, token.column - 1), |(line, column), character| {
+            advance(line, column, character)
+        });
+    (line, column + 1)
+}
+
 pub fn scan(source_code: &str) -> Result<Vec<Token>, SyntaxError> {
     let mut lexer = Lexer {
         chars: source_code.chars().peekable(),
         tokens: Vec::new(),
+        interpolations: Vec::new(),
         current_lexeme: String::new(),
         line: 1,
         column: 0,
@@ -223,12 +624,215 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_floats() {
+        assert_eq!(
+            token_types("2.75 1e-9 .5 1. 2E+3 1.e2"),
+            vec![
+                TokenType::Float(2.75),
+                TokenType::Float(1e-9),
+                TokenType::Float(0.5),
+                TokenType::Float(1.0),
+                TokenType::Float(2000.0),
+                TokenType::Float(100.0),
+                TokenType::Eof
+            ]
+        );
+        assert_eq!(scan("1.)").unwrap()[0].lexeme, "1.");
+
+        let err = scan("1..2").unwrap_err();
+        assert_eq!(err.message, "Unexpected Token: .");
+        assert_eq!((err.line, err.column), (1, 2));
+
+        let err = scan("1e").unwrap_err();
+        assert_eq!(err.message, "Expected digits in exponent");
+        assert_eq!((err.line, err.column), (1, 3));
+        let err = scan("1e+").unwrap_err();
+        assert_eq!(err.message, "Expected digits in exponent");
+        assert_eq!((err.line, err.column), (1, 4));
+
+        let err = scan("1e400").unwrap_err();
+        assert_eq!(err.message, "Float literal 1e400 is out of range");
+        assert_eq!((err.line, err.column), (1, 1));
+    }
+
+    #[test]
+    fn test_radix_and_separated_numbers() {
+        assert_eq!(
+            token_types("0xFF 0o755 0b1010 1_000_000 0x7fff_ffff_ffff_ffff 1_0.2_5e1_0 0"),
+            vec![
+                TokenType::Number(255),
+                TokenType::Number(0o755),
+                TokenType::Number(10),
+                TokenType::Number(1_000_000),
+                TokenType::Number(i64::MAX),
+                TokenType::Float(10.25e10),
+                TokenType::Number(0),
+                TokenType::Eof
+            ]
+        );
+        assert_eq!(scan("0b1_0").unwrap()[0].lexeme, "0b1_0");
+        assert_eq!(
+            token_types("0xFFFF_FFFF_FFFF_FFFF 0x8000_0000_0000_0000"),
+            vec![
+                TokenType::Number(-1),
+                TokenType::Number(i64::MIN),
+                TokenType::Eof
+            ]
+        );
+
+        let error = |input| {
+            let err = scan(input).unwrap_err();
+            (err.message, err.line, err.column)
+        };
+        assert_eq!(
+            error("0x"),
+            ("Expected hex digits after '0x'".to_string(), 1, 3)
+        );
+        assert_eq!(
+            error("1 +\n  0b102"),
+            ("Invalid digit '2' in binary literal".to_string(), 2, 7)
+        );
+        assert_eq!(
+            error("0o78"),
+            ("Invalid digit '8' in octal literal".to_string(), 1, 4)
+        );
+        assert_eq!(
+            error("0x_1"),
+            ("Expected hex digits after '0x'".to_string(), 1, 3)
+        );
+        assert_eq!(
+            error("1_000_;"),
+            ("Expected a digit after '_'".to_string(), 1, 7)
+        );
+        assert_eq!(
+            error("1__0"),
+            ("Expected a digit after '_'".to_string(), 1, 3)
+        );
+        assert_eq!(
+            error("0b1__0"),
+            ("Expected a digit after '_'".to_string(), 1, 5)
+        );
+        assert_eq!(
+            error("0x1_0000_0000_0000_0000"),
+            (
+                "Number 0x1_0000_0000_0000_0000 doesn't fit in 64 bits".to_string(),
+                1,
+                1
+            )
+        );
+    }
+
     #[test]
     fn test_strings() {
         let tokens = scan("\"hello\"").unwrap();
         assert_eq!(tokens[0].token_type, TokenType::String("hello".to_string()));
     }
 
+    #[test]
+    fn test_string_escapes() {
+        let tokens = scan(r#""a\n\t\\\"b" "\u{e9}\u{1F600}\u{41}""#).unwrap();
+        assert_eq!(
+            tokens[0].token_type,
+            TokenType::String("a\n\t\\\"b".to_string())
+        );
+        assert_eq!(tokens[0].lexeme, r#""a\n\t\\\"b""#);
+        assert_eq!(
+            tokens[1].token_type,
+            TokenType::String("\u{e9}\u{1F600}A".to_string())
+        );
+        assert_eq!(tokens[1].lexeme, r#""\u{e9}\u{1F600}\u{41}""#);
+
+        let error = |input| {
+            let err = scan(input).unwrap_err();
+            (err.message, err.line, err.column)
+        };
+        assert_eq!(
+            error(r#"print "ok\q";"#),
+            ("Invalid escape sequence '\\q'".to_string(), 1, 10)
+        );
+        assert_eq!(
+            error("\"a\n\tb\\x\""),
+            ("Invalid escape sequence '\\x'".to_string(), 2, 6)
+        );
+        assert_eq!(
+            error(r#""\u41""#),
+            ("Expected '{' after '\\u'".to_string(), 1, 2)
+        );
+        assert_eq!(
+            error(r#""\u{41""#),
+            ("Unterminated unicode escape".to_string(), 1, 2)
+        );
+        assert_eq!(
+            error(r#""\u{}""#),
+            ("Unicode escape must have 1 to 6 hex digits".to_string(), 1, 2)
+        );
+        assert_eq!(
+            error(r#""\u{D800}""#),
+            ("'\\u{D800}' is not a valid unicode character".to_string(), 1, 2)
+        );
+        assert_eq!(error(r#""abc\"#), ("Invalid String".to_string(), 1, 1));
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        use TokenType::*;
+
+        let tokens = scan(r#""total: ${a + b}!""#).unwrap();
+        let types: Vec<_> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                InterpolationStart("total: ".to_string()),
+                Identifier("a".to_string()),
+                Plus,
+                Identifier("b".to_string()),
+                InterpolationEnd("!".to_string()),
+                Eof,
+            ]
+        );
+        let lexemes: Vec<_> = tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(lexemes, vec![r#""total: ${"#, "a", "+", "b", r#"}!""#, ""]);
+        assert_eq!((tokens[4].line, tokens[4].column), (1, 16));
+
+        // Braces and strings inside the expression, and several expressions.
+        assert_eq!(
+            token_types(r#""${f(fn() { "}" })} and ${"${x}"}""#),
+            vec![
+                InterpolationStart("".to_string()),
+                Identifier("f".to_string()),
+                LeftParen,
+                Fn,
+                LeftParen,
+                RightParen,
+                LeftBrace,
+                String("}".to_string()),
+                RightBrace,
+                RightParen,
+                InterpolationMiddle(" and ".to_string()),
+                InterpolationStart("".to_string()),
+                Identifier("x".to_string()),
+                InterpolationEnd("".to_string()),
+                InterpolationEnd("".to_string()),
+                Eof,
+            ]
+        );
+
+        assert_eq!(
+            token_types(r#""$x \${x} {}""#),
+            vec![String("$x ${x} {}".to_string()), Eof]
+        );
+        assert_eq!(token_types("}"), vec![RightBrace, Eof]);
+
+        let error = |input| {
+            let err = scan(input).unwrap_err();
+            (err.message, err.line, err.column)
+        };
+        assert_eq!(error("print \"a ${b"), ("Invalid String".to_string(), 1, 7));
+        assert_eq!(error("print \"a ${b} c"), ("Invalid String".to_string(), 1, 7));
+        assert_eq!(error("print \"a ${ { }"), ("Invalid String".to_string(), 1, 7));
+    }
+
     #[test]
     fn test_arithmetic_operators() {
         assert_eq!(
@@ -333,6 +937,7 @@ mod tests {
                 TokenType::Number(9),
                 TokenType::And,
                 TokenType::Bang,
+                TokenType::False,
                 TokenType::Eof
             ]
         );
@@ -346,6 +951,104 @@ mod tests {
         assert_eq!(tokens[0].column, 1);
     }
 
+    #[test]
+    fn test_raw_strings() {
+        assert_eq!(
+            token_types(r###"r"C:\new\${x}" r#"say "hi""# r##"a "# b"## r"#""###),
+            vec![
+                TokenType::String(r"C:\new\${x}".to_string()),
+                TokenType::String(r#"say "hi""#.to_string()),
+                TokenType::String(r##"a "# b"##.to_string()),
+                TokenType::String("#".to_string()),
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(
+            token_types("r + rx"),
+            vec![
+                TokenType::Identifier("r".to_string()),
+                TokenType::Plus,
+                TokenType::Identifier("rx".to_string()),
+                TokenType::Eof,
+            ]
+        );
+
+        let tokens = scan("r#\"a\nb\"# x").unwrap();
+        assert_eq!(tokens[0].lexeme, "r#\"a\nb\"#");
+        assert_eq!((tokens[1].line, tokens[1].column), (2, 5));
+
+        let error = |input| {
+            let err = scan(input).unwrap_err();
+            (err.message, err.line, err.column)
+        };
+        assert_eq!(error("x = r#\"a\"\n"), ("Invalid String".to_string(), 1, 5));
+        assert_eq!(
+            error("r#a"),
+            ("Expected '\"' to start a raw string".to_string(), 1, 3)
+        );
+    }
+
+    #[test]
+    fn test_triple_quoted_strings() {
+        let string = |input| match &scan(input).unwrap()[0].token_type {
+            TokenType::String(s) => s.clone(),
+            other => panic!("Expected a string, got {other:?}"),
+        };
+        assert_eq!(string("\"\"\"\n    a\n      b\n\n    c\n    \"\"\""), "a\n  b\n\nc");
+        assert_eq!(string("\"\"\"\n\ta \"quoted\" \\t\n\t\"\"\""), "a \"quoted\" \t");
+        assert_eq!(string("\"\"\"  a\n  b\"\"\""), "a\nb");
+        assert_eq!(string("\"\"\"\n  \n\"\"\""), "");
+        assert_eq!(string("\"\"\"\n\"\"\""), "");
+        assert_eq!(string("\"\"\"\"\"\""), "");
+        assert_eq!(string("\"\"\"a \"\" b\"\"\""), "a \"\" b");
+        // Escaped line breaks and tabs aren't indentation.
+        assert_eq!(string("\"\"\"\n  \\n  a\n  \\tb\n  \"\"\""), "\n  a\n\tb");
+        // A tab doesn't count as the same indentation as spaces.
+        assert_eq!(string("\"\"\"\n  a\n\tb\n  \"\"\""), "  a\n\tb");
+        assert_eq!(string("\"\"\"\n\t  a\n\t b\n\t\"\"\""), " a\nb");
+
+        // Indentation is shared across the pieces of an interpolated string.
+        assert_eq!(
+            token_types("\"\"\"\n    a ${x}\n      ${y} b\n    \"\"\""),
+            vec![
+                TokenType::InterpolationStart("a ".to_string()),
+                TokenType::Identifier("x".to_string()),
+                TokenType::InterpolationMiddle("\n  ".to_string()),
+                TokenType::Identifier("y".to_string()),
+                TokenType::InterpolationEnd(" b".to_string()),
+                TokenType::Eof,
+            ]
+        );
+
+        let tokens = scan("\"\"\"\n  a\n  \"\"\" + 1").unwrap();
+        assert_eq!((tokens[1].line, tokens[1].column), (3, 7));
+        assert_eq!(tokens[0].lexeme, "\"\"\"\n  a\n  \"\"\"");
+    }
+
+    #[test]
+    fn test_long_string_errors() {
+        let error = |input| {
+            let err = scan(input).unwrap_err();
+            (err.message, err.line, err.column)
+        };
+        assert_eq!(
+            error("print \"\"\"\n    fine\n  \tbad \\q\n\"\"\";"),
+            ("Invalid escape sequence '\\q'".to_string(), 3, 11)
+        );
+        assert_eq!(
+            error("print \"line\nline ${1 @ 2}\";"),
+            ("Unexpected Token: @".to_string(), 2, 10)
+        );
+        assert_eq!(
+            error("print \"\"\"\n  a \"\"\n  b \""),
+            ("Invalid String".to_string(), 1, 7)
+        );
+        assert_eq!(
+            error("print \"\"\"\n  ${\n  \"a}\n"),
+            ("Invalid String".to_string(), 3, 3)
+        );
+    }
+
     #[test]
     fn test_multiple_tokens_per_line() {
         let tokens = scan("1 + 2").unwrap();
@@ -369,4 +1072,160 @@ mod tests {
         assert_eq!(err.line, 1);
         assert_eq!(err.column, 1);
     }
+
+    #[test]
+    fn test_number_range() {
+        assert_eq!(
+            token_types("9223372036854775807"),
+            vec![TokenType::Number(i64::MAX), TokenType::Eof]
+        );
+        let err = scan("1 +\n  9223372036854775808").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Number 9223372036854775808 doesn't fit in 64 bits"
+        );
+        assert_eq!((err.line, err.column), (2, 3));
+    }
+
+    #[test]
+    fn test_equality_operators() {
+        assert_eq!(
+            token_types("= == != ==="),
+            vec![
+                TokenType::Equal,
+                TokenType::EqualEqual,
+                TokenType::BangEqual,
+                TokenType::EqualEqual,
+                TokenType::Equal,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keywords() {
+        assert_eq!(
+            token_types("true false and or"),
+            vec![
+                TokenType::True,
+                TokenType::False,
+                TokenType::And,
+                TokenType::Or,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(
+            token_types("x _tmp value2 android truex"),
+            vec![
+                TokenType::Identifier("x".to_string()),
+                TokenType::Identifier("_tmp".to_string()),
+                TokenType::Identifier("value2".to_string()),
+                TokenType::Identifier("android".to_string()),
+                TokenType::Identifier("truex".to_string()),
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identifier_lexeme_and_position() {
+        let tokens = scan("1 + foo").unwrap();
+        assert_eq!(tokens[2].lexeme, "foo");
+        assert_eq!(tokens[2].column, 5);
+    }
+
+    #[test]
+    fn test_statement_tokens() {
+        assert_eq!(
+            token_types("print 1; x;"),
+            vec![
+                TokenType::Print,
+                TokenType::Number(1),
+                TokenType::Semicolon,
+                TokenType::Identifier("x".to_string()),
+                TokenType::Semicolon,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_declaration_tokens() {
+        assert_eq!(
+            token_types("{ let x = 1; }"),
+            vec![
+                TokenType::LeftBrace,
+                TokenType::Let,
+                TokenType::Identifier("x".to_string()),
+                TokenType::Equal,
+                TokenType::Number(1),
+                TokenType::Semicolon,
+                TokenType::RightBrace,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_control_flow_keywords() {
+        assert_eq!(
+            token_types("if else while for break continue format"),
+            vec![
+                TokenType::If,
+                TokenType::Else,
+                TokenType::While,
+                TokenType::For,
+                TokenType::Break,
+                TokenType::Continue,
+                TokenType::Identifier("format".to_string()),
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_tokens() {
+        assert_eq!(
+            token_types("fn f(a, b) { return a; }"),
+            vec![
+                TokenType::Fn,
+                TokenType::Identifier("f".to_string()),
+                TokenType::LeftParen,
+                TokenType::Identifier("a".to_string()),
+                TokenType::Comma,
+                TokenType::Identifier("b".to_string()),
+                TokenType::RightParen,
+                TokenType::LeftBrace,
+                TokenType::Return,
+                TokenType::Identifier("a".to_string()),
+                TokenType::Semicolon,
+                TokenType::RightBrace,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotation_tokens() {
+        assert_eq!(
+            token_types("fn(a: int) -> int - 1"),
+            vec![
+                TokenType::Fn,
+                TokenType::LeftParen,
+                TokenType::Identifier("a".to_string()),
+                TokenType::Colon,
+                TokenType::Identifier("int".to_string()),
+                TokenType::RightParen,
+                TokenType::Arrow,
+                TokenType::Identifier("int".to_string()),
+                TokenType::Minus,
+                TokenType::Number(1),
+                TokenType::Eof
+            ]
+        );
+    }
 }
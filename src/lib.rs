@@ -1,7 +1,17 @@
-use std::fmt;
+use std::{cell::Cell, fmt, rc::Rc};
 
+pub mod chunk;
+pub mod codegen;
+pub mod compiler;
+pub mod disassembler;
+pub mod interpreter;
 pub mod lexer;
 pub mod parser;
+pub mod printer;
+pub mod resolver;
+pub mod serialize;
+pub mod typeck;
+pub mod vm;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
@@ -11,6 +21,7 @@ pub enum TokenType {
     Slash,
     Bang,
     BangEqual,
+    Equal,
     EqualEqual,
     Greater,
     GreaterEqual,
@@ -20,18 +31,45 @@ pub enum TokenType {
     And,
     Or,
 
+    Identifier(String),
     String(String),
-    Number(i32),
+    /// The text of an interpolated string before its first `${`. The tokens
+    /// of the embedded expression follow.
+    InterpolationStart(String),
+    /// The text between the `}` closing one embedded expression and the `${`
+    /// opening the next.
+    InterpolationMiddle(String),
+    /// The text after the last embedded expression, up to the closing quote.
+    InterpolationEnd(String),
+    Number(i64),
+    Float(f64),
     True,
     False,
 
+    Print,
+    Let,
+    If,
+    Else,
+    While,
+    For,
+    Break,
+    Continue,
+    Fn,
+    Return,
+
     LeftParen,
     RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Semicolon,
+    Colon,
+    Arrow,
 
     Eof,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
@@ -64,24 +102,184 @@ pub enum BinaryOp {
     Or,
 }
 
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Expr {
-    Number(i32),
+    Number(i64),
+    Float(f64),
     String(String),
     Bool(bool),
 
     Unary {
         operator: UnaryOp,
         operand: Box<Expr>,
+        line: u32,
+        column: u32,
     },
 
     Binary {
         left: Box<Expr>,
         operator: BinaryOp,
         right: Box<Expr>,
+        line: u32,
+        column: u32,
     },
 
     Grouping(Box<Expr>),
+
+    /// `depth` is filled in by the resolver: the number of scopes between
+    /// the reference and its declaration, or `None` for a global.
+    Variable {
+        name: Token,
+        depth: Cell<Option<usize>>,
+    },
+
+    Assign {
+        name: Token,
+        value: Box<Expr>,
+        depth: Cell<Option<usize>>,
+    },
+
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+        line: u32,
+        column: u32,
+    },
+
+    Function(Rc<Function>),
+
+    /// `"total: ${a + b}"`. Each expression part is converted to a string
+    /// the way `print` would show it.
+    Interpolation(Vec<InterpolationPart>),
+}
+
+#[derive(Debug)]
+pub enum InterpolationPart {
+    Literal(String),
+    Expr(Expr),
+}
+
+#[derive(Debug)]
+pub struct Function {
+    /// `None` for anonymous function expressions.
+    pub name: Option<Token>,
+    pub params: Vec<Token>,
+    /// The annotation of each parameter, if it has one.
+    pub param_types: Vec<Option<TypeExpr>>,
+    pub return_type: Option<TypeExpr>,
+    pub body: Vec<Stmt>,
+}
+
+/// A type annotation. Annotations are only used by the type checker.
+#[derive(Debug)]
+pub enum TypeExpr {
+    /// `int`, `float`, `bool`, `string` or `nil`.
+    Named(Token),
+
+    /// `fn(int, int) -> bool`
+    Function {
+        params: Vec<TypeExpr>,
+        ret: Box<TypeExpr>,
+        line: u32,
+        column: u32,
+    },
+}
+
+#[derive(Debug)]
+pub enum Stmt {
+    Expression(Expr),
+
+    Print {
+        value: Expr,
+        line: u32,
+        column: u32,
+    },
+
+    Let {
+        name: Token,
+        annotation: Option<TypeExpr>,
+        initializer: Expr,
+    },
+
+    Block(Vec<Stmt>),
+
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+
+    For {
+        initializer: Option<Box<Stmt>>,
+        condition: Option<Expr>,
+        increment: Option<Expr>,
+        body: Box<Stmt>,
+    },
+
+    Break {
+        line: u32,
+        column: u32,
+    },
+
+    Continue {
+        line: u32,
+        column: u32,
+    },
+
+    Function(Rc<Function>),
+
+    Return {
+        value: Option<Expr>,
+        line: u32,
+        column: u32,
+    },
+}
+
+/// Whether running `statements` always ends in a `return`. Loops don't
+/// count, even `while (true)`.
+pub fn always_returns(statements: &[Stmt]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Stmt::Return { .. } => true,
+        Stmt::Block(statements) => always_returns(statements),
+        Stmt::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => {
+            always_returns(std::slice::from_ref(then_branch))
+                && always_returns(std::slice::from_ref(else_branch))
+        }
+        _ => false,
+    })
+}
+
+#[derive(Debug)]
+pub struct Program {
+    pub statements: Vec<Stmt>,
 }
 
 #[derive(Debug)]
@@ -99,12 +297,37 @@ impl fmt::Display for SyntaxError {
 
 impl SyntaxError {
     fn new(message: String, line: u32, column: u32) -> SyntaxError {
-        return SyntaxError {
+        SyntaxError {
             message,
             line,
             column,
-        };
+        }
     }
 }
 
 impl std::error::Error for SyntaxError {}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl RuntimeError {
+    fn new(message: String, line: u32, column: u32) -> RuntimeError {
+        RuntimeError {
+            message,
+            line,
+            column,
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
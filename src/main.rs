@@ -1,9 +1,54 @@
-use std::fs;
+use std::{env, fs, io, path::Path};
 
-use lang::{lexer, parser::Parser};
+use lang::{
+    codegen, compiler, disassembler, interpreter::Interpreter, lexer, parser::Parser, resolver,
+    serialize, typeck, vm::Vm,
+};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let contents = match fs::read_to_string("data/source.lg") {
+    let mut use_vm = false;
+    let mut typecheck = false;
+    let mut dump_bytecode = false;
+    let mut compile_output = None;
+    let mut emit = None;
+    let mut output = None;
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--vm" => use_vm = true,
+            "--typecheck" => typecheck = true,
+            "--dump-bytecode" => dump_bytecode = true,
+            "--compile" => match args.next() {
+                Some(file) => compile_output = Some(file),
+                None => return Err("--compile expects an output file".into()),
+            },
+            "--emit" => match args.next() {
+                Some(target) => emit = Some(target),
+                None => return Err("--emit expects a target".into()),
+            },
+            "-o" => match args.next() {
+                Some(file) => output = Some(file),
+                None => return Err("-o expects an output file".into()),
+            },
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(|| "data/source.lg".to_string());
+
+    // Files written by `--compile` skip straight to the VM.
+    if path.ends_with(".lgc") {
+        let script = serialize::deserialize(&fs::read(path)?)?;
+        if dump_bytecode {
+            print!("{}", disassembler::disassemble(&script));
+        } else {
+            let mut vm = Vm::new(io::stdout());
+            vm.run(script)?;
+        }
+        return Ok(());
+    }
+
+    let contents = match fs::read_to_string(path) {
         Ok(contents) => contents,
         Err(error) => {
             return Err(Box::new(error));
@@ -13,9 +58,48 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let tokens = lexer::scan(&contents)?;
 
     let mut parser = Parser::new(tokens);
-    let expr = parser.parse()?;
+    let program = parser.parse_program()?;
+
+    for warning in resolver::resolve(&program)? {
+        eprintln!("Warning: {}", warning);
+    }
+    if typecheck {
+        typeck::check(&program)?;
+    }
 
-    println!("{:#?}", expr);
+    if let Some(target) = emit {
+        let code = match target.as_str() {
+            "llvm" => codegen::llvm::emit(&program)?,
+            "c" => codegen::c::emit(&program)?,
+            "asm" => codegen::x86_64::emit(&program)?,
+            "wat" => codegen::wat::emit(&program)?,
+            _ => return Err(format!("Unknown --emit target '{target}'").into()),
+        };
+        match output {
+            Some(output) => {
+                // Generated C includes its runtime from the same directory.
+                if target == "c" {
+                    let header = Path::new(&output).with_file_name(codegen::c::RUNTIME_HEADER_NAME);
+                    fs::write(header, codegen::c::RUNTIME_HEADER)?;
+                }
+                fs::write(output, code)?;
+            }
+            None => print!("{code}"),
+        }
+    } else if let Some(output) = compile_output {
+        let script = compiler::compile(&program)?;
+        fs::write(output, serialize::serialize(&script))?;
+    } else if dump_bytecode {
+        let script = compiler::compile(&program)?;
+        print!("{}", disassembler::disassemble(&script));
+    } else if use_vm {
+        let script = compiler::compile(&program)?;
+        let mut vm = Vm::new(io::stdout());
+        vm.run(script)?;
+    } else {
+        let mut interpreter = Interpreter::new(io::stdout());
+        interpreter.run(&program)?;
+    }
 
     Ok(())
 }
@@ -1,27 +1,517 @@
-use crate::{BinaryOp, Expr, SyntaxError, Token, TokenType, UnaryOp};
+use std::{cell::Cell, mem, rc::Rc};
+
+use crate::{
+    BinaryOp, Expr, Function, InterpolationPart, Program, Stmt, SyntaxError, Token, TokenType,
+    TypeExpr, UnaryOp, lexer,
+};
+
+/// Upper bound on call arguments and function parameters.
+pub const MAX_ARGUMENTS: usize = 255;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // Number of loops enclosing the statement being parsed, used to reject
+    // `break` and `continue` outside of a loop body.
+    loop_depth: usize,
+    // Number of functions enclosing the statement being parsed, used to
+    // reject `return` at the top level.
+    function_depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            loop_depth: 0,
+            function_depth: 0,
+        }
     }
 
     pub fn parse(&mut self) -> Result<Expr, SyntaxError> {
-        self.expression()
+        let expr = self.expression()?;
+
+        if !self.is_at_end() {
+            return Err(self.error(&format!(
+                "Unexpected token after expression: {:?}",
+                self.peek().token_type
+            )));
+        }
+
+        Ok(expr)
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, SyntaxError> {
+        let mut statements = Vec::new();
+
+        while !self.is_at_end() {
+            statements.push(self.declaration()?);
+        }
+
+        Ok(Program { statements })
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, SyntaxError> {
+        match self.keyword_declaration()? {
+            Some(statement) => Ok(statement),
+            None => self.expression_statement(),
+        }
+    }
+
+    /// Parses a declaration or a statement that starts with a keyword or
+    /// `{`. Returns `None`, consuming nothing, before an expression
+    /// statement.
+    fn keyword_declaration(&mut self) -> Result<Option<Stmt>, SyntaxError> {
+        if self.match_tokens(&[TokenType::Let]) {
+            return self.let_declaration().map(Some);
+        }
+        if self.check(&TokenType::Fn) && self.check_next(&TokenType::Identifier(String::new())) {
+            self.advance();
+            let name = self.advance().clone();
+            return Ok(Some(Stmt::Function(self.function(Some(name))?)));
+        }
+
+        self.keyword_statement()
+    }
+
+    fn function(&mut self, name: Option<Token>) -> Result<Rc<Function>, SyntaxError> {
+        self.consume(TokenType::LeftParen, "Expected '(' before parameters")?;
+
+        let mut params = Vec::new();
+        let mut param_types = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(
+                        self.error(&format!("Can't have more than {MAX_ARGUMENTS} parameters"))
+                    );
+                }
+                params.push(
+                    self.consume(
+                        TokenType::Identifier(String::new()),
+                        "Expected parameter name",
+                    )?
+                    .clone(),
+                );
+                param_types.push(self.annotation()?);
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
+        let return_type = if self.match_tokens(&[TokenType::Arrow]) {
+            Some(self.type_expr()?)
+        } else {
+            None
+        };
+        self.consume(TokenType::LeftBrace, "Expected '{' before function body")?;
+
+        // A function body starts a fresh context: loops around the
+        // declaration can't be targeted by `break` or `continue` inside it.
+        let loop_depth = mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let body = self.function_body();
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+
+        Ok(Rc::new(Function {
+            name,
+            params,
+            param_types,
+            return_type,
+            body: body?,
+        }))
+    }
+
+    /// Like [`Parser::block`], except that a final expression without a
+    /// `;` is returned from the function: `fn(x) { x }`.
+    fn function_body(&mut self) -> Result<Vec<Stmt>, SyntaxError> {
+        let mut statements = Vec::new();
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.keyword_declaration()? {
+                statements.push(statement);
+                continue;
+            }
+
+            let (line, column) = (self.peek().line, self.peek().column);
+            let value = self.expression()?;
+            if self.check(&TokenType::RightBrace) {
+                statements.push(Stmt::Return {
+                    value: Some(value),
+                    line,
+                    column,
+                });
+            } else {
+                self.consume(TokenType::Semicolon, "Expected ';' after expression")?;
+                statements.push(Stmt::Expression(value));
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after block")?;
+        Ok(statements)
+    }
+
+    fn let_declaration(&mut self) -> Result<Stmt, SyntaxError> {
+        let name = self
+            .consume(
+                TokenType::Identifier(String::new()),
+                "Expected variable name after 'let'",
+            )?
+            .clone();
+        let annotation = self.annotation()?;
+        self.consume(TokenType::Equal, "Expected '=' after variable name")?;
+        let initializer = self.expression()?;
+        self.consume(
+            TokenType::Semicolon,
+            "Expected ';' after variable declaration",
+        )?;
+        Ok(Stmt::Let {
+            name,
+            annotation,
+            initializer,
+        })
+    }
+
+    /// An optional `: type` after a variable or parameter name.
+    fn annotation(&mut self) -> Result<Option<TypeExpr>, SyntaxError> {
+        if self.match_tokens(&[TokenType::Colon]) {
+            Ok(Some(self.type_expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn type_expr(&mut self) -> Result<TypeExpr, SyntaxError> {
+        if !self.match_tokens(&[TokenType::Fn]) {
+            let name = self.consume(TokenType::Identifier(String::new()), "Expected type")?;
+            return Ok(TypeExpr::Named(name.clone()));
+        }
+
+        let (line, column) = self.previous_position();
+        self.consume(TokenType::LeftParen, "Expected '(' after 'fn'")?;
+        let mut params = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                params.push(self.type_expr()?);
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after parameter types")?;
+        self.consume(TokenType::Arrow, "Expected '->' after parameter types")?;
+        let ret = Box::new(self.type_expr()?);
+        Ok(TypeExpr::Function {
+            params,
+            ret,
+            line,
+            column,
+        })
+    }
+
+    fn statement(&mut self) -> Result<Stmt, SyntaxError> {
+        match self.keyword_statement()? {
+            Some(statement) => Ok(statement),
+            None => self.expression_statement(),
+        }
+    }
+
+    /// Like [`Parser::keyword_declaration`], without the declarations.
+    fn keyword_statement(&mut self) -> Result<Option<Stmt>, SyntaxError> {
+        let statement = if self.match_tokens(&[TokenType::Print]) {
+            self.print_statement()
+        } else if self.match_tokens(&[TokenType::LeftBrace]) {
+            self.block().map(Stmt::Block)
+        } else if self.match_tokens(&[TokenType::If]) {
+            self.if_statement()
+        } else if self.match_tokens(&[TokenType::While]) {
+            self.while_statement()
+        } else if self.match_tokens(&[TokenType::For]) {
+            self.for_statement()
+        } else if self.match_tokens(&[TokenType::Break, TokenType::Continue]) {
+            self.loop_jump_statement()
+        } else if self.match_tokens(&[TokenType::Return]) {
+            self.return_statement()
+        } else {
+            return Ok(None);
+        };
+        statement.map(Some)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, SyntaxError> {
+        self.consume(TokenType::LeftParen, "Expected '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after if condition")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_tokens(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, SyntaxError> {
+        self.consume(TokenType::LeftParen, "Expected '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after while condition")?;
+
+        let body = Box::new(self.loop_body()?);
+
+        Ok(Stmt::While { condition, body })
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt, SyntaxError> {
+        self.consume(TokenType::LeftParen, "Expected '(' after 'for'")?;
+
+        let initializer = if self.match_tokens(&[TokenType::Semicolon]) {
+            None
+        } else if self.match_tokens(&[TokenType::Let]) {
+            Some(Box::new(self.let_declaration()?))
+        } else {
+            Some(Box::new(self.expression_statement()?))
+        };
+
+        let condition = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expected ';' after loop condition")?;
+
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expected ')' after for clauses")?;
+
+        let body = Box::new(self.loop_body()?);
+
+        Ok(Stmt::For {
+            initializer,
+            condition,
+            increment,
+            body,
+        })
+    }
+
+    fn loop_body(&mut self) -> Result<Stmt, SyntaxError> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn loop_jump_statement(&mut self) -> Result<Stmt, SyntaxError> {
+        let (line, column) = self.previous_position();
+        let is_break = matches!(self.previous().token_type, TokenType::Break);
+        let keyword = if is_break { "break" } else { "continue" };
+
+        if self.loop_depth == 0 {
+            return Err(SyntaxError::new(
+                format!("'{keyword}' outside of a loop"),
+                line,
+                column,
+            ));
+        }
+
+        self.consume(
+            TokenType::Semicolon,
+            &format!("Expected ';' after '{keyword}'"),
+        )?;
+
+        if is_break {
+            Ok(Stmt::Break { line, column })
+        } else {
+            Ok(Stmt::Continue { line, column })
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, SyntaxError> {
+        let mut statements = Vec::new();
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            statements.push(self.declaration()?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after block")?;
+        Ok(statements)
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, SyntaxError> {
+        let (line, column) = self.previous_position();
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expected ';' after value")?;
+        Ok(Stmt::Print {
+            value,
+            line,
+            column,
+        })
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, SyntaxError> {
+        let (line, column) = self.previous_position();
+
+        if self.function_depth == 0 {
+            return Err(SyntaxError::new(
+                "'return' outside of a function".to_string(),
+                line,
+                column,
+            ));
+        }
+
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expected ';' after return value")?;
+
+        Ok(Stmt::Return {
+            value,
+            line,
+            column,
+        })
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, SyntaxError> {
+        let expr = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expected ';' after expression")?;
+        Ok(Stmt::Expression(expr))
     }
 
     fn expression(&mut self) -> Result<Expr, SyntaxError> {
-        self.term()
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr, SyntaxError> {
+        let expr = self.or()?;
+
+        if self.match_tokens(&[TokenType::Equal]) {
+            let (line, column) = self.previous_position();
+            let value = self.assignment()?;
+
+            return match expr {
+                Expr::Variable { name, .. } => Ok(Expr::Assign {
+                    name,
+                    value: Box::new(value),
+                    depth: Cell::new(None),
+                }),
+                _ => Err(SyntaxError::new(
+                    "Invalid assignment target".to_string(),
+                    line,
+                    column,
+                )),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.and()?;
+
+        while self.match_tokens(&[TokenType::Or]) {
+            let (line, column) = self.previous_position();
+            let right = self.and()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::Or,
+                right: Box::new(right),
+                line,
+                column,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.equality()?;
+
+        while self.match_tokens(&[TokenType::And]) {
+            let (line, column) = self.previous_position();
+            let right = self.equality()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator: BinaryOp::And,
+                right: Box::new(right),
+                line,
+                column,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.comparison()?;
+
+        while self.match_tokens(&[TokenType::EqualEqual, TokenType::BangEqual]) {
+            let (line, column) = self.previous_position();
+            let operator = match self.previous().token_type {
+                TokenType::EqualEqual => BinaryOp::Equal,
+                TokenType::BangEqual => BinaryOp::NotEqual,
+                _ => unreachable!(),
+            };
+            let right = self.comparison()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                line,
+                column,
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.term()?;
+
+        while self.match_tokens(&[
+            TokenType::Less,
+            TokenType::LessEqual,
+            TokenType::Greater,
+            TokenType::GreaterEqual,
+        ]) {
+            let (line, column) = self.previous_position();
+            let operator = match self.previous().token_type {
+                TokenType::Less => BinaryOp::Less,
+                TokenType::LessEqual => BinaryOp::LessEqual,
+                TokenType::Greater => BinaryOp::Greater,
+                TokenType::GreaterEqual => BinaryOp::GreaterEqual,
+                _ => unreachable!(),
+            };
+            let right = self.term()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+                line,
+                column,
+            };
+        }
+
+        Ok(expr)
     }
 
     fn term(&mut self) -> Result<Expr, SyntaxError> {
         let mut expr = self.factor()?;
 
         while self.match_tokens(&[TokenType::Plus, TokenType::Minus]) {
+            let (line, column) = self.previous_position();
             let operator = match self.previous().token_type {
                 TokenType::Plus => BinaryOp::Add,
                 TokenType::Minus => BinaryOp::Sub,
@@ -32,6 +522,8 @@ impl Parser {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                line,
+                column,
             };
         }
 
@@ -42,6 +534,7 @@ impl Parser {
         let mut expr = self.unary()?;
 
         while self.match_tokens(&[TokenType::Star, TokenType::Slash]) {
+            let (line, column) = self.previous_position();
             let operator = match self.previous().token_type {
                 TokenType::Star => BinaryOp::Mul,
                 TokenType::Slash => BinaryOp::Div,
@@ -52,6 +545,8 @@ impl Parser {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
+                line,
+                column,
             };
         }
 
@@ -60,6 +555,7 @@ impl Parser {
 
     fn unary(&mut self) -> Result<Expr, SyntaxError> {
         if self.match_tokens(&[TokenType::Minus, TokenType::Bang]) {
+            let (line, column) = self.previous_position();
             let operator = match self.previous().token_type {
                 TokenType::Minus => UnaryOp::Negate,
                 TokenType::Bang => UnaryOp::Not,
@@ -69,10 +565,45 @@ impl Parser {
             return Ok(Expr::Unary {
                 operator,
                 operand: Box::new(operand),
+                line,
+                column,
             });
         }
 
-        self.primary()
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = self.primary()?;
+
+        while self.match_tokens(&[TokenType::LeftParen]) {
+            let (line, column) = self.previous_position();
+            let mut arguments = Vec::new();
+
+            if !self.check(&TokenType::RightParen) {
+                loop {
+                    if arguments.len() >= MAX_ARGUMENTS {
+                        return Err(
+                            self.error(&format!("Can't have more than {MAX_ARGUMENTS} arguments"))
+                        );
+                    }
+                    arguments.push(self.expression()?);
+                    if !self.match_tokens(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightParen, "Expected ')' after arguments")?;
+
+            expr = Expr::Call {
+                callee: Box::new(expr),
+                arguments,
+                line,
+                column,
+            };
+        }
+
+        Ok(expr)
     }
 
     fn primary(&mut self) -> Result<Expr, SyntaxError> {
@@ -88,11 +619,29 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Number(val))
             }
+            TokenType::Float(n) => {
+                let val = *n;
+                self.advance();
+                Ok(Expr::Float(val))
+            }
             TokenType::String(s) => {
                 let val = s.clone();
                 self.advance();
                 Ok(Expr::String(val))
             }
+            TokenType::InterpolationStart(s) => {
+                let val = s.clone();
+                self.advance();
+                self.interpolation(val)
+            }
+            TokenType::Identifier(_) => {
+                let name = token.clone();
+                self.advance();
+                Ok(Expr::Variable {
+                    name,
+                    depth: Cell::new(None),
+                })
+            }
             TokenType::True => {
                 self.advance();
                 Ok(Expr::Bool(true))
@@ -101,6 +650,10 @@ impl Parser {
                 self.advance();
                 Ok(Expr::Bool(false))
             }
+            TokenType::Fn => {
+                self.advance();
+                Ok(Expr::Function(self.function(None)?))
+            }
             TokenType::LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
@@ -111,6 +664,45 @@ impl Parser {
         }
     }
 
+    /// Parses the rest of an interpolated string after its start token:
+    /// expressions separated by middle tokens, up to the end token. Empty
+    /// pieces of text are left out.
+    fn interpolation(&mut self, start: String) -> Result<Expr, SyntaxError> {
+        let mut parts = Vec::new();
+        let mut text = start;
+        loop {
+            if !text.is_empty() {
+                parts.push(InterpolationPart::Literal(text));
+            }
+            if matches!(
+                self.peek().token_type,
+                TokenType::InterpolationMiddle(_) | TokenType::InterpolationEnd(_)
+            ) {
+                let (line, column) = lexer::interpolation_opening(self.previous());
+                return Err(SyntaxError::new(
+                    "Expected expression inside '${}'".to_string(),
+                    line,
+                    column,
+                ));
+            }
+            parts.push(InterpolationPart::Expr(self.expression()?));
+
+            text = match &self.peek().token_type {
+                TokenType::InterpolationMiddle(s) => s.clone(),
+                TokenType::InterpolationEnd(s) => {
+                    let s = s.clone();
+                    self.advance();
+                    if !s.is_empty() {
+                        parts.push(InterpolationPart::Literal(s));
+                    }
+                    return Ok(Expr::Interpolation(parts));
+                }
+                _ => return Err(self.error("Expected '}' after interpolated expression")),
+            };
+            self.advance();
+        }
+    }
+
     fn match_tokens(&mut self, types: &[TokenType]) -> bool {
         for t in types {
             if self.check(t) {
@@ -129,6 +721,15 @@ impl Parser {
             == std::mem::discriminant(token_type)
     }
 
+    fn check_next(&self, token_type: &TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => {
+                std::mem::discriminant(&token.token_type) == std::mem::discriminant(token_type)
+            }
+            None => false,
+        }
+    }
+
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -148,6 +749,11 @@ impl Parser {
         &self.tokens[self.current - 1]
     }
 
+    fn previous_position(&self) -> (u32, u32) {
+        let token = self.previous();
+        (token.line, token.column)
+    }
+
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token, SyntaxError> {
         if self.check(&token_type) {
             return Ok(self.advance());
@@ -239,7 +845,7 @@ mod tests {
     fn test_unary_negation() {
         let expr = parse_expr("-5");
         match expr {
-            Expr::Unary { operator: UnaryOp::Negate, operand } => {
+            Expr::Unary { operator: UnaryOp::Negate, operand, .. } => {
                 assert!(matches!(*operand, Expr::Number(5)));
             }
             _ => panic!("Expected Unary Negate"),
@@ -251,7 +857,7 @@ mod tests {
         // 1 + 2 + 3 should parse as (1 + 2) + 3
         let expr = parse_expr("1 + 2 + 3");
         match expr {
-            Expr::Binary { operator: BinaryOp::Add, left, right } => {
+            Expr::Binary { operator: BinaryOp::Add, left, right, .. } => {
                 match *left {
                     Expr::Binary { operator: BinaryOp::Add, .. } => {}
                     _ => panic!("Expected Add as left operand"),
@@ -261,4 +867,470 @@ mod tests {
             _ => panic!("Expected Binary Add at top"),
         }
     }
+
+    #[test]
+    fn test_comparison_precedence() {
+        // 1 + 2 < 4 should parse as (1 + 2) < 4
+        let expr = parse_expr("1 + 2 < 4");
+        match expr {
+            Expr::Binary { operator: BinaryOp::Less, left, .. } => {
+                match *left {
+                    Expr::Binary { operator: BinaryOp::Add, .. } => {}
+                    _ => panic!("Expected Add as left operand"),
+                }
+            }
+            _ => panic!("Expected Binary Less at top"),
+        }
+    }
+
+    #[test]
+    fn test_equality_precedence() {
+        // 1 < 2 != 3 >= 4 should parse as (1 < 2) != (3 >= 4)
+        let expr = parse_expr("1 < 2 != 3 >= 4");
+        match expr {
+            Expr::Binary { operator: BinaryOp::NotEqual, left, right, .. } => {
+                assert!(matches!(*left, Expr::Binary { operator: BinaryOp::Less, .. }));
+                assert!(matches!(*right, Expr::Binary { operator: BinaryOp::GreaterEqual, .. }));
+            }
+            _ => panic!("Expected Binary NotEqual at top"),
+        }
+    }
+
+    #[test]
+    fn test_logical_precedence() {
+        // 1 or 2 and 3 should parse as 1 or (2 and 3)
+        let expr = parse_expr("1 or 2 and 3");
+        match expr {
+            Expr::Binary { operator: BinaryOp::Or, right, .. } => {
+                match *right {
+                    Expr::Binary { operator: BinaryOp::And, .. } => {}
+                    _ => panic!("Expected And as right operand"),
+                }
+            }
+            _ => panic!("Expected Binary Or at top"),
+        }
+    }
+
+    #[test]
+    fn test_and_binds_looser_than_equality() {
+        // 1 != 2 and 3 > 4 should parse as (1 != 2) and (3 > 4)
+        let expr = parse_expr("1 != 2 and 3 > 4");
+        match expr {
+            Expr::Binary { operator: BinaryOp::And, left, right, .. } => {
+                assert!(matches!(*left, Expr::Binary { operator: BinaryOp::NotEqual, .. }));
+                assert!(matches!(*right, Expr::Binary { operator: BinaryOp::Greater, .. }));
+            }
+            _ => panic!("Expected Binary And at top"),
+        }
+    }
+
+    #[test]
+    fn test_comparison_left_associativity() {
+        // 1 < 2 < 3 should parse as (1 < 2) < 3
+        let expr = parse_expr("1 < 2 < 3");
+        match expr {
+            Expr::Binary { operator: BinaryOp::Less, left, right, .. } => {
+                assert!(matches!(*left, Expr::Binary { operator: BinaryOp::Less, .. }));
+                assert!(matches!(*right, Expr::Number(3)));
+            }
+            _ => panic!("Expected Binary Less at top"),
+        }
+    }
+
+    #[test]
+    fn test_logical_left_associativity() {
+        // 1 or 2 or 3 should parse as (1 or 2) or 3
+        let expr = parse_expr("1 or 2 or 3");
+        match expr {
+            Expr::Binary { operator: BinaryOp::Or, left, .. } => {
+                assert!(matches!(*left, Expr::Binary { operator: BinaryOp::Or, .. }));
+            }
+            _ => panic!("Expected Binary Or at top"),
+        }
+    }
+
+    #[test]
+    fn test_equality_and_booleans() {
+        // true == 1 < 2 should parse as true == (1 < 2)
+        let expr = parse_expr("true == 1 < 2");
+        match expr {
+            Expr::Binary { operator: BinaryOp::Equal, left, right, .. } => {
+                assert!(matches!(*left, Expr::Bool(true)));
+                assert!(matches!(*right, Expr::Binary { operator: BinaryOp::Less, .. }));
+            }
+            _ => panic!("Expected Binary Equal at top"),
+        }
+    }
+
+    #[test]
+    fn test_variable() {
+        let expr = parse_expr("1 + x");
+        match expr {
+            Expr::Binary { right, .. } => match *right {
+                Expr::Variable { name, .. } => {
+                    assert_eq!(name.lexeme, "x");
+                    assert_eq!(name.column, 5);
+                }
+                _ => panic!("Expected Variable as right operand"),
+            },
+            _ => panic!("Expected Binary at top"),
+        }
+    }
+
+    #[test]
+    fn test_trailing_tokens_rejected() {
+        let tokens = lexer::scan("1 2 3").unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert!(err.message.contains("Unexpected token after expression"));
+        assert_eq!((err.line, err.column), (1, 3));
+
+        let tokens = lexer::scan("(1) )").unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!((err.line, err.column), (1, 5));
+
+        let tokens = lexer::scan("1 +\n2\n  3").unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!((err.line, err.column), (3, 3));
+    }
+
+    fn parse_program(input: &str) -> Result<Program, SyntaxError> {
+        let tokens = lexer::scan(input).unwrap();
+        Parser::new(tokens).parse_program()
+    }
+
+    #[test]
+    fn test_program_statements() {
+        let program = parse_program("print 1 + 2;\n\"a\";").unwrap();
+        assert_eq!(program.statements.len(), 2);
+        match &program.statements[0] {
+            Stmt::Print { value, line, column } => {
+                assert!(matches!(value, Expr::Binary { operator: BinaryOp::Add, .. }));
+                assert_eq!((*line, *column), (1, 1));
+            }
+            _ => panic!("Expected Print statement"),
+        }
+        assert!(matches!(&program.statements[1], Stmt::Expression(Expr::String(_))));
+    }
+
+    #[test]
+    fn test_empty_program() {
+        assert!(parse_program("").unwrap().statements.is_empty());
+    }
+
+    #[test]
+    fn test_missing_semicolon() {
+        let err = parse_program("print 1\nprint 2;").unwrap_err();
+        assert_eq!(err.message, "Expected ';' after value");
+        assert_eq!((err.line, err.column), (2, 1));
+
+        let err = parse_program("1 + 2").unwrap_err();
+        assert_eq!(err.message, "Expected ';' after expression");
+    }
+
+    #[test]
+    fn test_let_declaration() {
+        let program = parse_program("let x = 1 + 2;").unwrap();
+        match &program.statements[0] {
+            Stmt::Let { name, annotation: None, initializer } => {
+                assert_eq!(name.lexeme, "x");
+                assert!(matches!(initializer, Expr::Binary { operator: BinaryOp::Add, .. }));
+            }
+            _ => panic!("Expected Let statement"),
+        }
+
+        let err = parse_program("let = 1;").unwrap_err();
+        assert_eq!(err.message, "Expected variable name after 'let'");
+        assert_eq!(err.column, 5);
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let program = parse_program("let f: fn(int) -> bool = fn(n: int, m) -> bool { n < m };").unwrap();
+        match &program.statements[0] {
+            Stmt::Let { annotation: Some(TypeExpr::Function { params, ret, line, column }), initializer: Expr::Function(function), .. } => {
+                assert!(matches!(&params[..], [TypeExpr::Named(name)] if name.lexeme == "int"));
+                assert!(matches!(ret.as_ref(), TypeExpr::Named(name) if name.lexeme == "bool"));
+                assert_eq!((*line, *column), (1, 8));
+                assert!(matches!(&function.param_types[..], [Some(TypeExpr::Named(_)), None]));
+                assert!(matches!(&function.return_type, Some(TypeExpr::Named(name)) if name.lexeme == "bool"));
+            }
+            _ => panic!("Expected annotated Let statement"),
+        }
+
+        let err = parse_program("let f: fn(int) = 1;").unwrap_err();
+        assert_eq!((err.message.as_str(), err.column), ("Expected '->' after parameter types", 16));
+        let err = parse_program("fn f(a:) {}").unwrap_err();
+        assert_eq!((err.message.as_str(), err.column), ("Expected type", 8));
+    }
+
+    #[test]
+    fn test_assignment_is_right_associative() {
+        // a = b = 1 should parse as a = (b = 1)
+        let expr = parse_expr("a = b = 1");
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                assert_eq!(name.lexeme, "a");
+                assert!(matches!(*value, Expr::Assign { .. }));
+            }
+            _ => panic!("Expected Assign at top"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let tokens = lexer::scan("1 + a = 2").unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(err.message, "Invalid assignment target");
+        assert_eq!(err.column, 7);
+    }
+
+    #[test]
+    fn test_nested_blocks() {
+        let program = parse_program("{ let a = 1; { print a; } }").unwrap();
+        match &program.statements[0] {
+            Stmt::Block(statements) => {
+                assert_eq!(statements.len(), 2);
+                assert!(matches!(&statements[1], Stmt::Block(inner) if inner.len() == 1));
+            }
+            _ => panic!("Expected Block statement"),
+        }
+
+        let err = parse_program("{ print 1;").unwrap_err();
+        assert_eq!(err.message, "Expected '}' after block");
+    }
+
+    #[test]
+    fn test_if_else() {
+        let program = parse_program("if (a < 1) print 1; else if (b) print 2; else { print 3; }").unwrap();
+        match &program.statements[0] {
+            Stmt::If { condition, else_branch: Some(else_branch), .. } => {
+                assert!(matches!(condition, Expr::Binary { operator: BinaryOp::Less, .. }));
+                match else_branch.as_ref() {
+                    Stmt::If { else_branch: Some(inner), .. } => {
+                        assert!(matches!(inner.as_ref(), Stmt::Block(_)));
+                    }
+                    _ => panic!("Expected nested If in else branch"),
+                }
+            }
+            _ => panic!("Expected If statement with else branch"),
+        }
+    }
+
+    #[test]
+    fn test_dangling_else_binds_to_nearest_if() {
+        let program = parse_program("if (a) if (b) print 1; else print 2;").unwrap();
+        match &program.statements[0] {
+            Stmt::If { then_branch, else_branch: None, .. } => {
+                assert!(matches!(then_branch.as_ref(), Stmt::If { else_branch: Some(_), .. }));
+            }
+            _ => panic!("Expected outer If without else branch"),
+        }
+    }
+
+    #[test]
+    fn test_while_loop() {
+        let program = parse_program("while (i < 10) { i = i + 1; continue; }").unwrap();
+        match &program.statements[0] {
+            Stmt::While { body, .. } => match body.as_ref() {
+                Stmt::Block(statements) => {
+                    assert!(matches!(statements[1], Stmt::Continue { line: 1, column: 29 }));
+                }
+                _ => panic!("Expected Block body"),
+            },
+            _ => panic!("Expected While statement"),
+        }
+    }
+
+    #[test]
+    fn test_for_loop_clauses() {
+        let program = parse_program("for (let i = 0; i < 3; i = i + 1) print i;").unwrap();
+        match &program.statements[0] {
+            Stmt::For { initializer: Some(initializer), condition: Some(_), increment: Some(_), .. } => {
+                assert!(matches!(initializer.as_ref(), Stmt::Let { .. }));
+            }
+            _ => panic!("Expected For statement with all clauses"),
+        }
+
+        let program = parse_program("for (;;) break;").unwrap();
+        match &program.statements[0] {
+            Stmt::For { initializer: None, condition: None, increment: None, body } => {
+                assert!(matches!(body.as_ref(), Stmt::Break { .. }));
+            }
+            _ => panic!("Expected For statement without clauses"),
+        }
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let err = parse_program("print 1;\n  break;").unwrap_err();
+        assert_eq!(err.message, "'break' outside of a loop");
+        assert_eq!((err.line, err.column), (2, 3));
+
+        let err = parse_program("while (true) {}\nif (true) { continue; }").unwrap_err();
+        assert_eq!(err.message, "'continue' outside of a loop");
+        assert_eq!((err.line, err.column), (2, 13));
+    }
+
+    #[test]
+    fn test_break_in_nested_loop_body() {
+        assert!(parse_program("while (a) { if (b) { for (;;) { break; } continue; } }").is_ok());
+    }
+
+    #[test]
+    fn test_function_declaration() {
+        let program = parse_program("fn add(a, b) { return a + b; }").unwrap();
+        match &program.statements[0] {
+            Stmt::Function(function) => {
+                assert_eq!(function.name.as_ref().unwrap().lexeme, "add");
+                let params: Vec<&str> = function.params.iter().map(|p| p.lexeme.as_str()).collect();
+                assert_eq!(params, vec!["a", "b"]);
+                assert!(matches!(function.body[0], Stmt::Return { value: Some(_), .. }));
+            }
+            _ => panic!("Expected Function statement"),
+        }
+    }
+
+    #[test]
+    fn test_anonymous_function_expression() {
+        let program = parse_program("let f = fn(x) { return x; };\nfn() {}();").unwrap();
+        match &program.statements[0] {
+            Stmt::Let { initializer: Expr::Function(function), .. } => {
+                assert!(function.name.is_none());
+                assert_eq!(function.params.len(), 1);
+            }
+            _ => panic!("Expected Let with a function initializer"),
+        }
+        assert!(matches!(
+            &program.statements[1],
+            Stmt::Expression(Expr::Call { callee, .. }) if matches!(**callee, Expr::Function(_))
+        ));
+    }
+
+    #[test]
+    fn test_trailing_expression_is_returned() {
+        let program = parse_program("let id = fn(x) { x };\nfn f(a) {\n  print a;\n  a + 1\n}").unwrap();
+        match &program.statements[0] {
+            Stmt::Let { initializer: Expr::Function(function), .. } => {
+                assert!(matches!(
+                    &function.body[..],
+                    [Stmt::Return { value: Some(Expr::Variable { .. }), line: 1, column: 18 }]
+                ));
+            }
+            _ => panic!("Expected Let with a function initializer"),
+        }
+        match &program.statements[1] {
+            Stmt::Function(function) => assert!(matches!(
+                &function.body[..],
+                [Stmt::Print { .. }, Stmt::Return { value: Some(Expr::Binary { .. }), line: 4, column: 3 }]
+            )),
+            _ => panic!("Expected Function statement"),
+        }
+
+        // Only the last expression of a function body may omit its ';'.
+        let err = parse_program("fn f() { 1 2 }").unwrap_err();
+        assert_eq!((err.message.as_str(), err.column), ("Expected ';' after expression", 12));
+        let err = parse_program("fn f() { if (true) { 1 } }").unwrap_err();
+        assert_eq!((err.message.as_str(), err.column), ("Expected ';' after expression", 24));
+        let err = parse_program("{ 1 }").unwrap_err();
+        assert_eq!(err.message, "Expected ';' after expression");
+    }
+
+    #[test]
+    fn test_deeply_nested_trailing_expressions() {
+        // Each body is parsed once, so this doesn't take 2^depth steps.
+        let depth = 40;
+        let source = format!("let f = {} 1 {};", "fn() { ".repeat(depth), "}".repeat(depth));
+        let program = parse_program(&source).unwrap();
+        let Stmt::Let { initializer, .. } = &program.statements[0] else {
+            panic!("Expected Let");
+        };
+        let mut expr = initializer;
+        for _ in 0..depth {
+            let Expr::Function(function) = expr else {
+                panic!("Expected a function");
+            };
+            let [Stmt::Return { value: Some(value), .. }] = &function.body[..] else {
+                panic!("Expected a single trailing expression");
+            };
+            expr = value;
+        }
+        assert!(matches!(expr, Expr::Number(1)));
+    }
+
+    #[test]
+    fn test_call_expression() {
+        // f(1)(2, 3) should parse as (f(1))(2, 3)
+        let expr = parse_expr("f(1)(2, 3)");
+        match expr {
+            Expr::Call { callee, arguments, line, column } => {
+                assert_eq!(arguments.len(), 2);
+                assert_eq!((line, column), (1, 5));
+                assert!(matches!(*callee, Expr::Call { .. }));
+            }
+            _ => panic!("Expected Call at top"),
+        }
+    }
+
+    #[test]
+    fn test_call_binds_tighter_than_unary() {
+        let expr = parse_expr("-f()");
+        match expr {
+            Expr::Unary { operand, .. } => assert!(matches!(*operand, Expr::Call { .. })),
+            _ => panic!("Expected Unary at top"),
+        }
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let expr = parse_expr(r#""total: ${a + b}${"!"}""#);
+        let Expr::Interpolation(parts) = expr else {
+            panic!("Expected Interpolation");
+        };
+        assert_eq!(parts.len(), 3);
+        assert!(matches!(&parts[0], InterpolationPart::Literal(s) if s == "total: "));
+        assert!(matches!(&parts[1], InterpolationPart::Expr(Expr::Binary { .. })));
+        assert!(matches!(&parts[2], InterpolationPart::Expr(Expr::String(s)) if s == "!"));
+
+        let error = |input| {
+            let err = Parser::new(lexer::scan(input).unwrap()).parse().unwrap_err();
+            (err.message, err.line, err.column)
+        };
+        assert_eq!(
+            error(r#""${a b}""#),
+            ("Expected '}' after interpolated expression".to_string(), 1, 6)
+        );
+        assert_eq!(
+            error(r#""a${}b""#),
+            ("Expected expression inside '${}'".to_string(), 1, 3)
+        );
+        assert_eq!(
+            error(r#""${1} and ${ }""#),
+            ("Expected expression inside '${}'".to_string(), 1, 11)
+        );
+        assert_eq!(
+            error("\"\"\"\n\tx ${}\n\"\"\""),
+            ("Expected expression inside '${}'".to_string(), 2, 7)
+        );
+    }
+
+    #[test]
+    fn test_return_outside_function() {
+        let err = parse_program("return 1;").unwrap_err();
+        assert_eq!(err.message, "'return' outside of a function");
+        assert_eq!((err.line, err.column), (1, 1));
+    }
+
+    #[test]
+    fn test_break_inside_function_inside_loop() {
+        let err = parse_program("while (true) {\n  fn f() { break; }\n}").unwrap_err();
+        assert_eq!(err.message, "'break' outside of a loop");
+        assert_eq!((err.line, err.column), (2, 12));
+    }
+
+    #[test]
+    fn test_unterminated_argument_list() {
+        let tokens = lexer::scan("f(1, 2").unwrap();
+        let err = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(err.message, "Expected ')' after arguments");
+    }
 }
@@ -0,0 +1,364 @@
+//! Turns syntax trees back into source code, with one statement per line
+//! and blocks indented by four spaces. Parsing the output gives back the
+//! same tree: parentheses are kept as [`Expr::Grouping`], so none need to be
+//! added.
+
+use std::fmt::Write;
+
+use crate::{Expr, Function, InterpolationPart, Program, Stmt, TypeExpr, UnaryOp};
+
+const INDENT: &str = "    ";
+
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+pub fn print_program(program: &Program) -> String {
+    let mut printer = Printer::new();
+    for statement in &program.statements {
+        printer.statement(statement);
+        printer.out.push('\n');
+    }
+    printer.out
+}
+
+pub fn print_expr(expr: &Expr) -> String {
+    let mut printer = Printer::new();
+    printer.expression(expr);
+    printer.out
+}
+
+impl Printer {
+    fn new() -> Self {
+        Printer {
+            out: String::new(),
+            indent: 0,
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        if statements.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push_str("{\n");
+        self.indent += 1;
+        for statement in statements {
+            self.out.push_str(&INDENT.repeat(self.indent));
+            self.statement(statement);
+            self.out.push('\n');
+        }
+        self.indent -= 1;
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.out.push('}');
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.expression(expr);
+                self.out.push(';');
+            }
+            Stmt::Print { value, .. } => {
+                self.out.push_str("print ");
+                self.expression(value);
+                self.out.push(';');
+            }
+            Stmt::Let {
+                name,
+                annotation,
+                initializer,
+            } => {
+                write!(self.out, "let {}", name.lexeme).unwrap();
+                if let Some(annotation) = annotation {
+                    self.out.push_str(": ");
+                    self.type_expr(annotation);
+                }
+                self.out.push_str(" = ");
+                self.expression(initializer);
+                self.out.push(';');
+            }
+            Stmt::Block(statements) => self.block(statements),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.out.push_str("if (");
+                self.expression(condition);
+                self.out.push_str(") ");
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.out.push_str(" else ");
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.out.push_str("while (");
+                self.expression(condition);
+                self.out.push_str(") ");
+                self.statement(body);
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.out.push_str("for (");
+                match initializer {
+                    Some(initializer) => self.statement(initializer),
+                    None => self.out.push(';'),
+                }
+                if let Some(condition) = condition {
+                    self.out.push(' ');
+                    self.expression(condition);
+                }
+                self.out.push(';');
+                if let Some(increment) = increment {
+                    self.out.push(' ');
+                    self.expression(increment);
+                }
+                self.out.push_str(") ");
+                self.statement(body);
+            }
+            Stmt::Break { .. } => self.out.push_str("break;"),
+            Stmt::Continue { .. } => self.out.push_str("continue;"),
+            Stmt::Function(function) => self.function(function),
+            Stmt::Return { value, .. } => {
+                self.out.push_str("return");
+                if let Some(value) = value {
+                    self.out.push(' ');
+                    self.expression(value);
+                }
+                self.out.push(';');
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.out.push_str("fn");
+        if let Some(name) = &function.name {
+            write!(self.out, " {}", name.lexeme).unwrap();
+        }
+        self.out.push('(');
+        for (i, (param, annotation)) in function
+            .params
+            .iter()
+            .zip(&function.param_types)
+            .enumerate()
+        {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(&param.lexeme);
+            if let Some(annotation) = annotation {
+                self.out.push_str(": ");
+                self.type_expr(annotation);
+            }
+        }
+        self.out.push_str(") ");
+        if let Some(return_type) = &function.return_type {
+            self.out.push_str("-> ");
+            self.type_expr(return_type);
+            self.out.push(' ');
+        }
+        self.block(&function.body);
+    }
+
+    fn type_expr(&mut self, annotation: &TypeExpr) {
+        match annotation {
+            TypeExpr::Named(name) => self.out.push_str(&name.lexeme),
+            TypeExpr::Function { params, ret, .. } => {
+                self.out.push_str("fn(");
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.type_expr(param);
+                }
+                self.out.push_str(") -> ");
+                self.type_expr(ret);
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(n) => write!(self.out, "{n}").unwrap(),
+            Expr::Float(n) => write!(self.out, "{n:?}").unwrap(),
+            Expr::String(s) => write!(self.out, "\"{}\"", escape(s)).unwrap(),
+            Expr::Bool(b) => write!(self.out, "{b}").unwrap(),
+            Expr::Unary {
+                operator, operand, ..
+            } => {
+                self.out.push(match operator {
+                    UnaryOp::Negate => '-',
+                    UnaryOp::Not => '!',
+                });
+                self.expression(operand);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                self.expression(left);
+                write!(self.out, " {} ", operator.symbol()).unwrap();
+                self.expression(right);
+            }
+            Expr::Grouping(inner) => {
+                self.out.push('(');
+                self.expression(inner);
+                self.out.push(')');
+            }
+            Expr::Variable { name, .. } => self.out.push_str(&name.lexeme),
+            Expr::Assign { name, value, .. } => {
+                write!(self.out, "{} = ", name.lexeme).unwrap();
+                self.expression(value);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                self.out.push('(');
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(argument);
+                }
+                self.out.push(')');
+            }
+            Expr::Function(function) => self.function(function),
+            Expr::Interpolation(parts) => {
+                self.out.push('"');
+                for part in parts {
+                    match part {
+                        InterpolationPart::Literal(text) => self.out.push_str(&escape(text)),
+                        InterpolationPart::Expr(expr) => {
+                            self.out.push_str("${");
+                            self.expression(expr);
+                            self.out.push('}');
+                        }
+                    }
+                }
+                self.out.push('"');
+            }
+        }
+    }
+}
+
+/// Writes `s` the way it would appear between quotes in source code.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(character) = chars.next() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '$' if chars.peek() == Some(&'{') => escaped.push_str("\\$"),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            character if character.is_control() => {
+                write!(escaped, "\\u{{{:x}}}", character as u32).unwrap();
+            }
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser::Parser};
+
+    fn reprint(input: &str) -> String {
+        let tokens = lexer::scan(input).unwrap();
+        print_program(&Parser::new(tokens).parse_program().unwrap())
+    }
+
+    /// Checks that `input` prints as `expected`, and that `expected` is
+    /// already in its printed form.
+    fn assert_prints(input: &str, expected: &str) {
+        assert_eq!(reprint(input), expected);
+        assert_eq!(reprint(expected), expected);
+    }
+
+    #[test]
+    fn test_expressions() {
+        let tokens = lexer::scan("-(1 + 2) * f(a, \"s\")(!true) == (b = 3)").unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(print_expr(&expr), "-(1 + 2) * f(a, \"s\")(!true) == (b = 3)");
+
+        let tokens = lexer::scan(".5 + 1. * 2.50e-9").unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(print_expr(&expr), "0.5 + 1.0 * 2.5e-9");
+
+        let tokens = lexer::scan(r#""say \"hi\"\t\\ \u{1b}[0m\u{e9}\n""#).unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(print_expr(&expr), r#""say \"hi\"\t\\ \u{1b}[0mé\n""#);
+
+        let tokens = lexer::scan(r#""$${a+1} \${b} ${f("}")}""#).unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        assert_eq!(print_expr(&expr), r#""$${a + 1} \${b} ${f("}")}""#);
+    }
+
+    #[test]
+    fn test_statements() {
+        assert_prints(
+            "let x=1;print x;{x=2;{}}if(x<2)print 1;else{print 2;}
+             while(true)break;for(;;)continue;for(let i=0;i<3;i=i+1){}",
+            "let x = 1;
+print x;
+{
+    x = 2;
+    {}
+}
+if (x < 2) print 1; else {
+    print 2;
+}
+while (true) break;
+for (;;) continue;
+for (let i = 0; i < 3; i = i + 1) {}
+",
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_prints(
+            "fn f(a,b){return;} let g=fn(x){x};print fn(){return fn(){};};",
+            "fn f(a, b) {
+    return;
+}
+let g = fn(x) {
+    return x;
+};
+print fn() {
+    return fn() {};
+};
+",
+        );
+    }
+
+    #[test]
+    fn test_annotations_round_trip() {
+        assert_prints(
+            "let x:int=1; fn add(a:int,b)->int{return a+b;}
+             let apply:fn(fn(int)->bool,int)->bool=fn(f:fn(int)->bool,n:int)->bool{f(n)};",
+            "let x: int = 1;
+fn add(a: int, b) -> int {
+    return a + b;
+}
+let apply: fn(fn(int) -> bool, int) -> bool = fn(f: fn(int) -> bool, n: int) -> bool {
+    return f(n);
+};
+",
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use std::{cell::Cell, collections::HashMap, fmt};
+
+use crate::{Expr, Function, InterpolationPart, Program, Stmt, SyntaxError, Token};
+
+#[derive(Debug)]
+pub struct Warning {
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+struct Local {
+    name: Token,
+    // `false` between declaration and the end of the initializer.
+    defined: bool,
+    used: bool,
+}
+
+struct Resolver {
+    // Block scopes only; globals are looked up dynamically and never appear
+    // here.
+    scopes: Vec<HashMap<String, Local>>,
+    warnings: Vec<Warning>,
+}
+
+/// Records the binding depth of every variable reference in `program` and
+/// reports static scoping errors. Unused locals come back as warnings.
+pub fn resolve(program: &Program) -> Result<Vec<Warning>, SyntaxError> {
+    let mut resolver = Resolver::new();
+    resolver.resolve_statements(&program.statements)?;
+    Ok(resolver.warnings)
+}
+
+/// Like [`resolve`], for a standalone expression evaluated at the top level.
+pub fn resolve_expr(expr: &Expr) -> Result<Vec<Warning>, SyntaxError> {
+    let mut resolver = Resolver::new();
+    resolver.resolve_expr(expr)?;
+    Ok(resolver.warnings)
+}
+
+impl Resolver {
+    fn new() -> Self {
+        Resolver {
+            scopes: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[Stmt]) -> Result<(), SyntaxError> {
+        statements
+            .iter()
+            .try_for_each(|statement| self.resolve_statement(statement))
+    }
+
+    fn resolve_statement(&mut self, stmt: &Stmt) -> Result<(), SyntaxError> {
+        match stmt {
+            Stmt::Expression(expr) => self.resolve_expr(expr),
+            Stmt::Print { value, .. } => self.resolve_expr(value),
+            Stmt::Let {
+                name, initializer, ..
+            } => {
+                self.declare(name)?;
+                self.resolve_expr(initializer)?;
+                self.define(name);
+                Ok(())
+            }
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                let result = self.resolve_statements(statements);
+                self.end_scope();
+                result
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition)?;
+                self.resolve_statement(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch)?;
+                }
+                Ok(())
+            }
+            Stmt::While { condition, body } => {
+                self.resolve_expr(condition)?;
+                self.resolve_statement(body)
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                // The interpreter runs the whole loop in a scope of its own.
+                self.begin_scope();
+                let result = self.resolve_for(initializer, condition, increment, body);
+                self.end_scope();
+                result
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => Ok(()),
+            Stmt::Function(function) => {
+                let name = function.name.as_ref().unwrap();
+                self.declare(name)?;
+                self.define(name);
+                self.resolve_function(function)
+            }
+            Stmt::Return { value, .. } => match value {
+                Some(value) => self.resolve_expr(value),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn resolve_for(
+        &mut self,
+        initializer: &Option<Box<Stmt>>,
+        condition: &Option<Expr>,
+        increment: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<(), SyntaxError> {
+        if let Some(initializer) = initializer {
+            self.resolve_statement(initializer)?;
+        }
+        if let Some(condition) = condition {
+            self.resolve_expr(condition)?;
+        }
+        if let Some(increment) = increment {
+            self.resolve_expr(increment)?;
+        }
+        self.resolve_statement(body)
+    }
+
+    fn resolve_function(&mut self, function: &Function) -> Result<(), SyntaxError> {
+        self.begin_scope();
+        let result = self.resolve_function_body(function);
+        self.end_scope();
+        result
+    }
+
+    fn resolve_function_body(&mut self, function: &Function) -> Result<(), SyntaxError> {
+        for param in &function.params {
+            self.declare(param)?;
+            self.define(param);
+            // Parameters are part of the function's signature, so leaving
+            // one unread isn't worth a warning.
+            self.mark_used(param);
+        }
+        self.resolve_statements(&function.body)
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) -> Result<(), SyntaxError> {
+        match expr {
+            Expr::Number(_) | Expr::Float(_) | Expr::String(_) | Expr::Bool(_) => Ok(()),
+            Expr::Grouping(inner) => self.resolve_expr(inner),
+            Expr::Unary { operand, .. } => self.resolve_expr(operand),
+            Expr::Binary { left, right, .. } => {
+                self.resolve_expr(left)?;
+                self.resolve_expr(right)
+            }
+            Expr::Variable { name, depth } => {
+                if let Some(scope) = self.scopes.last()
+                    && let Some(local) = scope.get(&name.lexeme)
+                    && !local.defined
+                {
+                    return Err(SyntaxError::new(
+                        format!(
+                            "Can't read local variable '{}' in its own initializer",
+                            name.lexeme
+                        ),
+                        name.line,
+                        name.column,
+                    ));
+                }
+
+                self.resolve_local(name, depth, true);
+                Ok(())
+            }
+            Expr::Assign { name, value, depth } => {
+                self.resolve_expr(value)?;
+                self.resolve_local(name, depth, false);
+                Ok(())
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(callee)?;
+                arguments
+                    .iter()
+                    .try_for_each(|argument| self.resolve_expr(argument))
+            }
+            Expr::Function(function) => self.resolve_function(function),
+            Expr::Interpolation(parts) => parts.iter().try_for_each(|part| match part {
+                InterpolationPart::Literal(_) => Ok(()),
+                InterpolationPart::Expr(expr) => self.resolve_expr(expr),
+            }),
+        }
+    }
+
+    fn resolve_local(&mut self, name: &Token, depth: &Cell<Option<usize>>, is_read: bool) {
+        for (distance, scope) in self.scopes.iter_mut().rev().enumerate() {
+            if let Some(local) = scope.get_mut(&name.lexeme) {
+                local.used |= is_read;
+                depth.set(Some(distance));
+                return;
+            }
+        }
+
+        depth.set(None);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+
+        let mut unused: Vec<Token> = scope
+            .into_values()
+            .filter(|local| !local.used && !local.name.lexeme.starts_with('_'))
+            .map(|local| local.name)
+            .collect();
+        unused.sort_by_key(|name| (name.line, name.column));
+
+        self.warnings.extend(unused.into_iter().map(|name| Warning {
+            message: format!("Unused local variable '{}'", name.lexeme),
+            line: name.line,
+            column: name.column,
+        }));
+    }
+
+    fn declare(&mut self, name: &Token) -> Result<(), SyntaxError> {
+        let Some(scope) = self.scopes.last_mut() else {
+            return Ok(());
+        };
+
+        if let Some(existing) = scope.get(&name.lexeme) {
+            return Err(SyntaxError::new(
+                format!(
+                    "Variable '{}' is already declared in this scope at {}:{}",
+                    name.lexeme, existing.name.line, existing.name.column
+                ),
+                name.line,
+                name.column,
+            ));
+        }
+
+        scope.insert(
+            name.lexeme.clone(),
+            Local {
+                name: name.clone(),
+                defined: false,
+                used: false,
+            },
+        );
+        Ok(())
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.lexeme))
+        {
+            local.defined = true;
+        }
+    }
+
+    fn mark_used(&mut self, name: &Token) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&name.lexeme))
+        {
+            local.used = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser::Parser};
+
+    fn parse_program(input: &str) -> Program {
+        let tokens = lexer::scan(input).unwrap();
+        Parser::new(tokens).parse_program().unwrap()
+    }
+
+    fn resolve_source(input: &str) -> Result<Vec<Warning>, SyntaxError> {
+        resolve(&parse_program(input))
+    }
+
+    fn print_depth(stmt: &Stmt) -> Option<usize> {
+        match stmt {
+            Stmt::Print { value: Expr::Variable { depth, .. }, .. } => depth.get(),
+            _ => panic!("Expected print of a variable"),
+        }
+    }
+
+    #[test]
+    fn test_global_has_no_depth() {
+        let program = parse_program("let a = 1; print a;");
+        resolve(&program).unwrap();
+        assert_eq!(print_depth(&program.statements[1]), None);
+    }
+
+    #[test]
+    fn test_block_depths() {
+        let program = parse_program("{ let a = 1; { let b = 2; print a; print b; } }");
+        resolve(&program).unwrap();
+        match &program.statements[0] {
+            Stmt::Block(outer) => match &outer[1] {
+                Stmt::Block(inner) => {
+                    assert_eq!(print_depth(&inner[1]), Some(1));
+                    assert_eq!(print_depth(&inner[2]), Some(0));
+                }
+                _ => panic!("Expected inner Block"),
+            },
+            _ => panic!("Expected outer Block"),
+        }
+    }
+
+    #[test]
+    fn test_closure_depth() {
+        let program = parse_program("fn outer() { let x = 1; fn inner() { print x; } inner(); }");
+        resolve(&program).unwrap();
+        match &program.statements[0] {
+            Stmt::Function(outer) => match &outer.body[1] {
+                Stmt::Function(inner) => assert_eq!(print_depth(&inner.body[0]), Some(1)),
+                _ => panic!("Expected inner Function"),
+            },
+            _ => panic!("Expected outer Function"),
+        }
+    }
+
+    #[test]
+    fn test_read_in_own_initializer() {
+        let err = resolve_source("let a = 1;\n{ let a = a + 1; print a; }").unwrap_err();
+        assert_eq!(err.message, "Can't read local variable 'a' in its own initializer");
+        assert_eq!((err.line, err.column), (2, 11));
+
+        // Globals may refer to a previous definition of themselves.
+        assert!(resolve_source("let a = 1; let a = a + 1;").is_ok());
+    }
+
+    #[test]
+    fn test_duplicate_declaration() {
+        let err = resolve_source("{\n  let a = 1;\n  let a = 2;\n}").unwrap_err();
+        assert_eq!(err.message, "Variable 'a' is already declared in this scope at 2:7");
+        assert_eq!((err.line, err.column), (3, 7));
+
+        let err = resolve_source("fn f(a, a) {}").unwrap_err();
+        assert_eq!(err.column, 9);
+
+        // Shadowing in a nested scope is fine.
+        assert!(resolve_source("{ let a = 1; { let a = 2; print a; } print a; }").is_ok());
+    }
+
+    #[test]
+    fn test_unused_locals() {
+        let warnings = resolve_source(
+            "let global = 1;\n{\n  let b = 1;\n  let a = 2;\n  let _c = 3;\n  let d = 4;\n  print d;\n}",
+        )
+        .unwrap();
+        let messages: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "3:7: Unused local variable 'b'",
+                "4:7: Unused local variable 'a'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assignment_is_not_a_use() {
+        let warnings = resolve_source("fn f(p) { let x = 1; x = p; }").unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "Unused local variable 'x'");
+    }
+}
@@ -0,0 +1,818 @@
+//! Binary file format for compiled scripts, so a program can be compiled
+//! once and later run on the VM without lexing or parsing it again.
+//!
+//! All integers are big endian. The layout is:
+//!
+//! ```text
+//! magic       b"LGBC"
+//! version     u16
+//! checksum    u32, FNV-1a over everything after the header
+//! functions   u32 count, then one record per function; the script is first
+//!             and every function is stored after the one that creates it
+//!
+//! function    name: u8 flag (1 if present) + string
+//!             arity: u8, upvalue count: u8
+//!             constants: u32 count, each a u8 tag followed by
+//!                 0 = number (i64), 1 = string, 2 = function (u32 index),
+//!                 3 = float (f64 bits)
+//!             code: u32 length + bytes
+//!             lines: u32 count, each (offset, line, column) as u32
+//!
+//! string      u32 length + UTF-8 bytes
+//! ```
+//!
+//! Loading checks the header, the checksum and the structure of every
+//! function, and verifies that the code cannot make the VM read outside its
+//! stack, constants or upvalues, so a corrupt file is rejected up front
+//! instead of crashing the VM halfway through a run.
+
+use std::{collections::BTreeSet, fmt, rc::Rc};
+
+use crate::chunk::{Chunk, Constant, FunctionProto, LineStart, OpCode};
+
+pub const MAGIC: &[u8; 4] = b"LGBC";
+pub const VERSION: u16 = 3;
+
+const HEADER_LEN: usize = 10;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadError {
+    pub message: String,
+}
+
+impl LoadError {
+    fn new(message: String) -> Self {
+        LoadError { message }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid bytecode file: {}", self.message)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Encodes a script produced by [`crate::compiler::compile`].
+pub fn serialize(script: &FunctionProto) -> Vec<u8> {
+    let mut functions = Vec::new();
+    collect_functions(script, &mut functions);
+
+    let mut body = Vec::new();
+    write_u32(&mut body, functions.len());
+    for function in &functions {
+        write_function(&mut body, function, &functions);
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_be_bytes());
+    bytes.extend_from_slice(&checksum(&body).to_be_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+/// Decodes and verifies a file written by [`serialize`].
+pub fn deserialize(bytes: &[u8]) -> Result<Rc<FunctionProto>, LoadError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::new(
+            "Not a compiled script (missing magic number)".to_string(),
+        ));
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(LoadError::new(
+            "Unexpected end of file in header".to_string(),
+        ));
+    }
+
+    let version = u16::from_be_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(LoadError::new(format!(
+            "Unsupported version {version}, expected {VERSION}"
+        )));
+    }
+
+    let expected = u32::from_be_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+    if checksum(&bytes[HEADER_LEN..]) != expected {
+        return Err(LoadError::new(
+            "Checksum mismatch, the file is corrupt".to_string(),
+        ));
+    }
+
+    let mut reader = Reader {
+        bytes,
+        position: HEADER_LEN,
+    };
+    let count = reader.u32()?;
+    if count == 0 {
+        return Err(LoadError::new("File contains no functions".to_string()));
+    }
+
+    let mut functions = Vec::new();
+    for index in 0..count {
+        functions.push(reader.function(index, count)?);
+    }
+    if reader.position != bytes.len() {
+        return Err(LoadError::new(format!(
+            "Unexpected data after the last function at byte {}",
+            reader.position
+        )));
+    }
+
+    let script = &functions[0];
+    if script.arity != 0 || script.upvalue_count != 0 {
+        return Err(LoadError::new(
+            "The script must take no arguments and capture no variables".to_string(),
+        ));
+    }
+    for index in 0..functions.len() {
+        verify(&functions, index)
+            .map_err(|message| LoadError::new(format!("function {index}: {message}")))?;
+    }
+
+    // Functions only reference ones stored after them, so building from the
+    // end means every nested function already exists.
+    let mut built: Vec<Option<Rc<FunctionProto>>> = vec![None; functions.len()];
+    for (index, function) in functions.into_iter().enumerate().rev() {
+        let constants = function
+            .constants
+            .into_iter()
+            .map(|constant| match constant {
+                RawConstant::Number(n) => Constant::Number(n),
+                RawConstant::Float(n) => Constant::Float(n),
+                RawConstant::String(s) => Constant::String(s.into()),
+                RawConstant::Function(nested) => {
+                    Constant::Function(Rc::clone(built[nested].as_ref().unwrap()))
+                }
+            })
+            .collect();
+
+        built[index] = Some(Rc::new(FunctionProto {
+            name: function.name,
+            arity: function.arity,
+            upvalue_count: function.upvalue_count,
+            chunk: Chunk {
+                code: function.code,
+                constants,
+                lines: function.lines,
+            },
+        }));
+    }
+
+    Ok(built.swap_remove(0).unwrap())
+}
+
+/// Lists `function` and everything nested in it, each parent before its
+/// children.
+fn collect_functions<'a>(function: &'a FunctionProto, functions: &mut Vec<&'a FunctionProto>) {
+    functions.push(function);
+    for constant in &function.chunk.constants {
+        if let Constant::Function(nested) = constant {
+            collect_functions(nested, functions);
+        }
+    }
+}
+
+fn write_function(out: &mut Vec<u8>, function: &FunctionProto, functions: &[&FunctionProto]) {
+    match &function.name {
+        Some(name) => {
+            out.push(1);
+            write_string(out, name);
+        }
+        None => out.push(0),
+    }
+    out.push(function.arity);
+    out.push(function.upvalue_count);
+
+    let chunk = &function.chunk;
+    write_u32(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Number(n) => {
+                out.push(TAG_NUMBER);
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+            Constant::Float(n) => {
+                out.push(TAG_FLOAT);
+                out.extend_from_slice(&n.to_bits().to_be_bytes());
+            }
+            Constant::String(s) => {
+                out.push(TAG_STRING);
+                write_string(out, s);
+            }
+            Constant::Function(nested) => {
+                let index = functions
+                    .iter()
+                    .position(|f| std::ptr::eq(*f, &**nested))
+                    .unwrap();
+                out.push(TAG_FUNCTION);
+                write_u32(out, index);
+            }
+        }
+    }
+
+    write_u32(out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+
+    write_u32(out, chunk.lines.len());
+    for start in &chunk.lines {
+        write_u32(out, start.offset);
+        out.extend_from_slice(&start.line.to_be_bytes());
+        out.extend_from_slice(&start.column.to_be_bytes());
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_be_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    write_u32(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+enum RawConstant {
+    Number(i64),
+    Float(f64),
+    String(String),
+    // Index into the function table.
+    Function(usize),
+}
+
+struct RawFunction {
+    name: Option<String>,
+    arity: u8,
+    upvalue_count: u8,
+    constants: Vec<RawConstant>,
+    code: Vec<u8>,
+    lines: Vec<LineStart>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], LoadError> {
+        if self.bytes.len() - self.position < len {
+            return Err(LoadError::new(format!(
+                "Unexpected end of file at byte {}",
+                self.bytes.len()
+            )));
+        }
+        let slice = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i64(&mut self) -> Result<i64, LoadError> {
+        let bytes = self.take(8)?;
+        Ok(i64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let position = self.position;
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| LoadError::new(format!("Invalid UTF-8 in string at byte {position}")))
+    }
+
+    fn function(&mut self, index: u32, count: u32) -> Result<RawFunction, LoadError> {
+        let name = match self.u8()? {
+            0 => None,
+            1 => Some(self.string()?),
+            flag => {
+                return Err(LoadError::new(format!(
+                    "Invalid name flag {flag} at byte {}",
+                    self.position - 1
+                )));
+            }
+        };
+        let arity = self.u8()?;
+        let upvalue_count = self.u8()?;
+
+        let mut constants = Vec::new();
+        for _ in 0..self.u32()? {
+            let constant = match self.u8()? {
+                TAG_NUMBER => RawConstant::Number(self.i64()?),
+                TAG_FLOAT => RawConstant::Float(f64::from_bits(self.i64()? as u64)),
+                TAG_STRING => RawConstant::String(self.string()?),
+                TAG_FUNCTION => {
+                    let position = self.position;
+                    let nested = self.u32()?;
+                    if nested <= index {
+                        return Err(LoadError::new(format!(
+                            "Function {index} refers to function {nested} at byte {position}, \
+                             which is not stored after it"
+                        )));
+                    }
+                    if nested >= count {
+                        return Err(LoadError::new(format!(
+                            "Function {index} refers to function {nested} at byte {position}, \
+                             but the file only has {count}"
+                        )));
+                    }
+                    RawConstant::Function(nested as usize)
+                }
+                tag => {
+                    return Err(LoadError::new(format!(
+                        "Invalid constant tag {tag} at byte {}",
+                        self.position - 1
+                    )));
+                }
+            };
+            constants.push(constant);
+        }
+
+        let len = self.u32()? as usize;
+        let code = self.take(len)?.to_vec();
+
+        let mut lines = Vec::new();
+        for _ in 0..self.u32()? {
+            lines.push(LineStart {
+                offset: self.u32()? as usize,
+                line: self.u32()?,
+                column: self.u32()?,
+            });
+        }
+        if !lines.is_sorted_by(|a, b| a.offset < b.offset) {
+            return Err(LoadError::new(format!(
+                "Line table of function {index} is out of order"
+            )));
+        }
+
+        Ok(RawFunction {
+            name,
+            arity,
+            upvalue_count,
+            constants,
+            code,
+            lines,
+        })
+    }
+}
+
+/// Abstract VM state before an instruction: the number of stack slots in use
+/// by the frame, counting the callee in slot 0, and which of them have been
+/// captured by a closure and must be closed rather than popped.
+#[derive(Clone, PartialEq)]
+struct FrameState {
+    height: usize,
+    captured: BTreeSet<usize>,
+}
+
+impl FrameState {
+    fn expect(&self, operands: usize, offset: usize) -> Result<(), String> {
+        // Slot 0 belongs to the frame and is only removed by `Return`.
+        if self.height <= operands {
+            return Err(format!("Stack underflow at offset {offset}"));
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self, count: usize, offset: usize) -> Result<(), String> {
+        self.expect(count, offset)?;
+        self.height -= count;
+        if self.captured.range(self.height..).next().is_some() {
+            return Err(format!(
+                "Captured variable is popped without being closed at offset {offset}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Checks that every reachable instruction of `functions[index]` has valid
+/// operands and a consistent stack depth.
+fn verify(functions: &[RawFunction], index: usize) -> Result<(), String> {
+    let function = &functions[index];
+    let code = &function.code;
+    let constants = &function.constants;
+
+    // First decode linearly to find instruction boundaries and check the
+    // operands that don't depend on the stack.
+    let mut starts = vec![false; code.len()];
+    let mut offset = 0;
+    while offset < code.len() {
+        starts[offset] = true;
+        let op = OpCode::from_byte(code[offset])
+            .ok_or_else(|| format!("Invalid opcode {} at offset {offset}", code[offset]))?;
+
+        let mut size = instruction_size(op);
+        if offset + size > code.len() {
+            return Err(format!("Truncated instruction at offset {offset}"));
+        }
+
+        match op {
+            OpCode::Constant => match constant_at(constants, code, offset)? {
+                RawConstant::Number(_) | RawConstant::Float(_) | RawConstant::String(_) => {}
+                RawConstant::Function(_) => {
+                    return Err(format!(
+                        "Constant at offset {offset} must be a number or string"
+                    ));
+                }
+            },
+            OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal => {
+                match constant_at(constants, code, offset)? {
+                    RawConstant::String(_) => {}
+                    _ => return Err(format!("Variable name at offset {offset} must be a string")),
+                }
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue
+                if code[offset + 1] >= function.upvalue_count =>
+            {
+                return Err(format!("Invalid upvalue index at offset {offset}"));
+            }
+            OpCode::CheckBool if code[offset + 1] > 1 => {
+                return Err(format!("Invalid logical operator at offset {offset}"));
+            }
+            OpCode::Closure => {
+                let RawConstant::Function(nested) = constant_at(constants, code, offset)? else {
+                    return Err(format!("Closure at offset {offset} must load a function"));
+                };
+                let nested = functions
+                    .get(*nested)
+                    .ok_or_else(|| format!("Unknown function at offset {offset}"))?;
+                size += 2 * nested.upvalue_count as usize;
+                if offset + size > code.len() {
+                    return Err(format!("Truncated instruction at offset {offset}"));
+                }
+                for pair in code[offset + 3..offset + size].chunks(2) {
+                    if pair[0] > 1 || (pair[0] == 0 && pair[1] >= function.upvalue_count) {
+                        return Err(format!("Invalid captured variable at offset {offset}"));
+                    }
+                }
+            }
+            _ => {}
+        }
+        offset += size;
+    }
+
+    // Then walk every path through the code, tracking the stack.
+    let mut states: Vec<Option<FrameState>> = vec![None; code.len()];
+    let mut pending = Vec::new();
+    let entry = FrameState {
+        height: 1 + function.arity as usize,
+        captured: BTreeSet::new(),
+    };
+    merge(&mut states, &mut pending, &starts, 0, entry)?;
+
+    while let Some(offset) = pending.pop() {
+        let mut state = states[offset].clone().unwrap();
+        let op = OpCode::from_byte(code[offset]).unwrap();
+        let operand = code.get(offset + 1).copied().unwrap_or(0) as usize;
+        let next = offset + instruction_size(op);
+        let jump = || {
+            let distance = u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as usize;
+            if op == OpCode::Loop {
+                next.checked_sub(distance)
+                    .ok_or_else(|| format!("Loop before the start of the code at offset {offset}"))
+            } else {
+                Ok(next + distance)
+            }
+        };
+
+        match op {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetGlobal
+            | OpCode::GetUpvalue => state.height += 1,
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print => state.pop(1, offset)?,
+            OpCode::GetLocal | OpCode::SetLocal => {
+                if operand >= state.height {
+                    return Err(format!("Invalid local slot at offset {offset}"));
+                }
+                if op == OpCode::GetLocal {
+                    state.height += 1;
+                } else {
+                    state.expect(1, offset)?;
+                }
+            }
+            OpCode::SetGlobal
+            | OpCode::SetUpvalue
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::CheckBool => state.expect(1, offset)?,
+            OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide => {
+                state.pop(2, offset)?;
+                state.height += 1;
+            }
+            OpCode::Jump | OpCode::Loop => {
+                merge(&mut states, &mut pending, &starts, jump()?, state)?;
+                continue;
+            }
+            OpCode::JumpIfFalse => {
+                state.expect(1, offset)?;
+                merge(&mut states, &mut pending, &starts, jump()?, state.clone())?;
+            }
+            OpCode::Call => {
+                state.pop(operand + 1, offset)?;
+                state.height += 1;
+            }
+            OpCode::Interpolate => {
+                state.pop(operand, offset)?;
+                state.height += 1;
+            }
+            OpCode::Closure => {
+                let RawConstant::Function(nested) = constant_at(constants, code, offset)? else {
+                    unreachable!("checked while decoding");
+                };
+                let end = next + 2 * functions[*nested].upvalue_count as usize;
+                for pair in code[next..end].chunks(2) {
+                    let slot = pair[1] as usize;
+                    if pair[0] == 1 {
+                        if slot >= state.height {
+                            return Err(format!("Invalid captured slot at offset {offset}"));
+                        }
+                        state.captured.insert(slot);
+                    }
+                }
+                state.height += 1;
+                merge(&mut states, &mut pending, &starts, end, state)?;
+                continue;
+            }
+            OpCode::CloseUpvalue => {
+                state.expect(1, offset)?;
+                state.captured.remove(&(state.height - 1));
+                state.pop(1, offset)?;
+            }
+            OpCode::Return => {
+                state.expect(1, offset)?;
+                continue;
+            }
+        }
+        merge(&mut states, &mut pending, &starts, next, state)?;
+    }
+
+    Ok(())
+}
+
+fn merge(
+    states: &mut [Option<FrameState>],
+    pending: &mut Vec<usize>,
+    starts: &[bool],
+    target: usize,
+    mut state: FrameState,
+) -> Result<(), String> {
+    if target >= starts.len() {
+        return Err("Execution can run past the end of the code".to_string());
+    }
+    if !starts[target] {
+        return Err(format!(
+            "Jump into the middle of an instruction at offset {target}"
+        ));
+    }
+
+    match &states[target] {
+        None => {}
+        Some(existing) if existing.height != state.height => {
+            return Err(format!("Inconsistent stack depth at offset {target}"));
+        }
+        Some(existing) => {
+            if state.captured.is_subset(&existing.captured) {
+                return Ok(());
+            }
+            state.captured.extend(existing.captured.iter().copied());
+        }
+    }
+    states[target] = Some(state);
+    pending.push(target);
+    Ok(())
+}
+
+fn instruction_size(op: OpCode) -> usize {
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::Jump
+        | OpCode::JumpIfFalse
+        | OpCode::Loop
+        | OpCode::Closure => 3,
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::CheckBool
+        | OpCode::Interpolate
+        | OpCode::Call => 2,
+        _ => 1,
+    }
+}
+
+fn constant_at<'a>(
+    constants: &'a [RawConstant],
+    code: &[u8],
+    offset: usize,
+) -> Result<&'a RawConstant, String> {
+    let index = u16::from_be_bytes([code[offset + 1], code[offset + 2]]) as usize;
+    constants
+        .get(index)
+        .ok_or_else(|| format!("Invalid constant index {index} at offset {offset}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler, lexer, parser::Parser, vm::Vm};
+
+    fn compile(input: &str) -> Rc<FunctionProto> {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        compiler::compile(&program).unwrap()
+    }
+
+    fn run(script: Rc<FunctionProto>) -> String {
+        let mut vm = Vm::new(Vec::new());
+        vm.run(script).unwrap();
+        String::from_utf8(vm.into_output()).unwrap()
+    }
+
+    // Replaces the body of a serialized file and fixes up its checksum, to
+    // get past the header checks.
+    fn with_body(mut bytes: Vec<u8>, edit: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut body = bytes.split_off(HEADER_LEN);
+        edit(&mut body);
+        bytes[6..10].copy_from_slice(&checksum(&body).to_be_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        deserialize(bytes).unwrap_err().message
+    }
+
+    const PROGRAM: &str = "
+        fn make_counter(step) {
+            let count = 0;
+            return fn() { count = count + step; return count; };
+        }
+        let counter = make_counter(5);
+        counter();
+        for (let i = 0; i < 3; i = i + 1) { if (i == 1) continue; print i; }
+        print counter() + 1;
+        print \"done\" + \"!\";
+        print 0.5 * 3;";
+
+    #[test]
+    fn test_round_trip() {
+        let script = compile(PROGRAM);
+        let bytes = serialize(&script);
+        assert_eq!(&bytes[..4], MAGIC);
+
+        let loaded = deserialize(&bytes).unwrap();
+        assert_eq!(loaded, script);
+        assert_eq!(run(loaded), "0\n2\n11\ndone!\n1.5\n");
+    }
+
+    #[test]
+    fn test_rejects_bad_header() {
+        let bytes = serialize(&compile("print 1;"));
+
+        assert_eq!(
+            error(b"print 1;"),
+            "Not a compiled script (missing magic number)"
+        );
+        assert_eq!(error(&bytes[..7]), "Unexpected end of file in header");
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_be_bytes());
+        assert_eq!(error(&newer), "Unsupported version 4, expected 3");
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(error(&flipped), "Checksum mismatch, the file is corrupt");
+    }
+
+    #[test]
+    fn test_rejects_truncated_and_trailing_data() {
+        let bytes = serialize(&compile("print 1;"));
+        let truncated = with_body(bytes.clone(), |body| {
+            body.pop();
+        });
+        assert!(error(&truncated).starts_with("Unexpected end of file"));
+
+        let extended = with_body(bytes, |body| body.push(0));
+        assert!(error(&extended).starts_with("Unexpected data after the last function"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_code() {
+        // A script that only holds `code`, with one string constant.
+        let script = |code: &[u8]| {
+            let mut body = Vec::new();
+            write_u32(&mut body, 1);
+            body.extend_from_slice(&[0, 0, 0]);
+            write_u32(&mut body, 1);
+            body.push(TAG_STRING);
+            write_string(&mut body, "x");
+            write_u32(&mut body, code.len());
+            body.extend_from_slice(code);
+            write_u32(&mut body, 0);
+            with_body(serialize(&compile("")), |b| *b = body)
+        };
+        let op = |op: OpCode| op as u8;
+
+        assert!(deserialize(&script(&[op(OpCode::Nil), op(OpCode::Return)])).is_ok());
+
+        for (code, message) in [
+            (vec![200], "Invalid opcode 200 at offset 0"),
+            (vec![op(OpCode::Constant), 0], "Truncated instruction at offset 0"),
+            (vec![op(OpCode::Constant), 0, 1], "Invalid constant index 1 at offset 0"),
+            (vec![op(OpCode::Nil)], "Execution can run past the end of the code"),
+            (vec![op(OpCode::Pop), op(OpCode::Return)], "Stack underflow at offset 0"),
+            (vec![op(OpCode::GetLocal), 1], "Invalid local slot at offset 0"),
+            (vec![op(OpCode::GetUpvalue), 0], "Invalid upvalue index at offset 0"),
+            (
+                vec![op(OpCode::Jump), 0, 1, op(OpCode::Constant), 0, 0, op(OpCode::Return)],
+                "Jump into the middle of an instruction at offset 4",
+            ),
+            (
+                vec![op(OpCode::Nil), op(OpCode::Loop), 0, 4, op(OpCode::Return)],
+                "Inconsistent stack depth at offset 0",
+            ),
+        ] {
+            assert_eq!(error(&script(&code)), format!("function 0: {message}"));
+        }
+    }
+
+    #[test]
+    fn test_rejects_closing_captured_slot_with_pop() {
+        let script = compile("{ let x = 1; fn f() { return x; } f(); }");
+        let bytes = serialize(&script);
+        assert!(deserialize(&bytes).is_ok());
+
+        // Replace the `CloseUpvalue` that ends the block with a plain `Pop`.
+        let close = OpCode::CloseUpvalue as u8;
+        let broken = with_body(bytes, |body| {
+            let index = body.iter().rposition(|&byte| byte == close).unwrap();
+            body[index] = OpCode::Pop as u8;
+        });
+        assert!(error(&broken).contains("Captured variable is popped without being closed"));
+    }
+
+    #[test]
+    fn test_rejects_missing_nested_function() {
+        // A one-function script with an unused constant naming function 99.
+        let mut body = Vec::new();
+        write_u32(&mut body, 1);
+        body.extend_from_slice(&[0, 0, 0]);
+        write_u32(&mut body, 1);
+        body.push(TAG_FUNCTION);
+        write_u32(&mut body, 99);
+        write_u32(&mut body, 2);
+        body.extend_from_slice(&[OpCode::Nil as u8, OpCode::Return as u8]);
+        write_u32(&mut body, 0);
+        let bytes = with_body(serialize(&compile("")), |b| *b = body);
+
+        assert_eq!(
+            error(&bytes),
+            "Function 0 refers to function 99 at byte 22, but the file only has 1"
+        );
+    }
+
+    #[test]
+    fn test_corrupt_bytes_never_panic() {
+        let bytes = serialize(&compile(PROGRAM));
+        for index in 0..bytes.len() - HEADER_LEN {
+            for bit in 0..8 {
+                let _ = deserialize(&with_body(bytes.clone(), |body| body[index] ^= 1 << bit));
+            }
+            let _ = deserialize(&with_body(bytes.clone(), |body| body.truncate(index)));
+        }
+    }
+}
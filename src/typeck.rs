@@ -0,0 +1,1014 @@
+//! An optional pass, run after the resolver, that infers a static type for
+//! every expression with Hindley–Milner inference and rejects ill-typed
+//! programs before they run.
+//!
+//! Parameters and anything else not yet known start out as type variables,
+//! which unification fills in. `let`s and function declarations are
+//! generalized, so `let id = fn(x) { x };` can be used at several types.
+//! Variables can be reassigned and captured by closures, so only syntactic
+//! values (function literals, literals and variables) are generalized, and
+//! variables with a polymorphic type can't be assigned to.
+//!
+//! `+` adds ints or concatenates strings, and adds ints when neither operand
+//! is known. `==` and `!=` compare values of any types, and conditions may
+//! have any type, following the interpreter's truthiness rules. Globals are
+//! looked up when the program runs, so a global used before its declaration
+//! gets a type variable the declaration must agree with, and redeclaring a
+//! global must keep its type.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    BinaryOp, Expr, Function, InterpolationPart, Program, Stmt, Token, TypeExpr, UnaryOp,
+    always_returns,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    String,
+    /// The result of a function that doesn't return a value.
+    Nil,
+    Function {
+        params: Vec<Type>,
+        ret: Box<Type>,
+    },
+    /// A type variable, shown as `'a`, `'b`, ...
+    Var(u32),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Nil => write!(f, "nil"),
+            Type::Function { params, ret } => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{param}")?;
+                }
+                write!(f, ") -> {ret}")
+            }
+            Type::Var(n) if *n < 26 => write!(f, "'{}", (b'a' + *n as u8) as char),
+            Type::Var(n) => write!(f, "'t{n}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TypeError {
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl TypeError {
+    fn new(message: String, line: u32, column: u32) -> TypeError {
+        TypeError {
+            message,
+            line,
+            column,
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// A type whose `vars` may be instantiated with any types at each use.
+#[derive(Clone)]
+struct Scheme {
+    vars: Vec<u32>,
+    ty: Type,
+}
+
+impl Scheme {
+    fn monomorphic(ty: Type) -> Self {
+        Scheme {
+            vars: Vec::new(),
+            ty,
+        }
+    }
+}
+
+/// Why two types failed to unify.
+enum Mismatch {
+    Types,
+    /// The variable would have to contain itself.
+    Infinite(u32, Type),
+}
+
+/// The function whose body is being checked.
+struct FunctionContext {
+    ret: Type,
+    // Whether `ret` comes from an annotation.
+    declared: bool,
+    // Position of the return type annotation, or else of the first `return`
+    // with a value.
+    first_return: Option<(u32, u32)>,
+}
+
+struct Checker {
+    // The first scope holds the globals.
+    scopes: Vec<HashMap<String, Scheme>>,
+    // Globals used before their declaration.
+    forward: HashSet<String>,
+    // What each type variable has been unified with.
+    substitution: Vec<Option<Type>>,
+    functions: Vec<FunctionContext>,
+}
+
+/// Type checks a resolved program.
+pub fn check(program: &Program) -> Result<(), TypeError> {
+    Checker::new().check_statements(&program.statements)
+}
+
+/// Like [`check`], for a standalone expression, returning its type.
+pub fn check_expr(expr: &Expr) -> Result<Type, TypeError> {
+    let mut checker = Checker::new();
+    let ty = checker.infer(expr)?;
+    Ok(checker.normalize(&[&ty]).remove(0))
+}
+
+impl Checker {
+    fn new() -> Self {
+        Checker {
+            scopes: vec![HashMap::new()],
+            forward: HashSet::new(),
+            substitution: Vec::new(),
+            functions: Vec::new(),
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+        Type::Var(self.substitution.len() as u32 - 1)
+    }
+
+    /// Follows `ty` through the substitution until it isn't a bound
+    /// variable.
+    fn shallow(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(n) = ty {
+            match &self.substitution[n as usize] {
+                Some(bound) => ty = bound.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// Applies the substitution everywhere in `ty`.
+    fn resolve(&self, ty: &Type) -> Type {
+        match self.shallow(ty) {
+            Type::Function { params, ret } => Type::Function {
+                params: params.iter().map(|param| self.resolve(param)).collect(),
+                ret: Box::new(self.resolve(&ret)),
+            },
+            ty => ty,
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), Mismatch> {
+        match (self.shallow(a), self.shallow(b)) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(x), ty) | (ty, Type::Var(x)) => {
+                let ty = self.resolve(&ty);
+                if free_vars(&ty).contains(&x) {
+                    return Err(Mismatch::Infinite(x, ty));
+                }
+                self.substitution[x as usize] = Some(ty);
+                Ok(())
+            }
+            (
+                Type::Function { params, ret },
+                Type::Function {
+                    params: other_params,
+                    ret: other_ret,
+                },
+            ) => {
+                if params.len() != other_params.len() {
+                    return Err(Mismatch::Types);
+                }
+                for (a, b) in params.iter().zip(&other_params) {
+                    self.unify(a, b)?;
+                }
+                self.unify(&ret, &other_ret)
+            }
+            (a, b) if a == b => Ok(()),
+            _ => Err(Mismatch::Types),
+        }
+    }
+
+    /// Unifies `actual` with `expected`, describing a mismatch with
+    /// `message`, which is given both types.
+    fn expect(
+        &mut self,
+        actual: &Type,
+        expected: &Type,
+        line: u32,
+        column: u32,
+        message: impl FnOnce(&str, &str) -> String,
+    ) -> Result<(), TypeError> {
+        let message = match self.unify(actual, expected) {
+            Ok(()) => return Ok(()),
+            Err(Mismatch::Types) => {
+                let names = self.describe(&[actual, expected]);
+                message(&names[0], &names[1])
+            }
+            Err(Mismatch::Infinite(var, ty)) => {
+                let names = self.describe(&[&Type::Var(var), &ty]);
+                format!(
+                    "Infinite type: {} would contain itself in {}",
+                    names[0], names[1]
+                )
+            }
+        };
+        Err(TypeError::new(message, line, column))
+    }
+
+    /// The type arithmetic on operands of `types` works in: float if any
+    /// of them is already known to be a float, and int otherwise.
+    fn number_type(&self, types: &[&Type]) -> Type {
+        if types.iter().any(|ty| self.shallow(ty) == Type::Float) {
+            Type::Float
+        } else {
+            Type::Int
+        }
+    }
+
+    /// Resolves `types` and renames their variables `'a`, `'b`, ... in
+    /// order of appearance.
+    fn normalize(&self, types: &[&Type]) -> Vec<Type> {
+        let mut names = HashMap::new();
+        types
+            .iter()
+            .map(|ty| rename(&self.resolve(ty), &mut names))
+            .collect()
+    }
+
+    fn describe(&self, types: &[&Type]) -> Vec<String> {
+        self.normalize(types)
+            .iter()
+            .map(|ty| ty.to_string())
+            .collect()
+    }
+
+    /// Quantifies the variables of `ty` that don't appear in the
+    /// environment.
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let mut environment = HashSet::new();
+        for scheme in self.scopes.iter().flat_map(|scope| scope.values()) {
+            let bound = free_vars(&self.resolve(&scheme.ty));
+            environment.extend(bound.into_iter().filter(|var| !scheme.vars.contains(var)));
+        }
+        let mut vars: Vec<u32> = free_vars(&ty)
+            .into_iter()
+            .filter(|var| !environment.contains(var))
+            .collect();
+        vars.dedup();
+        Scheme { vars, ty }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mut fresh = HashMap::new();
+        for var in &scheme.vars {
+            let ty = self.fresh();
+            fresh.insert(*var, ty);
+        }
+        substitute(&self.resolve(&scheme.ty), &fresh)
+    }
+
+    fn lookup(&mut self, name: &Token) -> Scheme {
+        if let Some(scheme) = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+        {
+            return scheme.clone();
+        }
+
+        // A global declared later, or never.
+        let scheme = Scheme::monomorphic(self.fresh());
+        self.scopes[0].insert(name.lexeme.clone(), scheme.clone());
+        self.forward.insert(name.lexeme.clone());
+        scheme
+    }
+
+    /// Binds `name` to `ty` in the innermost scope, generalizing it if it's
+    /// the type of a value.
+    fn define(&mut self, name: &Token, ty: Type, is_value: bool) -> Result<(), TypeError> {
+        if self.scopes.len() == 1
+            && let Some(existing) = self.scopes[0].remove(&name.lexeme)
+        {
+            self.forward.remove(&name.lexeme);
+            let existing = self.instantiate(&existing);
+            self.expect(&ty, &existing, name.line, name.column, |new, old| {
+                format!(
+                    "Global '{}' has type {old} and can't be redeclared with type {new}",
+                    name.lexeme
+                )
+            })?;
+        }
+
+        let scheme = if is_value {
+            self.generalize(&ty)
+        } else {
+            Scheme::monomorphic(ty)
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.lexeme.clone(), scheme);
+        Ok(())
+    }
+
+    fn check_statements(&mut self, statements: &[Stmt]) -> Result<(), TypeError> {
+        statements
+            .iter()
+            .try_for_each(|statement| self.check_statement(statement))
+    }
+
+    fn check_statement(&mut self, stmt: &Stmt) -> Result<(), TypeError> {
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print { value: expr, .. } => {
+                self.infer(expr)?;
+            }
+            Stmt::Let {
+                name,
+                annotation,
+                initializer,
+            } => {
+                let mut ty = self.infer(initializer)?;
+                if let Some(annotation) = annotation {
+                    let declared = annotation_type(annotation)?;
+                    self.expect(&ty, &declared, name.line, name.column, |ty, declared| {
+                        format!(
+                            "Can't initialize '{}', declared as {declared}, with {ty}",
+                            name.lexeme
+                        )
+                    })?;
+                    ty = declared;
+                }
+                self.define(name, ty, is_value(initializer))?;
+            }
+            Stmt::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                let result = self.check_statements(statements);
+                self.scopes.pop();
+                result?;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.infer(condition)?;
+                self.check_statement(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.check_statement(else_branch)?;
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.infer(condition)?;
+                self.check_statement(body)?;
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.scopes.push(HashMap::new());
+                let result = self.check_for(initializer, condition, increment, body);
+                self.scopes.pop();
+                result?;
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Function(function) => {
+                // Inside its body the function is monomorphic, so recursive
+                // calls agree with each other.
+                let name = function.name.as_ref().unwrap();
+                let var = self.fresh();
+                self.scopes.push(HashMap::from([(
+                    name.lexeme.clone(),
+                    Scheme::monomorphic(var.clone()),
+                )]));
+                let result = self.infer_function(function);
+                self.scopes.pop();
+                let ty = result?;
+
+                self.expect(&var, &ty, name.line, name.column, |used, actual| {
+                    format!(
+                        "Function '{}' has type {actual} but is called recursively as {used}",
+                        name.lexeme
+                    )
+                })?;
+                self.define(name, ty, true)?;
+            }
+            Stmt::Return {
+                value,
+                line,
+                column,
+            } => {
+                let ty = match value {
+                    Some(value) => self.infer(value)?,
+                    None => Type::Nil,
+                };
+                let context = self.functions.last_mut().unwrap();
+                if value.is_some() && context.first_return.is_none() {
+                    context.first_return = Some((*line, *column));
+                }
+                let (ret, declared) = (context.ret.clone(), context.declared);
+                self.expect(&ty, &ret, *line, *column, |ty, ret| {
+                    if declared {
+                        format!("Function is declared to return {ret} but returns {ty}")
+                    } else {
+                        format!("Function returns both {ret} and {ty}")
+                    }
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_for(
+        &mut self,
+        initializer: &Option<Box<Stmt>>,
+        condition: &Option<Expr>,
+        increment: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<(), TypeError> {
+        if let Some(initializer) = initializer {
+            self.check_statement(initializer)?;
+        }
+        if let Some(condition) = condition {
+            self.infer(condition)?;
+        }
+        if let Some(increment) = increment {
+            self.infer(increment)?;
+        }
+        self.check_statement(body)
+    }
+
+    fn infer_function(&mut self, function: &Function) -> Result<Type, TypeError> {
+        let mut params = Vec::new();
+        let mut scope = HashMap::new();
+        for (param, annotation) in function.params.iter().zip(&function.param_types) {
+            let ty = match annotation {
+                Some(annotation) => annotation_type(annotation)?,
+                None => self.fresh(),
+            };
+            scope.insert(param.lexeme.clone(), Scheme::monomorphic(ty.clone()));
+            params.push(ty);
+        }
+        let (ret, first_return) = match &function.return_type {
+            Some(annotation) => (annotation_type(annotation)?, Some(position(annotation))),
+            None => (self.fresh(), None),
+        };
+        let declared = first_return.is_some();
+
+        self.scopes.push(scope);
+        self.functions.push(FunctionContext {
+            ret: ret.clone(),
+            declared,
+            first_return,
+        });
+        let result = self.check_statements(&function.body);
+        let context = self.functions.pop().unwrap();
+        self.scopes.pop();
+        result?;
+
+        // Falling off the end returns nil.
+        if !always_returns(&function.body) {
+            let (line, column) = context.first_return.unwrap_or((0, 0));
+            self.expect(&Type::Nil, &ret, line, column, |_, ret| {
+                if declared {
+                    format!(
+                        "Function is declared to return {ret} but can end without returning a value"
+                    )
+                } else {
+                    format!("Function returns {ret} but can also end without returning a value")
+                }
+            })?;
+        }
+        Ok(Type::Function {
+            params,
+            ret: Box::new(ret),
+        })
+    }
+
+    fn infer(&mut self, expr: &Expr) -> Result<Type, TypeError> {
+        match expr {
+            Expr::Number(_) => Ok(Type::Int),
+            Expr::Float(_) => Ok(Type::Float),
+            Expr::String(_) => Ok(Type::String),
+            Expr::Bool(_) => Ok(Type::Bool),
+            Expr::Grouping(inner) => self.infer(inner),
+            Expr::Unary {
+                operator,
+                operand,
+                line,
+                column,
+            } => {
+                let ty = self.infer(operand)?;
+                let (expected, symbol) = match operator {
+                    UnaryOp::Negate => (self.number_type(&[&ty]), "-"),
+                    UnaryOp::Not => (Type::Bool, "!"),
+                };
+                self.expect(&ty, &expected, *line, *column, |ty, expected| {
+                    format!("Operand of '{symbol}' must be {expected}, got {ty}")
+                })?;
+                Ok(expected)
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                line,
+                column,
+            } => {
+                let left = self.infer(left)?;
+                let right = self.infer(right)?;
+                let number = self.number_type(&[&left, &right]);
+                let (operands, result) = match operator {
+                    BinaryOp::Equal | BinaryOp::NotEqual => return Ok(Type::Bool),
+                    BinaryOp::And | BinaryOp::Or => (Type::Bool, Type::Bool),
+                    BinaryOp::Less
+                    | BinaryOp::LessEqual
+                    | BinaryOp::Greater
+                    | BinaryOp::GreaterEqual => (number, Type::Bool),
+                    BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => (number.clone(), number),
+                    BinaryOp::Add => {
+                        let is_string = |ty: Type| ty == Type::String;
+                        if is_string(self.shallow(&left)) || is_string(self.shallow(&right)) {
+                            (Type::String, Type::String)
+                        } else {
+                            (number.clone(), number)
+                        }
+                    }
+                };
+
+                // An int operand is converted when the other one is a float.
+                let mut fits = |ty: &Type| {
+                    operands == Type::Float && self.shallow(ty) == Type::Int
+                        || self.unify(ty, &operands).is_ok()
+                };
+                if !fits(&left) || !fits(&right) {
+                    let names = self.describe(&[&left, &right]);
+                    return Err(TypeError::new(
+                        format!(
+                            "Invalid operand types for '{}': {} and {}",
+                            operator.symbol(),
+                            names[0],
+                            names[1]
+                        ),
+                        *line,
+                        *column,
+                    ));
+                }
+                Ok(result)
+            }
+            Expr::Variable { name, .. } => {
+                let scheme = self.lookup(name);
+                Ok(self.instantiate(&scheme))
+            }
+            Expr::Assign { name, value, .. } => {
+                let ty = self.infer(value)?;
+                let scheme = self.lookup(name);
+                if !scheme.vars.is_empty() {
+                    let names = self.describe(&[&scheme.ty]);
+                    return Err(TypeError::new(
+                        format!(
+                            "Can't assign to '{}', which has polymorphic type {}",
+                            name.lexeme, names[0]
+                        ),
+                        name.line,
+                        name.column,
+                    ));
+                }
+                self.expect(&ty, &scheme.ty, name.line, name.column, |ty, expected| {
+                    format!(
+                        "Can't assign {ty} to '{}', which has type {expected}",
+                        name.lexeme
+                    )
+                })?;
+                Ok(ty)
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                line,
+                column,
+            } => {
+                let callee = self.infer(callee)?;
+                let mut types = Vec::new();
+                for argument in arguments {
+                    types.push(self.infer(argument)?);
+                }
+
+                match self.shallow(&callee) {
+                    Type::Function { params, ret } => {
+                        if params.len() != types.len() {
+                            return Err(TypeError::new(
+                                format!(
+                                    "Expected {} arguments but got {}",
+                                    params.len(),
+                                    types.len()
+                                ),
+                                *line,
+                                *column,
+                            ));
+                        }
+                        for (i, (param, ty)) in params.iter().zip(&types).enumerate() {
+                            self.expect(ty, param, *line, *column, |ty, param| {
+                                format!("Argument {} must be {param}, got {ty}", i + 1)
+                            })?;
+                        }
+                        Ok(*ret)
+                    }
+                    Type::Var(_) => {
+                        let ret = self.fresh();
+                        let expected = Type::Function {
+                            params: types,
+                            ret: Box::new(ret.clone()),
+                        };
+                        self.expect(&callee, &expected, *line, *column, |callee, expected| {
+                            format!("Can't call {callee} as {expected}")
+                        })?;
+                        Ok(ret)
+                    }
+                    ty => {
+                        let names = self.describe(&[&ty]);
+                        Err(TypeError::new(
+                            format!("Can only call functions, got {}", names[0]),
+                            *line,
+                            *column,
+                        ))
+                    }
+                }
+            }
+            Expr::Function(function) => self.infer_function(function),
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    if let InterpolationPart::Expr(expr) = part {
+                        self.infer(expr)?;
+                    }
+                }
+                Ok(Type::String)
+            }
+        }
+    }
+}
+
+/// The type an annotation stands for.
+fn annotation_type(annotation: &TypeExpr) -> Result<Type, TypeError> {
+    match annotation {
+        TypeExpr::Named(name) => match name.lexeme.as_str() {
+            "int" => Ok(Type::Int),
+            "float" => Ok(Type::Float),
+            "bool" => Ok(Type::Bool),
+            "string" => Ok(Type::String),
+            "nil" => Ok(Type::Nil),
+            _ => Err(TypeError::new(
+                format!("Unknown type '{}'", name.lexeme),
+                name.line,
+                name.column,
+            )),
+        },
+        TypeExpr::Function { params, ret, .. } => Ok(Type::Function {
+            params: params
+                .iter()
+                .map(annotation_type)
+                .collect::<Result<_, _>>()?,
+            ret: Box::new(annotation_type(ret)?),
+        }),
+    }
+}
+
+fn position(annotation: &TypeExpr) -> (u32, u32) {
+    match annotation {
+        TypeExpr::Named(name) => (name.line, name.column),
+        TypeExpr::Function { line, column, .. } => (*line, *column),
+    }
+}
+
+/// The variables in `ty`, in order of appearance.
+fn free_vars(ty: &Type) -> Vec<u32> {
+    match ty {
+        Type::Var(n) => vec![*n],
+        Type::Function { params, ret } => params
+            .iter()
+            .chain([ret.as_ref()])
+            .flat_map(free_vars)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn substitute(ty: &Type, vars: &HashMap<u32, Type>) -> Type {
+    match ty {
+        Type::Var(n) => vars.get(n).cloned().unwrap_or(Type::Var(*n)),
+        Type::Function { params, ret } => Type::Function {
+            params: params.iter().map(|param| substitute(param, vars)).collect(),
+            ret: Box::new(substitute(ret, vars)),
+        },
+        ty => ty.clone(),
+    }
+}
+
+fn rename(ty: &Type, names: &mut HashMap<u32, u32>) -> Type {
+    match ty {
+        Type::Var(n) => {
+            let next = names.len() as u32;
+            Type::Var(*names.entry(*n).or_insert(next))
+        }
+        Type::Function { params, ret } => Type::Function {
+            params: params.iter().map(|param| rename(param, names)).collect(),
+            ret: Box::new(rename(ret, names)),
+        },
+        ty => ty.clone(),
+    }
+}
+
+/// Whether the type of `expr` may be generalized: evaluating it can't
+/// create a variable its type depends on.
+fn is_value(expr: &Expr) -> bool {
+    match expr {
+        Expr::Number(_)
+        | Expr::Float(_)
+        | Expr::String(_)
+        | Expr::Bool(_)
+        | Expr::Variable { .. }
+        | Expr::Function(_) => true,
+        Expr::Grouping(inner) => is_value(inner),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser::Parser, resolver};
+
+    fn check_source(input: &str) -> Result<(), TypeError> {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        resolver::resolve(&program).unwrap();
+        check(&program)
+    }
+
+    fn type_of(input: &str) -> Type {
+        let tokens = lexer::scan(input).unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        resolver::resolve_expr(&expr).unwrap();
+        check_expr(&expr).unwrap()
+    }
+
+    fn error(input: &str) -> (String, u32, u32) {
+        let error = check_source(input).unwrap_err();
+        (error.message, error.line, error.column)
+    }
+
+    #[test]
+    fn test_expression_types() {
+        assert_eq!(type_of("1 + 2 * 3"), Type::Int);
+        assert_eq!(type_of("\"a\" + \"b\""), Type::String);
+        assert_eq!(type_of("1 < 2 and !false"), Type::Bool);
+        assert_eq!(type_of("1 == \"1\""), Type::Bool);
+        assert_eq!(type_of("\"${1} ${fn() {}}\""), Type::String);
+        assert_eq!(
+            type_of("fn(a, b) { return a + 1; }"),
+            Type::Function {
+                params: vec![Type::Int, Type::Var(0)],
+                ret: Box::new(Type::Int)
+            }
+        );
+        assert_eq!(type_of("fn() {}").to_string(), "fn() -> nil");
+        assert_eq!(type_of("fn(a) { if (a) return 1; else return 2; }").to_string(), "fn('a) -> int");
+        assert_eq!(type_of("fn(f, x) { return f(x); }").to_string(), "fn(fn('a) -> 'b, 'a) -> 'b");
+        assert_eq!(type_of("fn(a, b) { return a + b; }").to_string(), "fn(int, int) -> int");
+        assert_eq!(type_of("fn(a, b) { return a + \"\" + b; }").to_string(), "fn(string, string) -> string");
+    }
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(type_of("1.5 * 2"), Type::Float);
+        assert_eq!(type_of("1 - 0.5 < 3"), Type::Bool);
+        assert_eq!(type_of("-.5"), Type::Float);
+        assert_eq!(type_of("fn(a) { return a * 2.0; }").to_string(), "fn(float) -> float");
+        assert_eq!(type_of("fn(a: int) { return a * 2.0; }").to_string(), "fn(int) -> float");
+        assert_eq!(
+            error("let x = 1;\nx = 1.5;"),
+            ("Can't assign float to 'x', which has type int".to_string(), 2, 1)
+        );
+        assert_eq!(
+            error("print 1.5 + true;"),
+            ("Invalid operand types for '+': float and bool".to_string(), 1, 11)
+        );
+    }
+
+    #[test]
+    fn test_operator_errors_point_at_operator() {
+        assert_eq!(
+            error("let a = 1;\nprint \"a\" + a;"),
+            ("Invalid operand types for '+': string and int".to_string(), 2, 11)
+        );
+        assert_eq!(
+            error("print 1 < true;"),
+            ("Invalid operand types for '<': int and bool".to_string(), 1, 9)
+        );
+        assert_eq!(
+            error("print 1 and true;"),
+            ("Invalid operand types for 'and': int and bool".to_string(), 1, 9)
+        );
+        assert_eq!(
+            error("print -\"a\";"),
+            ("Operand of '-' must be int, got string".to_string(), 1, 7)
+        );
+        assert_eq!(
+            error("print !1;"),
+            ("Operand of '!' must be bool, got int".to_string(), 1, 7)
+        );
+    }
+
+    #[test]
+    fn test_variables_keep_their_type() {
+        assert_eq!(
+            error("let a = 1;\na = \"one\";"),
+            ("Can't assign string to 'a', which has type int".to_string(), 2, 1)
+        );
+        assert_eq!(
+            error("let s = \"a\";\n{ let t = s + s; print t - 1; }"),
+            ("Invalid operand types for '-': string and int".to_string(), 2, 26)
+        );
+        assert!(check_source("let a = 1; { let a = \"shadow\"; print a + \"!\"; } a = 2;").is_ok());
+    }
+
+    #[test]
+    fn test_calls() {
+        assert_eq!(
+            error("fn f(a) { return a; }\nprint f(1, 2);"),
+            ("Expected 1 arguments but got 2".to_string(), 2, 8)
+        );
+        assert_eq!(
+            error("let x = 1;\nx();"),
+            ("Can only call functions, got int".to_string(), 2, 2)
+        );
+        assert_eq!(
+            error("fn name() { return \"n\"; }\nprint name() * 2;"),
+            ("Invalid operand types for '*': string and int".to_string(), 2, 14)
+        );
+        assert_eq!(
+            error("fn f() {}\nprint f() + 1;"),
+            ("Invalid operand types for '+': nil and int".to_string(), 2, 11)
+        );
+    }
+
+    #[test]
+    fn test_inference() {
+        assert!(
+            check_source(
+                "fn fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+                 print fib(10) + 1;
+                 fn show(x) { print \"value: \" + x; }
+                 show(\"1\");
+                 fn apply(f, x) { return f(x); }
+                 print apply(fn(x) { return x * 2; }, 21) - 1;
+                 fn later() { return global + 1; }
+                 let global = 1;
+                 if (1) print \"truthy\";"
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            error("fn show(x) { print \"value: \" + x; }\nshow(1);"),
+            ("Argument 1 must be string, got int".to_string(), 2, 5)
+        );
+        assert_eq!(
+            error("fn f(n) { print n + 1; return n and true; }"),
+            ("Invalid operand types for 'and': int and bool".to_string(), 1, 33)
+        );
+        assert_eq!(
+            error("fn later() { return global + 1; }\nlet global = \"g\";"),
+            ("Global 'global' has type int and can't be redeclared with type string".to_string(), 2, 5)
+        );
+    }
+
+    #[test]
+    fn test_let_polymorphism() {
+        assert!(
+            check_source(
+                "let id = fn(x) { x };
+                 print id(1) + 1;
+                 print id(\"a\") + \"b\";
+                 print !id(true);
+                 fn twice(f, x) { return f(f(x)); }
+                 print twice(id, 1) * twice(fn(n) { n + 1 }, 2);
+                 { let pick = fn(a, b) { a }; print pick(1, \"a\") - pick(2, true); }"
+            )
+            .is_ok()
+        );
+        assert_eq!(type_of("fn(x) { let id = fn(y) { y }; return id(id)(x); }").to_string(), "fn('a) -> 'a");
+
+        // Only values are generalized: `make()` could return a shared cell.
+        assert_eq!(
+            error("fn make() { return fn(x) { x }; }\nlet f = make();\nprint f(1) + f(\"a\");"),
+            ("Argument 1 must be int, got string".to_string(), 3, 15)
+        );
+        assert_eq!(
+            error("let id = fn(x) { x };\nid = fn(x) { x + 1 };"),
+            ("Can't assign to 'id', which has polymorphic type fn('a) -> 'a".to_string(), 2, 1)
+        );
+        // Parameters are monomorphic.
+        assert_eq!(
+            error("fn f(g) { print g(1); print g(\"a\"); }"),
+            ("Argument 1 must be int, got string".to_string(), 1, 30)
+        );
+    }
+
+    #[test]
+    fn test_conflicting_types_are_both_shown() {
+        assert_eq!(
+            error("fn f(a) {\n  if (a) return 1;\n  return \"one\";\n}"),
+            ("Function returns both int and string".to_string(), 3, 3)
+        );
+        assert_eq!(
+            error("fn f(a) {\n  if (a) return 1;\n}"),
+            ("Function returns int but can also end without returning a value".to_string(), 2, 10)
+        );
+        assert_eq!(
+            error("fn apply(f) { return f(1); }\nprint apply(fn(s) { s + \"!\" });"),
+            ("Argument 1 must be fn(int) -> 'a, got fn(string) -> string".to_string(), 2, 12)
+        );
+        assert_eq!(
+            error("fn f(n) { return f(n, n); }"),
+            ("Function 'f' has type fn('a) -> 'b but is called recursively as fn('a, 'a) -> 'b".to_string(), 1, 4)
+        );
+    }
+
+    #[test]
+    fn test_occurs_check() {
+        assert_eq!(
+            error("fn f(x) { return x(x); }"),
+            ("Infinite type: 'a would contain itself in fn('a) -> 'b".to_string(), 1, 19)
+        );
+        assert_eq!(
+            error("fn f(x) { return f; }"),
+            ("Infinite type: 'a would contain itself in fn('b) -> 'a".to_string(), 1, 4)
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        assert!(
+            check_source(
+                "let id = fn(x) { x };
+                 let f: fn(int) -> int = id;
+                 fn add(a: int, b: int) -> int { a + b }
+                 fn show(s: string) { print s; }
+                 let n: nil = show(\"a\");"
+            )
+            .is_ok()
+        );
+        assert_eq!(type_of("fn(a: string, b) -> bool { b }").to_string(), "fn(string, bool) -> bool");
+        assert_eq!(
+            error("let x: int = \"one\";"),
+            ("Can't initialize 'x', declared as int, with string".to_string(), 1, 5)
+        );
+        assert_eq!(
+            error("fn f(a: string) { return a - 1; }"),
+            ("Invalid operand types for '-': string and int".to_string(), 1, 28)
+        );
+        assert_eq!(
+            error("fn f() -> int {\n  return \"s\";\n}"),
+            ("Function is declared to return int but returns string".to_string(), 2, 3)
+        );
+        assert_eq!(
+            error("fn f(a) -> int {\n  if (a) return 1;\n}"),
+            ("Function is declared to return int but can end without returning a value".to_string(), 1, 12)
+        );
+        assert_eq!(
+            error("let x: number = 1;"),
+            ("Unknown type 'number'".to_string(), 1, 8)
+        );
+    }
+}
@@ -0,0 +1,620 @@
+use std::{cell::RefCell, collections::HashMap, fmt, io::Write, ptr, rc::Rc};
+
+use crate::{
+    RuntimeError,
+    chunk::{Constant, FunctionProto, OpCode},
+};
+
+/// Deepest call nesting allowed before reporting a stack overflow, matching
+/// the tree-walking interpreter.
+const MAX_FRAMES: usize = 200;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(i64),
+    Float(f64),
+    String(Rc<str>),
+    Bool(bool),
+    Nil,
+    Function(Rc<Closure>),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::Float(l), Value::Float(r)) => l == r,
+            (Value::Number(i), Value::Float(f)) | (Value::Float(f), Value::Number(i)) => {
+                *i as f64 == *f
+            }
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Function(l), Value::Function(r)) => l == r,
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Bool(false) | Value::Nil)
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Nil => "nil",
+            Value::Function(_) => "function",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Float(n) => write!(f, "{n:?}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Nil => write!(f, "nil"),
+            Value::Function(closure) => match &closure.function.name {
+                Some(name) => write!(f, "<fn {name}>"),
+                None => write!(f, "<fn>"),
+            },
+        }
+    }
+}
+
+/// A variable captured by a closure: a stack slot while the declaring
+/// function is running, then a value of its own once that slot goes away.
+#[derive(Debug)]
+enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    function: Rc<FunctionProto>,
+    upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Stack index of the callee; arguments and locals follow it.
+    base: usize,
+}
+
+impl CallFrame {
+    fn read_byte(&mut self) -> u8 {
+        let byte = self.closure.function.chunk.code[self.ip];
+        self.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let value = self.closure.function.chunk.read_u16(self.ip);
+        self.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> &Constant {
+        let index = self.read_u16() as usize;
+        &self.closure.function.chunk.constants[index]
+    }
+
+    fn read_name(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Constant::String(name) => Rc::clone(name),
+            constant => panic!("Expected a name constant, got {constant:?}"),
+        }
+    }
+}
+
+pub struct Vm<W: Write> {
+    out: W,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    // Sorted by stack slot.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl<W: Write> Vm<W> {
+    pub fn new(out: W) -> Self {
+        Vm {
+            out,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+        }
+    }
+
+    pub fn into_output(self) -> W {
+        self.out
+    }
+
+    /// Runs a script produced by [`crate::compiler::compile`]. Globals
+    /// persist between runs.
+    pub fn run(&mut self, script: Rc<FunctionProto>) -> Result<(), RuntimeError> {
+        let closure = Rc::new(Closure {
+            function: script,
+            upvalues: Vec::new(),
+        });
+        self.stack.push(Value::Function(Rc::clone(&closure)));
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: 0,
+        });
+
+        let result = self.execute();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn execute(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            let offset = self.frame().ip;
+            let byte = self.frame().read_byte();
+            let op = OpCode::from_byte(byte)
+                .unwrap_or_else(|| panic!("Invalid opcode {byte} at offset {offset}"));
+
+            match op {
+                OpCode::Constant => {
+                    let value = match self.frame().read_constant() {
+                        Constant::Number(n) => Value::Number(*n),
+                        Constant::Float(n) => Value::Float(*n),
+                        Constant::String(s) => Value::String(Rc::clone(s)),
+                        Constant::Function(_) => unreachable!("functions are loaded by Closure"),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.stack.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().read_byte() as usize;
+                    let base = self.frame().base;
+                    self.stack.push(self.stack[base + slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().read_byte() as usize;
+                    let base = self.frame().base;
+                    self.stack[base + slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.frame().read_name();
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => {
+                            return Err(self.error(offset, format!("Undefined variable '{name}'")));
+                        }
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.frame().read_name();
+                    let value = self.stack.pop().unwrap();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.frame().read_name();
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(slot) => *slot = value,
+                        None => {
+                            return Err(self.error(
+                                offset,
+                                format!("Cannot assign to undeclared variable '{name}'"),
+                            ));
+                        }
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.frame().read_byte() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.frame().read_byte() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = self.peek(0).clone();
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::Equal => {
+                    let right = self.stack.pop().unwrap();
+                    let left = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(left == right));
+                }
+                OpCode::NotEqual => {
+                    let right = self.stack.pop().unwrap();
+                    let left = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(left != right));
+                }
+                OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide => {
+                    let right = self.stack.pop().unwrap();
+                    let left = self.stack.pop().unwrap();
+                    let value = self.binary(op, left, right, offset)?;
+                    self.stack.push(value);
+                }
+                OpCode::Not => match self.stack.pop().unwrap() {
+                    Value::Bool(b) => self.stack.push(Value::Bool(!b)),
+                    operand => {
+                        return Err(self.error(
+                            offset,
+                            format!("Operand of '!' must be a bool, got {}", operand.type_name()),
+                        ));
+                    }
+                },
+                OpCode::Negate => match self.stack.pop().unwrap() {
+                    Value::Number(n) => match n.checked_neg() {
+                        Some(n) => self.stack.push(Value::Number(n)),
+                        None => return Err(self.error(offset, "Integer overflow".to_string())),
+                    },
+                    Value::Float(n) => self.stack.push(Value::Float(-n)),
+                    operand => {
+                        return Err(self.error(
+                            offset,
+                            format!(
+                                "Operand of '-' must be a number, got {}",
+                                operand.type_name()
+                            ),
+                        ));
+                    }
+                },
+                OpCode::CheckBool => {
+                    let operator = if self.frame().read_byte() == 0 {
+                        "and"
+                    } else {
+                        "or"
+                    };
+                    if !matches!(self.peek(0), Value::Bool(_)) {
+                        let message = format!(
+                            "Operands of '{operator}' must be bools, got {}",
+                            self.peek(0).type_name()
+                        );
+                        return Err(self.error(offset, message));
+                    }
+                }
+                OpCode::Interpolate => {
+                    let count = self.frame().read_byte() as usize;
+                    let values = self.stack.split_off(self.stack.len() - count);
+                    let s: String = values.iter().map(|value| value.to_string()).collect();
+                    self.stack.push(Value::String(s.into()));
+                }
+                OpCode::Print => {
+                    let value = self.stack.pop().unwrap();
+                    if let Err(error) = writeln!(self.out, "{value}") {
+                        return Err(self.error(offset, format!("Failed to print: {error}")));
+                    }
+                }
+                OpCode::Jump => {
+                    let distance = self.frame().read_u16() as usize;
+                    self.frame().ip += distance;
+                }
+                OpCode::JumpIfFalse => {
+                    let distance = self.frame().read_u16() as usize;
+                    if !self.peek(0).is_truthy() {
+                        self.frame().ip += distance;
+                    }
+                }
+                OpCode::Loop => {
+                    let distance = self.frame().read_u16() as usize;
+                    self.frame().ip -= distance;
+                }
+                OpCode::Call => {
+                    let argument_count = self.frame().read_byte() as usize;
+                    self.call(argument_count, offset)?;
+                }
+                OpCode::Closure => {
+                    let function = match self.frame().read_constant() {
+                        Constant::Function(function) => Rc::clone(function),
+                        constant => panic!("Expected a function constant, got {constant:?}"),
+                    };
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count as usize);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.frame().read_byte() == 1;
+                        let index = self.frame().read_byte() as usize;
+                        let upvalue = if is_local {
+                            let slot = self.frame().base + index;
+                            self.capture_upvalue(slot)
+                        } else {
+                            Rc::clone(&self.frame().closure.upvalues[index])
+                        };
+                        upvalues.push(upvalue);
+                    }
+
+                    self.stack
+                        .push(Value::Function(Rc::new(Closure { function, upvalues })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                OpCode::Return => {
+                    let result = self.stack.pop().unwrap();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+            }
+        }
+    }
+
+    fn binary(
+        &self,
+        op: OpCode,
+        left: Value,
+        right: Value,
+        offset: usize,
+    ) -> Result<Value, RuntimeError> {
+        match (op, left, right) {
+            (OpCode::Add, Value::String(l), Value::String(r)) => {
+                Ok(Value::String(format!("{l}{r}").into()))
+            }
+
+            (OpCode::Divide, Value::Number(_), Value::Number(0)) => {
+                Err(self.error(offset, "Division by zero".to_string()))
+            }
+
+            (op, Value::Number(l), Value::Number(r)) => {
+                let result = match op {
+                    OpCode::Add => l.checked_add(r),
+                    OpCode::Subtract => l.checked_sub(r),
+                    OpCode::Multiply => l.checked_mul(r),
+                    OpCode::Divide => l.checked_div(r),
+                    OpCode::Less => return Ok(Value::Bool(l < r)),
+                    OpCode::LessEqual => return Ok(Value::Bool(l <= r)),
+                    OpCode::Greater => return Ok(Value::Bool(l > r)),
+                    OpCode::GreaterEqual => return Ok(Value::Bool(l >= r)),
+                    _ => unreachable!(),
+                };
+                result
+                    .map(Value::Number)
+                    .ok_or_else(|| self.error(offset, "Integer overflow".to_string()))
+            }
+
+            (
+                op,
+                left @ (Value::Number(_) | Value::Float(_)),
+                right @ (Value::Number(_) | Value::Float(_)),
+            ) => {
+                let (l, r) = (as_float(&left), as_float(&right));
+                Ok(match op {
+                    OpCode::Add => Value::Float(l + r),
+                    OpCode::Subtract => Value::Float(l - r),
+                    OpCode::Multiply => Value::Float(l * r),
+                    OpCode::Divide => Value::Float(l / r),
+                    OpCode::Less => Value::Bool(l < r),
+                    OpCode::LessEqual => Value::Bool(l <= r),
+                    OpCode::Greater => Value::Bool(l > r),
+                    OpCode::GreaterEqual => Value::Bool(l >= r),
+                    _ => unreachable!(),
+                })
+            }
+
+            (op, left, right) => {
+                let symbol = match op {
+                    OpCode::Add => "+",
+                    OpCode::Subtract => "-",
+                    OpCode::Multiply => "*",
+                    OpCode::Divide => "/",
+                    OpCode::Less => "<",
+                    OpCode::LessEqual => "<=",
+                    OpCode::Greater => ">",
+                    OpCode::GreaterEqual => ">=",
+                    _ => unreachable!(),
+                };
+                Err(self.error(
+                    offset,
+                    format!(
+                        "Invalid operand types for '{symbol}': {} and {}",
+                        left.type_name(),
+                        right.type_name()
+                    ),
+                ))
+            }
+        }
+    }
+
+    fn call(&mut self, argument_count: usize, offset: usize) -> Result<(), RuntimeError> {
+        let closure = match self.peek(argument_count) {
+            Value::Function(closure) => Rc::clone(closure),
+            callee => {
+                let message = format!("Can only call functions, got {}", callee.type_name());
+                return Err(self.error(offset, message));
+            }
+        };
+
+        let arity = closure.function.arity as usize;
+        if argument_count != arity {
+            return Err(self.error(
+                offset,
+                format!("Expected {arity} arguments but got {argument_count}"),
+            ));
+        }
+
+        // The script itself occupies the first frame.
+        if self.frames.len() > MAX_FRAMES {
+            return Err(self.error(offset, "Stack overflow".to_string()));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: self.stack.len() - argument_count - 1,
+        });
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let position = self.open_upvalues.partition_point(
+            |upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open < slot),
+        );
+
+        if let Some(existing) = self.open_upvalues.get(position)
+            && matches!(*existing.borrow(), Upvalue::Open(open) if open == slot)
+        {
+            return Rc::clone(existing);
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.insert(position, Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Moves every captured variable at or above `from` off the stack.
+    fn close_upvalues(&mut self, from: usize) {
+        let position = self.open_upvalues.partition_point(
+            |upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open < from),
+        );
+
+        for upvalue in self.open_upvalues.drain(position..) {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => unreachable!(),
+            };
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[slot].clone());
+        }
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn error(&self, offset: usize, message: String) -> RuntimeError {
+        let chunk = &self.frames.last().unwrap().closure.function.chunk;
+        let (line, column) = chunk.position(offset);
+        RuntimeError::new(message, line, column)
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Number(n) => *n as f64,
+        Value::Float(n) => *n,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler, lexer, parser::Parser, resolver};
+
+    fn run(input: &str) -> Result<String, RuntimeError> {
+        let tokens = lexer::scan(input).unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        resolver::resolve(&program).unwrap();
+        let script = compiler::compile(&program).unwrap();
+        let mut vm = Vm::new(Vec::new());
+        vm.run(script)?;
+        Ok(String::from_utf8(vm.into_output()).unwrap())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(run("print (1 + 2) * 3 - 4 / 2;").unwrap(), "7\n");
+        assert_eq!(run("print -(2 - 5);").unwrap(), "3\n");
+    }
+
+    #[test]
+    fn test_stack_is_balanced() {
+        let tokens = lexer::scan("let a = 1; { let b = a + 1; b; } if (a) a; for (;;) break;").unwrap();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        let mut vm = Vm::new(Vec::new());
+        vm.run(compiler::compile(&program).unwrap()).unwrap();
+        assert!(vm.stack.is_empty());
+        assert!(vm.frames.is_empty());
+    }
+
+    #[test]
+    fn test_upvalues_outlive_frames() {
+        let source = "
+            fn pair() {
+                let value = 0;
+                fn get() { return value; }
+                fn set(v) { value = v; }
+                return fn(which) { if (which) return get; return set; };
+            }
+            let p = pair();
+            p(false)(41);
+            print p(true)() + 1;
+        ";
+        assert_eq!(run(source).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn test_closures_in_loop_share_variable() {
+        let source = "
+            let f = 0;
+            for (let i = 0; i < 3; i = i + 1) {
+                let j = i;
+                if (i == 1) { f = fn() { return i * 10 + j; }; }
+            }
+            print f();
+        ";
+        assert_eq!(run(source).unwrap(), "31\n");
+    }
+
+    #[test]
+    fn test_globals_persist_between_runs() {
+        let mut vm = Vm::new(Vec::new());
+        for source in ["let a = 20;", "a = a + 1;", "print a * 2;"] {
+            let tokens = lexer::scan(source).unwrap();
+            let program = Parser::new(tokens).parse_program().unwrap();
+            vm.run(compiler::compile(&program).unwrap()).unwrap();
+        }
+        assert_eq!(String::from_utf8(vm.into_output()).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn test_error_position() {
+        let err = run("let a = 1;\nprint a +\n  \"x\";").unwrap_err();
+        assert_eq!(err.message, "Invalid operand types for '+': number and string");
+        assert_eq!((err.line, err.column), (2, 9));
+    }
+}
@@ -0,0 +1,431 @@
+//! Programs compiled by each ahead-of-time backend and run with its
+//! toolchain, which must print what the interpreter prints and fail with the
+//! same runtime error. A backend whose toolchain is missing is skipped with a
+//! note, unless `LANG_REQUIRE_TOOLCHAINS` is set.
+
+use std::{
+    env, fmt, fs,
+    path::Path,
+    process::{Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use lang::{
+    Program,
+    codegen::{CodegenError, c, llvm, wat, x86_64},
+    interpreter::Interpreter,
+    lexer,
+    parser::Parser,
+    resolver,
+};
+
+#[derive(Clone, Copy)]
+enum Backend {
+    C,
+    Llvm,
+    Wat,
+    X86_64,
+}
+
+const ALL: &[Backend] = &[Backend::C, Backend::Llvm, Backend::Wat, Backend::X86_64];
+// The backends that support strings.
+const STRINGS: &[Backend] = &[Backend::C, Backend::Llvm, Backend::Wat];
+
+// Runs the module's `main`, writing what it prints to stdout and its runtime
+// error to stderr.
+const WAT_HOST: &str = r#"
+const fs = require("fs");
+const bytes = fs.readFileSync(process.argv[2]);
+let memory;
+const text = (address, length) =>
+  new TextDecoder().decode(new Uint8Array(memory.buffer, address, length));
+class LangError extends Error {}
+const env = {
+  print_number: (n) => process.stdout.write(`${n}\n`),
+  print_string: (address, length) => process.stdout.write(text(address, length) + "\n"),
+  error: (line, column, address, length) => {
+    process.stderr.write(`${line}:${column}: ${text(address, length)}\n`);
+    throw new LangError();
+  },
+};
+WebAssembly.instantiate(bytes, { env }).then(({ instance }) => {
+  memory = instance.exports.memory;
+  try {
+    instance.exports.main();
+  } catch (error) {
+    if (!(error instanceof LangError)) throw error;
+    process.exitCode = 1;
+  }
+});
+"#;
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Backend::C => "c",
+            Backend::Llvm => "llvm",
+            Backend::Wat => "wat",
+            Backend::X86_64 => "x86-64",
+        })
+    }
+}
+
+impl Backend {
+    fn emit(self, program: &Program) -> Result<String, CodegenError> {
+        match self {
+            Backend::C => c::emit(program),
+            Backend::Llvm => llvm::emit(program),
+            Backend::Wat => wat::emit(program),
+            Backend::X86_64 => x86_64::emit(program),
+        }
+    }
+
+    // Why the output can't be built and run here, if it can't.
+    fn unavailable(self) -> Option<String> {
+        let tools: &[&str] = match self {
+            Backend::C => &["cc"],
+            Backend::Llvm => &["lli"],
+            Backend::Wat => &["wat2wasm", "node"],
+            Backend::X86_64 => {
+                if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+                    return Some("this isn't x86-64 Linux".to_string());
+                }
+                &["as", "ld"]
+            }
+        };
+        tools
+            .iter()
+            .find(|tool| Command::new(tool).arg("--version").output().is_err())
+            .map(|tool| format!("{tool} is not installed"))
+    }
+
+    // Builds `code` in `dir` and runs the result.
+    fn run(self, dir: &Path, code: &str) -> Output {
+        match self {
+            Backend::C => {
+                fs::write(dir.join(c::RUNTIME_HEADER_NAME), c::RUNTIME_HEADER).unwrap();
+                fs::write(dir.join("main.c"), code).unwrap();
+                // Locals the source never reads stay unused in C.
+                build(
+                    dir,
+                    "cc",
+                    &[
+                        "-std=c11",
+                        "-Wall",
+                        "-Werror",
+                        "-Wno-unused-variable",
+                        "-o",
+                        "main",
+                        "main.c",
+                    ],
+                );
+                Command::new(dir.join("main")).output().unwrap()
+            }
+            Backend::Llvm => {
+                fs::write(dir.join("main.ll"), code).unwrap();
+                Command::new("lli")
+                    .arg(dir.join("main.ll"))
+                    .output()
+                    .unwrap()
+            }
+            Backend::Wat => {
+                fs::write(dir.join("main.wat"), code).unwrap();
+                fs::write(dir.join("host.js"), WAT_HOST).unwrap();
+                build(dir, "wat2wasm", &["main.wat", "-o", "main.wasm"]);
+                Command::new("node")
+                    .args(["host.js", "main.wasm"])
+                    .current_dir(dir)
+                    .output()
+                    .unwrap()
+            }
+            Backend::X86_64 => {
+                fs::write(dir.join("main.s"), code).unwrap();
+                build(dir, "as", &["-o", "main.o", "main.s"]);
+                build(dir, "ld", &["-o", "main", "main.o"]);
+                Command::new(dir.join("main")).output().unwrap()
+            }
+        }
+    }
+}
+
+fn build(dir: &Path, tool: &str, args: &[&str]) {
+    let output = Command::new(tool)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{tool} failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn parse(source: &str) -> Program {
+    let tokens = lexer::scan(source).unwrap();
+    let program = Parser::new(tokens).parse_program().unwrap();
+    resolver::resolve(&program).unwrap();
+    program
+}
+
+// Compiles `source` with each of `backends` and checks the programs print
+// what the interpreter prints and fail with the same error.
+fn check(backends: &[Backend], source: &str) {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    let program = parse(source);
+    let mut interpreter = Interpreter::new(Vec::new());
+    let result = interpreter.run(&program);
+    let expected_output = String::from_utf8(interpreter.into_output()).unwrap();
+    let expected_error = match &result {
+        Ok(()) => String::new(),
+        Err(error) => format!("{error}\n"),
+    };
+
+    for &backend in backends {
+        let code = backend
+            .emit(&program)
+            .unwrap_or_else(|error| panic!("{backend} can't compile:\n{source}\n{error}"));
+        if let Some(reason) = backend.unavailable() {
+            if env::var_os("LANG_REQUIRE_TOOLCHAINS").is_some() {
+                panic!("{reason}, but LANG_REQUIRE_TOOLCHAINS is set");
+            }
+            eprintln!("skipping {backend}: {reason}");
+            continue;
+        }
+
+        let run = RUNS.fetch_add(1, Ordering::Relaxed);
+        let dir = env::temp_dir().join(format!("lang-{backend}-{}-{run}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = backend.run(&dir, &code);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected_output,
+            "{backend} output for:\n{source}"
+        );
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            expected_error,
+            "{backend} error for:\n{source}"
+        );
+        assert_eq!(
+            output.status.success(),
+            result.is_ok(),
+            "{backend} exit status for:\n{source}"
+        );
+    }
+}
+
+#[test]
+fn integer_arithmetic() {
+    check(
+        ALL,
+        "print 1 + 2 * 3; print 7 / 2; print -7 / 2; print 0; print 2147483647 + 1;
+         print -2147483647 - 2; print 65536 * 65536; print -(-2147483647 - 1) / -1;
+         print 9223372036854775807; print -9223372036854775807 - 1;
+         print 4294967296 * -2147483648; print 3037000499 * 3037000499;
+         print (-9223372036854775807 - 1) / 2; print -(-9223372036854775807) / -1;",
+    );
+}
+
+#[test]
+fn comparison_and_logic() {
+    check(
+        ALL,
+        "print 1 < 2; print 3 >= 4; print 1 == 1; print 1 != 1;
+         print true and false; print false or true; print !true;
+         print false and 1 / 0 == 1; print true or 1 / 0 == 1;",
+    );
+    check(
+        &[Backend::C, Backend::X86_64],
+        "print 1 == true; print 1 != false;",
+    );
+    check(STRINGS, "print 1 == \"1\"; print \"a\" != 2;");
+}
+
+#[test]
+fn floats() {
+    check(
+        &[Backend::C],
+        "print 3.14; print 1e-9; print .5; print 1.; print 2.5e-5; print 0.0001;
+         print 1e15; print 1e16; print 1.5e16; print 1e300 * 10; print -(1e300 * 1e300);
+         print 0.1 + 0.2; print 1.1 * 3; print 1 / 4.0; print 7 / 2.0; print 1 - 2.5;
+         print -0.0; print 1 / 0.0; print 0.0 / 0.0; print 9007199254740993 * 1.0;
+         print 1 == 1.0; print 1.5 != 1.5; print 2 < 2.5; print 2.5 >= 3; print 1.0 == \"1\";",
+    );
+}
+
+#[test]
+fn strings() {
+    check(
+        STRINGS,
+        "let s = \"foo\"; print s + \"bar\"; print s == \"foo\"; print s != \"foo\";
+         let t = s + s; print t; print \"\" + \"\" == \"\"; print \"a\" + \"b\" == \"ab\";
+         print \"caf\\u{e9}\"; print \"\\\"q\\\"\\t\\\\\\n\";",
+    );
+}
+
+#[test]
+fn interpolation() {
+    check(
+        STRINGS,
+        r#"fn show(n) { return "n=${n}"; } fn nothing() {}
+           print show(-42); print "${true}/${false} ${1 == 2} ${"s"}${0}";
+           print "${-9223372036854775807 - 1} ${9223372036854775807} [${nothing()}]";
+           print "${"${show(1)}" + "!"}";"#,
+    );
+    check(
+        &[Backend::C],
+        r#"fn add(a, b) { return a + b; } let x = 2;
+           print "${x / 4.0} ${add} ${fn() {}}";"#,
+    );
+}
+
+#[test]
+fn control_flow() {
+    check(
+        ALL,
+        "let sum = 0;
+         for (let i = 0; i < 10; i = i + 1) {
+             if (i == 3) continue;
+             if (i == 8) break;
+             let doubled = i * 2;
+             sum = sum + doubled;
+         }
+         print sum;
+         let n = 0;
+         while (true) { n = n + 1; if (n < 3) continue; if (n > 4) break; }
+         print n;
+         if (0) print 1; else print 2;
+         if (1 > 2) print 3;",
+    );
+}
+
+#[test]
+fn functions() {
+    check(
+        ALL,
+        "fn fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+         fn sign(n) { if (n < 0) { return -1; } else { return 1; } }
+         fn noop() {}
+         fn many(a, b, c, d, e, f, g, h) { return a - b + c - d + e - f + g * h; }
+         print fib(20);
+         print sign(-5); print sign(5);
+         print noop();
+         print many(1, 2, 3, 4, 5, 6, 7, 8);
+         print 1 + many(1, 2, 3, 4, 5, 6, 7, many(1, 1, 1, 1, 1, 1, 1, 1));
+         let total = 0;
+         fn bump(by) { total = total + by; }
+         bump(2); bump(3);
+         print total;",
+    );
+    check(
+        STRINGS,
+        "fn greet(name) { print \"hello \" + name; }
+         fn both(a, b) { return a and b; }
+         greet(\"world\");
+         print greet(\"again\");
+         print both(true, false);",
+    );
+    // Returning from inside a loop, which the backends can't prove always
+    // happens.
+    check(
+        ALL,
+        "fn first(n) { while (true) { return n; } }
+         fn search(n) { while (n > 0) { if (n == 3) return n; n = n - 1; } }
+         print first(1); print search(7);",
+    );
+}
+
+#[test]
+fn function_values_and_closures() {
+    check(
+        &[Backend::C],
+        "fn add(a, b) { return a + b; }
+         print add; print fn() {}; print add == add; print fn() {} == fn() {};
+         fn make_counter() {
+             let count = 0;
+             return fn() { count = count + 1; return count; };
+         }
+         let a = make_counter();
+         let b = make_counter();
+         print a(); print a(); print b();
+         fn outer() {
+             let v = 1;
+             fn middle() {
+                 fn inner() { v = v + 1; return v; }
+                 return inner;
+             }
+             let f = middle();
+             f();
+             return f() + v;
+         }
+         print outer();
+         let fs = 0;
+         for (let i = 0; i < 3; i = i + 1) {
+             let j = i;
+             if (i == 1) fs = fn() { return i * 10 + j; };
+         }
+         print fs();",
+    );
+}
+
+#[test]
+fn arithmetic_errors() {
+    for source in [
+        "print 1;\nprint 4 / (2 - 2);",
+        "print 1;\nprint 9223372036854775807 + 1;",
+        "print -9223372036854775807 - 2;",
+        "print 4294967296 * 2147483648;",
+        "print (-9223372036854775807 - 1) / -1;",
+        "let m = -9223372036854775807 - 1;\nprint m / -1;",
+        "print -(-9223372036854775807 - 1);",
+        "let m = -9223372036854775807 - 1;\nprint -m;",
+    ] {
+        check(ALL, source);
+    }
+}
+
+#[test]
+fn stack_overflow() {
+    check(
+        ALL,
+        "fn f(n) { if (n < 0) return 0; return f(n + 1); }\nf(0);",
+    );
+    check(
+        &[Backend::C, Backend::X86_64],
+        "fn f(n) { print n; return f(n + 1); }\nf(0);",
+    );
+}
+
+#[test]
+fn undefined_variables() {
+    check(
+        &[Backend::C, Backend::X86_64],
+        "fn f() { return x; }\nprint f();\nlet x = 1;",
+    );
+    check(
+        &[Backend::C, Backend::X86_64],
+        "fn f() { x = 1; }\nf();\nlet x = 1;",
+    );
+    check(&[Backend::C], "print x;");
+    check(&[Backend::C], "x = 1;");
+}
+
+#[test]
+fn type_errors() {
+    for source in [
+        "print 1;\nprint 1 + \"a\";",
+        "print \"a\" < \"b\";",
+        "print -true;",
+        "print 1.5 * \"a\";",
+        "print !1;",
+        "print true and 1;",
+        "let x = 1;\nx();",
+        "fn f(a) {}\nf(1, 2);",
+    ] {
+        check(&[Backend::C], source);
+    }
+}
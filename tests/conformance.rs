@@ -0,0 +1,424 @@
+//! Programs run through both the tree-walking interpreter and the bytecode
+//! VM, which must agree on output and on any runtime error.
+
+use lang::{
+    Program, RuntimeError, compiler, interpreter::Interpreter, lexer, parser::Parser, resolver,
+    vm::Vm,
+};
+
+struct Outcome {
+    output: String,
+    // (message, line, column)
+    error: Option<(String, u32, u32)>,
+}
+
+fn parse(source: &str) -> Program {
+    let tokens = lexer::scan(source).unwrap();
+    let program = Parser::new(tokens).parse_program().unwrap();
+    resolver::resolve(&program).unwrap();
+    program
+}
+
+fn outcome(output: Vec<u8>, result: Result<(), RuntimeError>) -> Outcome {
+    Outcome {
+        output: String::from_utf8(output).unwrap(),
+        error: result
+            .err()
+            .map(|error| (error.message, error.line, error.column)),
+    }
+}
+
+fn interpret(source: &str) -> Outcome {
+    let program = parse(source);
+    let mut interpreter = Interpreter::new(Vec::new());
+    let result = interpreter.run(&program);
+    outcome(interpreter.into_output(), result)
+}
+
+fn run_vm(source: &str) -> Outcome {
+    let program = parse(source);
+    let script = compiler::compile(&program).unwrap();
+    let mut vm = Vm::new(Vec::new());
+    let result = vm.run(script);
+    outcome(vm.into_output(), result)
+}
+
+fn check(source: &str, expected_output: &str, expected_error: Option<(&str, u32, u32)>) {
+    let expected_error =
+        expected_error.map(|(message, line, column)| (message.to_string(), line, column));
+
+    for (engine, outcome) in [("interpreter", interpret(source)), ("vm", run_vm(source))] {
+        assert_eq!(
+            outcome.output, expected_output,
+            "{engine} output for:\n{source}"
+        );
+        assert_eq!(
+            outcome.error, expected_error,
+            "{engine} error for:\n{source}"
+        );
+    }
+}
+
+fn check_output(source: &str, expected_output: &str) {
+    check(source, expected_output, None);
+}
+
+#[test]
+fn arithmetic_and_comparison() {
+    check_output(
+        "print 1 + 2 * 3; print (1 + 2) * 3; print 7 / 2; print -7 / 2; print 10 - 4 - 3;
+         print 1 < 2; print 2 <= 1; print 3 > 3; print 3 >= 3;",
+        "7\n9\n3\n-3\n3\ntrue\nfalse\nfalse\ntrue\n",
+    );
+}
+
+#[test]
+fn integer_overflow() {
+    check_output(
+        "print 2147483647 + 1; print -2147483647 - 2; print 65536 * 65536;
+         print 9223372036854775807; print -9223372036854775807 - 1;",
+        "2147483648\n-2147483649\n4294967296\n9223372036854775807\n-9223372036854775808\n",
+    );
+    check(
+        "print 1;\nprint 9223372036854775807 + 1;",
+        "1\n",
+        Some(("Integer overflow", 2, 27)),
+    );
+    check(
+        "let min = -9223372036854775807 - 1;\nprint min * -1;",
+        "",
+        Some(("Integer overflow", 2, 11)),
+    );
+    check(
+        "let min = -9223372036854775807 - 1;\nprint -min;",
+        "",
+        Some(("Integer overflow", 2, 7)),
+    );
+    check(
+        "let min = -9223372036854775807 - 1;\nprint min / -1;",
+        "",
+        Some(("Integer overflow", 2, 11)),
+    );
+}
+
+#[test]
+fn numeric_literals() {
+    check_output(
+        "print 0xFF; print 0o755; print 0b1010; print 1_000_000 + 0xdead_beef; print 1_0.5;",
+        "255\n493\n10\n3736928559\n10.5\n",
+    );
+}
+
+#[test]
+fn floats() {
+    check_output(
+        "print 3.14; print .5 + 1; print 7 / 2.0; print 0.1 + 0.2; print 1e-9 * 2; print 1e16;
+         print -2.5 * 2; print 1 / 0.0; print 1 == 1.0; print 0.5 < 1; print 2.0 + 1 == 3;",
+        "3.14\n1.5\n3.5\n0.30000000000000004\n2e-9\n1e16\n-5.0\ninf\ntrue\ntrue\ntrue\n",
+    );
+    check(
+        "let x = 1.5;\nprint x + true;",
+        "",
+        Some(("Invalid operand types for '+': float and bool", 2, 9)),
+    );
+}
+
+#[test]
+fn equality() {
+    check_output(
+        "print 1 == 1; print 1 != 1; print \"a\" == \"a\"; print 1 == \"1\"; print true == true;
+         fn f() {} let g = f; print f == g; print fn() {} == fn() {};",
+        "true\nfalse\ntrue\nfalse\ntrue\ntrue\nfalse\n",
+    );
+}
+
+#[test]
+fn strings() {
+    check_output(
+        "let s = \"foo\"; print s + \"bar\"; print s;",
+        "foobar\nfoo\n",
+    );
+    check_output(
+        r#"print "a\tb\n\"c\" \\ \u{e9}\u{1F600}"; print "\"" == "\u{22}";"#,
+        "a\tb\n\"c\" \\ \u{e9}\u{1F600}\ntrue\n",
+    );
+}
+
+#[test]
+fn string_interpolation() {
+    check_output(
+        r#"let a = 2; let b = 3;
+           fn nothing() {}
+           fn add(x, y) { x + y }
+           print "total: ${a + b}!";
+           print "${a} ${2.5} ${true} ${nothing()} ${add} ${"s"}";
+           print "${add(a, fn() { return 1; }())} and ${"in ${"${a}"}ner"}";
+           print "\${a} costs $${a}";"#,
+        "total: 5!\n2 2.5 true nil <fn add> s\n3 and in 2ner\n${a} costs $2\n",
+    );
+    check_output(
+        "let log = \"\"; fn f(x) { log = log + x; return x; }
+         print \"${f(\"a\")}${f(\"b\")}\" + log;",
+        "abab\n",
+    );
+    check(
+        "let x = 1;\nprint \"x = ${x + \"1\"}\";",
+        "",
+        Some(("Invalid operand types for '+': number and string", 2, 16)),
+    );
+
+    // More parts than fit in one VM instruction.
+    let source: String = (0..300).map(|i| format!("${{{i}}},")).collect();
+    let expected: String = (0..300).map(|i| format!("{i},")).collect();
+    check_output(&format!("print \"{source}\";"), &format!("{expected}\n"));
+}
+
+#[test]
+fn raw_and_triple_quoted_strings() {
+    check_output(
+        r####"print r"C:\dir\${x}"; print r#"say "hi""#;
+              let items = 2;
+              print """
+                  Order:
+                    ${items} items
+                  Done.
+                  """;"####,
+        "C:\\dir\\${x}\nsay \"hi\"\nOrder:\n  2 items\nDone.\n",
+    );
+    check(
+        "let items = 2;\nprint \"\"\"\n    Order:\n      ${items + \"x\"} items\n    \"\"\";",
+        "",
+        Some(("Invalid operand types for '+': number and string", 4, 15)),
+    );
+}
+
+#[test]
+fn logical_operators() {
+    check_output(
+        "print true and false; print false or true; print !true; print 1 < 2 and 2 < 3;
+         print false and 1 / 0; print true or 1 / 0;",
+        "false\ntrue\nfalse\ntrue\nfalse\ntrue\n",
+    );
+    check(
+        "print 1;\nprint true and 1;",
+        "1\n",
+        Some(("Operands of 'and' must be bools, got number", 2, 12)),
+    );
+    check(
+        "print 1 or true;",
+        "",
+        Some(("Operands of 'or' must be bools, got number", 1, 9)),
+    );
+    check(
+        "print !nil_value;",
+        "",
+        Some(("Undefined variable 'nil_value'", 1, 8)),
+    );
+}
+
+#[test]
+fn runtime_type_errors() {
+    check(
+        "print 1 + \"a\";",
+        "",
+        Some(("Invalid operand types for '+': number and string", 1, 9)),
+    );
+    check(
+        "print \"a\" < \"b\";",
+        "",
+        Some(("Invalid operand types for '<': string and string", 1, 11)),
+    );
+    check(
+        "print -true;",
+        "",
+        Some(("Operand of '-' must be a number, got bool", 1, 7)),
+    );
+    check(
+        "print !1;",
+        "",
+        Some(("Operand of '!' must be a bool, got number", 1, 7)),
+    );
+    check(
+        "print 1;\n  print 4 / (2 - 2);",
+        "1\n",
+        Some(("Division by zero", 2, 11)),
+    );
+}
+
+#[test]
+fn globals_and_scopes() {
+    check_output(
+        "let a = \"global\";
+         {
+             let a = \"outer\";
+             {
+                 let a = \"inner\";
+                 print a;
+             }
+             print a;
+         }
+         print a;
+         let b = 1;
+         b = b + 1;
+         let c = b = 5;
+         print b + c;",
+        "inner\nouter\nglobal\n10\n",
+    );
+    check(
+        "{ let x = 1; }\nprint x;",
+        "",
+        Some(("Undefined variable 'x'", 2, 7)),
+    );
+    check(
+        "let a = 1;\n  b = a;",
+        "",
+        Some(("Cannot assign to undeclared variable 'b'", 2, 3)),
+    );
+}
+
+#[test]
+fn control_flow() {
+    check_output(
+        "if (1 < 2) print \"then\"; else print \"else\";
+         fn zero() { return 0; }
+         fn nothing() {}
+         if (zero()) print \"zero is truthy\";
+         if (nothing()) print \"unreachable\"; else print \"nil is falsy\";",
+        "then\nzero is truthy\nnil is falsy\n",
+    );
+    check_output(
+        "if (false) print 1; else if (\"\") print 2; else print 3;
+         let i = 0;
+         let sum = 0;
+         while (i < 10) {
+             i = i + 1;
+             if (i == 3) continue;
+             if (i == 8) break;
+             let doubled = i * 2;
+             sum = sum + doubled;
+         }
+         print sum;
+         for (let j = 0; j < 6; j = j + 1) {
+             let skip = j == 1;
+             if (skip) continue;
+             if (j == 4) break;
+             print j;
+         }
+         for (;;) { let k = 1; { let l = 2; break; } }
+         print \"done\";",
+        "2\n50\n0\n2\n3\ndone\n",
+    );
+}
+
+#[test]
+fn functions_and_recursion() {
+    check_output(
+        "fn add(a, b) { return a + b; }
+         fn noop() {}
+         print add(1, 2);
+         print noop();
+         print add;
+         print fn() {};
+         fn fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+         print fib(20);",
+        "3\nnil\n<fn add>\n<fn>\n6765\n",
+    );
+    check(
+        "fn f(a) {}\nf(1,\n  2);",
+        "",
+        Some(("Expected 1 arguments but got 2", 2, 2)),
+    );
+    check(
+        "let x = 1;\n  x();",
+        "",
+        Some(("Can only call functions, got number", 2, 4)),
+    );
+    check(
+        "fn f(n) { print n; return f(n + 1); }\nf(0);",
+        &(0..200).map(|n| format!("{n}\n")).collect::<String>(),
+        Some(("Stack overflow", 1, 28)),
+    );
+    check_output(
+        "let id = fn(x) { x };
+         fn add(a, b) { print a; a + b }
+         print id(\"a\");
+         print add(1, 2);",
+        "a\n1\n3\n",
+    );
+    check_output(
+        "let x: int = 1;
+         fn add(a: int, b: fn(int) -> int) -> int { return a + b(a); }
+         let s: whatever = \"not checked\";
+         print add(x, fn(n: int) -> int { n * 2 });
+         print s;",
+        "3\nnot checked\n",
+    );
+}
+
+#[test]
+fn closures() {
+    check_output(
+        "fn make_counter() {
+             let count = 0;
+             return fn() { count = count + 1; return count; };
+         }
+         let a = make_counter();
+         let b = make_counter();
+         print a(); print a(); print b();
+
+         let x = \"global\";
+         {
+             fn show() { print x; }
+             show();
+             let x = \"block\";
+             show();
+         }
+
+         fn outer() {
+             let v = 1;
+             fn middle() {
+                 fn inner() { v = v + 1; return v; }
+                 return inner;
+             }
+             let f = middle();
+             f();
+             return f() + v;
+         }
+         print outer();
+
+         let fs = 0;
+         for (let i = 0; i < 3; i = i + 1) {
+             let j = i;
+             if (i == 1) fs = fn() { return i * 10 + j; };
+         }
+         print fs();",
+        "1\n2\n1\nglobal\nglobal\n6\n31\n",
+    );
+}
+
+#[test]
+fn closures_capture_loop_body_locals_per_iteration() {
+    check_output(
+        "let first = 0;
+         let second = 0;
+         let i = 0;
+         while (i < 2) {
+             let captured = i;
+             if (i == 0) first = fn() { return captured; };
+             else second = fn() { return captured; };
+             i = i + 1;
+         }
+         print first();
+         print second();",
+        "0\n1\n",
+    );
+}
+
+#[test]
+fn output_before_error_is_kept() {
+    check(
+        "print \"before\";\nfn f() { return 1 / 0; }\nprint f();",
+        "before\n",
+        Some(("Division by zero", 2, 19)),
+    );
+}
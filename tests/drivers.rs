@@ -0,0 +1,163 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+fn run_file(name: &str, source: &str) -> Output {
+    run_file_with_args(name, source, &[])
+}
+
+fn run_file_with_args(name: &str, source: &str, args: &[&str]) -> Output {
+    let path: PathBuf = env::temp_dir().join(format!("lang-{}-{}.lg", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+fn run_repl(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_repl"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_file_runs_program() {
+    let output = run_file("program", "print (1 + 2) * 3;\n1;\nprint \"done\";\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "9\ndone\n");
+}
+
+#[test]
+fn test_file_rejects_trailing_tokens() {
+    let output = run_file("trailing", "print 1 + 2\n3;\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Expected ';' after value"));
+    assert!(stderr.contains("line: 2"));
+}
+
+#[test]
+fn test_repl_rejects_trailing_tokens() {
+    let output = run_repl("1 2 3\n(1) )\n1 + 1\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stderr.contains("Parse error: 1:3: Unexpected token after expression"));
+    assert!(stderr.contains("Parse error: 1:5: Unexpected token after expression"));
+    assert!(stdout.contains("> 2\n"));
+}
+
+#[test]
+fn test_repl_runs_statements() {
+    let output = run_repl("print 1 + 2; print \"x\";\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("> 3\nx\n"));
+}
+
+#[test]
+fn test_repl_keeps_variables_between_lines() {
+    let output = run_repl("let x = 40;\nx = x + 2;\nx\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("> 42\n"));
+}
+
+#[test]
+fn test_file_reports_resolver_diagnostics() {
+    let output = run_file("unused", "{ let unused = 1; }\nprint 1;\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("Warning: 1:7: Unused local variable 'unused'")
+    );
+
+    let output = run_file("own-initializer", "{ let a = a; }\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("own initializer"));
+}
+
+#[test]
+fn test_file_runs_on_vm() {
+    let source = "fn square(x) { return x * x; }\nprint square(7);\nprint 1 / 0;\n";
+    let output = run_file_with_args("vm", source, &["--vm"]);
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "49\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Division by zero"));
+}
+
+#[test]
+fn test_file_dumps_bytecode() {
+    let output = run_file_with_args("dump", "print 1 + 2;\n", &["--dump-bytecode"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("== <script> ==\n"));
+    assert!(stdout.contains("Constant            0 1"));
+    assert!(stdout.contains("1:9 Add"));
+    assert!(!stdout.contains("\n3\n"));
+}
+
+#[test]
+fn test_file_compiles_to_bytecode_file() {
+    let compiled = env::temp_dir().join(format!("lang-compiled-{}.lgc", std::process::id()));
+    let compiled_arg = compiled.to_str().unwrap();
+    let output = run_file_with_args(
+        "compile",
+        "fn greet(name) { return \"hi \" + name; }\nprint greet(\"there\");\n",
+        &["--compile", compiled_arg],
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let run = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg(&compiled)
+        .output()
+        .unwrap();
+    assert!(run.status.success());
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "hi there\n");
+
+    let mut bytes = fs::read(&compiled).unwrap();
+    bytes[4] = 0xff;
+    fs::write(&compiled, bytes).unwrap();
+    let run = Command::new(env!("CARGO_BIN_EXE_lang"))
+        .arg(&compiled)
+        .output()
+        .unwrap();
+    fs::remove_file(&compiled).unwrap();
+    assert!(!run.status.success());
+    assert!(String::from_utf8_lossy(&run.stderr).contains("Unsupported version"));
+}
+
+#[test]
+fn test_file_type_checks_before_running() {
+    let source = "print \"start\";\nprint \"a\" + 1;\n";
+    let output = run_file_with_args("typecheck", source, &["--typecheck"]);
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid operand types for '+': string and int"));
+
+    // Without the flag the program runs until the error.
+    let output = run_file("untyped", source);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "start\n");
+}